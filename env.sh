@@ -1,6 +1,27 @@
 #!/bin/sh
 
+# Settings may also live in a TOML file, see ebrz.example.toml
+# EBRZ_CONFIG=ebrz.toml
 EBRZ_TG_ENV=test
 # EBRZ_TG_ENV=prod
 EBRZ_TG_API_KEY=
-EBRZ_DB_PATH=rbrz.db
+EBRZ_DB_PATH=ebrz.db
+# EBRZ_SHUTDOWN_TIMEOUT=10
+# EBRZ_TG_API_URL=https://api.telegram.org
+# EBRZ_TG_LOCAL_MODE=false
+# EBRZ_PLAYGROUND_BACKEND=remote
+# EBRZ_PLAYGROUND_URL=https://play.rust-lang.org
+# EBRZ_PLAYGROUND_TIMEOUT=60
+# EBRZ_WEBHOOK_URL=https://example.com/ebrz
+# EBRZ_WEBHOOK_SECRET=
+# EBRZ_WEBHOOK_LISTEN=127.0.0.1:8080
+# EBRZ_WEBHOOK_LISTEN_PATH=/ebrz
+# EBRZ_TOOLCHAIN_CHANNEL=stable
+# EBRZ_TOOLCHAIN_EDITION=2021
+# EBRZ_TOOLCHAIN_PROFILE=debug
+# EBRZ_LOG_FORMAT=text
+# EBRZ_LOG_LEVEL=info
+# EBRZ_CHATS_DEFAULT=allow
+# Comma separated chat ids
+# EBRZ_CHATS_ALLOW=
+# EBRZ_CHATS_DENY=
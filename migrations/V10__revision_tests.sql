@@ -0,0 +1 @@
+ALTER TABLE `playground_revision` ADD COLUMN `tests` INTEGER NOT NULL DEFAULT 0;
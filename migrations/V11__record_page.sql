@@ -0,0 +1 @@
+ALTER TABLE `playground_record` ADD COLUMN `page` INTEGER NOT NULL DEFAULT 0;
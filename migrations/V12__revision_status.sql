@@ -0,0 +1,4 @@
+-- Revisions from before this migration are treated as finished
+ALTER TABLE `playground_revision` ADD COLUMN `status` INTEGER NOT NULL DEFAULT 1;
+
+CREATE INDEX playground_revision_pending ON `playground_revision` (`status`) WHERE `status` = 0;
@@ -0,0 +1,9 @@
+CREATE TABLE `bot_update_offset` (
+    `id` INTEGER NOT NULL PRIMARY KEY CHECK (`id` = 0),
+    `update_offset` INTEGER NOT NULL
+);
+
+CREATE TABLE `bot_handled_update` (
+    `update_id` INTEGER NOT NULL PRIMARY KEY,
+    `handled_at` TEXT NOT NULL DEFAULT (strftime('%Y-%m-%d %H:%M:%f', 'now'))
+);
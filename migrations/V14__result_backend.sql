@@ -0,0 +1 @@
+ALTER TABLE `playground_revision` ADD COLUMN `result_backend` TEXT NOT NULL DEFAULT '';
@@ -0,0 +1,4 @@
+ALTER TABLE `playground_revision` ADD COLUMN `miri_success` INTEGER NULL;
+ALTER TABLE `playground_revision` ADD COLUMN `miri_exit_detail` TEXT NOT NULL DEFAULT '';
+ALTER TABLE `playground_revision` ADD COLUMN `miri_stdout` TEXT NOT NULL DEFAULT '';
+ALTER TABLE `playground_revision` ADD COLUMN `miri_stderr` TEXT NOT NULL DEFAULT '';
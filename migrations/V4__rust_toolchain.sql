@@ -0,0 +1,3 @@
+ALTER TABLE `playground_revision` ADD COLUMN `rust_edition` INTEGER NOT NULL DEFAULT 2021;
+ALTER TABLE `playground_revision` ADD COLUMN `rust_channel` INTEGER NOT NULL DEFAULT 0;
+ALTER TABLE `playground_revision` ADD COLUMN `rust_profile` INTEGER NOT NULL DEFAULT 0;
@@ -0,0 +1 @@
+ALTER TABLE `playground_revision` ADD COLUMN `user_code` TEXT NOT NULL DEFAULT '';
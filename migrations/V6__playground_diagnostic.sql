@@ -0,0 +1,11 @@
+CREATE TABLE `playground_diagnostic` (
+    `id` INTEGER NOT NULL PRIMARY KEY,
+    `revision_id` INTEGER NOT NULL REFERENCES `playground_revision`(`id`) ON DELETE CASCADE ON UPDATE CASCADE DEFERRABLE INITIALLY DEFERRED,
+    `level` INTEGER NOT NULL,
+    `code` TEXT NULL,
+    `message` TEXT NOT NULL,
+    `spans` TEXT NOT NULL DEFAULT '[]',
+    `notes` TEXT NOT NULL DEFAULT '[]'
+);
+
+CREATE INDEX playground_diagnostic_revision_id ON `playground_diagnostic` (`revision_id`);
@@ -0,0 +1,4 @@
+ALTER TABLE `playground_revision` ADD COLUMN `clippy_success` INTEGER NULL;
+ALTER TABLE `playground_revision` ADD COLUMN `clippy_stderr` TEXT NOT NULL DEFAULT '';
+ALTER TABLE `playground_revision` ADD COLUMN `clippy_warning_count` INTEGER NOT NULL DEFAULT 0;
+ALTER TABLE `playground_revision` ADD COLUMN `clippy_error_count` INTEGER NOT NULL DEFAULT 0;
@@ -0,0 +1,2 @@
+ALTER TABLE `playground_revision` ADD COLUMN `format_success` INTEGER NULL;
+ALTER TABLE `playground_revision` ADD COLUMN `code_formatted` TEXT NOT NULL DEFAULT '';
@@ -0,0 +1,10 @@
+CREATE TABLE `playground_artifact` (
+    `id` INTEGER NOT NULL PRIMARY KEY,
+    `revision_id` INTEGER NOT NULL REFERENCES `playground_revision`(`id`) ON DELETE CASCADE ON UPDATE CASCADE DEFERRABLE INITIALLY DEFERRED,
+    `kind` INTEGER NOT NULL,
+    `success` INTEGER NOT NULL,
+    `code` TEXT NOT NULL DEFAULT '',
+    `stderr` TEXT NOT NULL DEFAULT ''
+);
+
+CREATE UNIQUE INDEX playground_artifact_revision_id_kind ON `playground_artifact` (`revision_id`, `kind`);
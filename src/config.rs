@@ -0,0 +1,857 @@
+use std::{collections::HashMap, env, fs, io, net::SocketAddr, path::PathBuf, time::Duration};
+
+use serde::Deserialize;
+use thiserror::Error;
+use tracing::level_filters::LevelFilter;
+
+use crate::{
+    handler::{TgEnv, WebhookConfig},
+    repository::playground_record::{
+        PlaygroundRustChannel, PlaygroundRustEdition, PlaygroundRustProfile,
+        PlaygroundRustToolchain,
+    },
+    service::{
+        failover::CircuitBreakerConfig,
+        local::{SandboxConfig, SandboxLimits},
+    },
+};
+
+const DEFAULT_CONFIG_PATH: &str = "ebrz.toml";
+const CLOUD_API_URL: &str = "https://api.telegram.org";
+
+#[derive(Debug, Error)]
+pub enum ConfigError {
+    #[error("cannot read config file {path}: {source}")]
+    Read { path: PathBuf, source: io::Error },
+    #[error("invalid config file: {0}")]
+    Parse(#[from] toml::de::Error),
+    #[error("missing config `{0}`")]
+    Missing(&'static str),
+    #[error("invalid config `{key}`: {reason}")]
+    Invalid { key: &'static str, reason: String },
+}
+
+pub type ConfigResult<T> = Result<T, ConfigError>;
+
+#[derive(Debug, Clone)]
+pub struct Config {
+    pub db_path: PathBuf,
+    pub shutdown_timeout: Duration,
+    pub tg_api_key: String,
+    pub tg_api_url: String,
+    pub tg_env: TgEnv,
+    /// Whether `tg_api_url` is a self-hosted Bot API server running with `--local`.
+    pub tg_local_mode: bool,
+    /// Long polling is used unless a webhook is configured.
+    pub webhook: Option<WebhookConfig>,
+    /// Tried in this order, a single one unless failover is configured.
+    pub playground_backends: Vec<PlaygroundBackendConfig>,
+    pub playground_timeout: Duration,
+    pub circuit_breaker: CircuitBreakerConfig,
+    /// Settings of the local backend, set when it is used.
+    pub sandbox: Option<SandboxConfig>,
+    pub default_toolchain: PlaygroundRustToolchain,
+    pub log_format: LogFormat,
+    /// Default level, `RUST_LOG` still takes precedence.
+    pub log_level: LevelFilter,
+    pub chat_policy: ChatPolicy,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LogFormat {
+    Text,
+    Json,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PlaygroundBackendConfig {
+    /// Shown next to results when there is more than one backend.
+    pub name: String,
+    /// The local sandbox is used if not set.
+    pub url: Option<String>,
+}
+
+/// Which chats the bot answers eval commands in.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ChatPolicy {
+    pub allow_by_default: bool,
+    pub allow: Vec<i64>,
+    pub deny: Vec<i64>,
+}
+
+impl ChatPolicy {
+    pub fn allows(&self, chat_id: i64) -> bool {
+        if self.deny.contains(&chat_id) {
+            false
+        } else {
+            self.allow_by_default || self.allow.contains(&chat_id)
+        }
+    }
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct RawConfig {
+    db_path: Option<String>,
+    shutdown_timeout: Option<u64>,
+    telegram: RawTelegramConfig,
+    webhook: RawWebhookConfig,
+    playground: RawPlaygroundConfig,
+    sandbox: RawSandboxConfig,
+    toolchain: RawToolchainConfig,
+    log: RawLogConfig,
+    chats: RawChatsConfig,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct RawTelegramConfig {
+    env: Option<String>,
+    api_key: Option<String>,
+    api_url: Option<String>,
+    local_mode: Option<bool>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct RawWebhookConfig {
+    url: Option<String>,
+    secret: Option<String>,
+    listen: Option<String>,
+    listen_path: Option<String>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct RawPlaygroundConfig {
+    backend: Option<String>,
+    url: Option<String>,
+    timeout: Option<u64>,
+    circuit_failures: Option<u32>,
+    circuit_cooldown: Option<u64>,
+    failover: Vec<RawFailoverBackend>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct RawFailoverBackend {
+    name: Option<String>,
+    url: Option<String>,
+    local: bool,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct RawSandboxConfig {
+    bwrap: Option<String>,
+    work_dir: Option<String>,
+    ro_binds: Option<Vec<String>>,
+    memory_limit: Option<u64>,
+    cpu_limit: Option<u64>,
+    output_limit: Option<u64>,
+    process_limit: Option<u64>,
+    max_jobs: Option<usize>,
+    toolchains: RawSandboxToolchains,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct RawSandboxToolchains {
+    stable: Option<String>,
+    beta: Option<String>,
+    nightly: Option<String>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct RawToolchainConfig {
+    channel: Option<String>,
+    edition: Option<String>,
+    profile: Option<String>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct RawLogConfig {
+    format: Option<String>,
+    level: Option<String>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct RawChatsConfig {
+    default: Option<String>,
+    allow: Vec<i64>,
+    deny: Vec<i64>,
+}
+
+impl Config {
+    /// Loads the file named by `EBRZ_CONFIG` (or `ebrz.toml` if present), then applies
+    /// `EBRZ_*` environment overrides.
+    pub fn load() -> ConfigResult<Self> {
+        let (path, required) = match env::var_os("EBRZ_CONFIG") {
+            Some(path) => (PathBuf::from(path), true),
+            None => (PathBuf::from(DEFAULT_CONFIG_PATH), false),
+        };
+        let file = match fs::read_to_string(&path) {
+            Ok(file) => Some(file),
+            Err(e) if e.kind() == io::ErrorKind::NotFound && !required => None,
+            Err(source) => return Err(ConfigError::Read { path, source }),
+        };
+        Self::from_sources(file.as_deref(), |key| env::var(key).ok())
+    }
+
+    fn from_sources(
+        file: Option<&str>,
+        env: impl Fn(&str) -> Option<String>,
+    ) -> ConfigResult<Self> {
+        let mut raw: RawConfig = match file {
+            Some(file) => toml::from_str(file)?,
+            None => RawConfig::default(),
+        };
+        let overrides = [
+            ("EBRZ_DB_PATH", &mut raw.db_path),
+            ("EBRZ_TG_ENV", &mut raw.telegram.env),
+            ("EBRZ_TG_API_KEY", &mut raw.telegram.api_key),
+            ("EBRZ_TG_API_URL", &mut raw.telegram.api_url),
+            ("EBRZ_WEBHOOK_URL", &mut raw.webhook.url),
+            ("EBRZ_WEBHOOK_SECRET", &mut raw.webhook.secret),
+            ("EBRZ_WEBHOOK_LISTEN", &mut raw.webhook.listen),
+            ("EBRZ_WEBHOOK_LISTEN_PATH", &mut raw.webhook.listen_path),
+            ("EBRZ_PLAYGROUND_BACKEND", &mut raw.playground.backend),
+            ("EBRZ_PLAYGROUND_URL", &mut raw.playground.url),
+            ("EBRZ_TOOLCHAIN_CHANNEL", &mut raw.toolchain.channel),
+            ("EBRZ_TOOLCHAIN_EDITION", &mut raw.toolchain.edition),
+            ("EBRZ_TOOLCHAIN_PROFILE", &mut raw.toolchain.profile),
+            ("EBRZ_LOG_FORMAT", &mut raw.log.format),
+            ("EBRZ_LOG_LEVEL", &mut raw.log.level),
+            ("EBRZ_CHATS_DEFAULT", &mut raw.chats.default),
+        ];
+        for (key, value) in overrides {
+            if let Some(v) = env(key) {
+                *value = Some(v);
+            }
+        }
+        if let Some(local_mode) = env("EBRZ_TG_LOCAL_MODE") {
+            raw.telegram.local_mode = Some(parse_value("EBRZ_TG_LOCAL_MODE", &local_mode)?);
+        }
+        if let Some(timeout) = env("EBRZ_PLAYGROUND_TIMEOUT") {
+            raw.playground.timeout = Some(parse_value("EBRZ_PLAYGROUND_TIMEOUT", &timeout)?);
+        }
+        if let Some(timeout) = env("EBRZ_SHUTDOWN_TIMEOUT") {
+            raw.shutdown_timeout = Some(parse_value("EBRZ_SHUTDOWN_TIMEOUT", &timeout)?);
+        }
+        if let Some(allow) = env("EBRZ_CHATS_ALLOW") {
+            raw.chats.allow = parse_list("EBRZ_CHATS_ALLOW", &allow)?;
+        }
+        if let Some(deny) = env("EBRZ_CHATS_DENY") {
+            raw.chats.deny = parse_list("EBRZ_CHATS_DENY", &deny)?;
+        }
+        raw.validate()
+    }
+}
+
+impl RawConfig {
+    fn validate(self) -> ConfigResult<Config> {
+        let tg_api_key = self
+            .telegram
+            .api_key
+            .filter(|key| !key.is_empty())
+            .ok_or(ConfigError::Missing("telegram.api_key"))?;
+        let tg_env = match self.telegram.env.as_deref() {
+            Some("test") => TgEnv::Test,
+            Some("prod") => TgEnv::Prod,
+            Some(env) => return Err(invalid("telegram.env", format!("unknown env `{env}`"))),
+            None => return Err(ConfigError::Missing("telegram.env")),
+        };
+        let tg_api_url = parse_base_url(
+            "telegram.api_url",
+            self.telegram
+                .api_url
+                .unwrap_or_else(|| CLOUD_API_URL.into()),
+        )?;
+        let tg_local_mode = self.telegram.local_mode.unwrap_or(false);
+        if tg_local_mode && tg_api_url == CLOUD_API_URL {
+            return Err(invalid(
+                "telegram.local_mode",
+                "requires a self-hosted `telegram.api_url`".into(),
+            ));
+        }
+        let webhook = match (self.webhook.url, self.webhook.secret) {
+            (Some(url), Some(secret_token)) => {
+                if !secret_token
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+                    || !(1..=256).contains(&secret_token.len())
+                {
+                    return Err(invalid(
+                        "webhook.secret",
+                        "must be 1-256 characters of A-Z, a-z, 0-9, _ and -".into(),
+                    ));
+                }
+                let listen_addr = parse_value::<SocketAddr>(
+                    "webhook.listen",
+                    self.webhook.listen.as_deref().unwrap_or("127.0.0.1:8080"),
+                )?;
+                if let Some(path) = self.webhook.listen_path.as_deref() {
+                    if !path.starts_with('/') {
+                        return Err(invalid(
+                            "webhook.listen_path",
+                            format!("`{path}` does not start with /"),
+                        ));
+                    }
+                }
+                Some(WebhookConfig {
+                    url: check_url("webhook.url", url)?,
+                    listen_addr,
+                    listen_path: self.webhook.listen_path,
+                    secret_token,
+                })
+            }
+            (Some(_), None) => return Err(ConfigError::Missing("webhook.secret")),
+            (None, _) => None,
+        };
+        let default_toolchain = PlaygroundRustToolchain {
+            channel: match self.toolchain.channel.as_deref() {
+                None | Some("stable") => PlaygroundRustChannel::Stable,
+                Some("beta") => PlaygroundRustChannel::Beta,
+                Some("nightly") => PlaygroundRustChannel::Nightly,
+                Some(c) => return Err(invalid("toolchain.channel", format!("unknown `{c}`"))),
+            },
+            edition: match self.toolchain.edition.as_deref() {
+                Some("2015") => PlaygroundRustEdition::Rust2015,
+                Some("2018") => PlaygroundRustEdition::Rust2018,
+                None | Some("2021") => PlaygroundRustEdition::Rust2021,
+                Some("2024") => PlaygroundRustEdition::Rust2024,
+                Some(e) => return Err(invalid("toolchain.edition", format!("unknown `{e}`"))),
+            },
+            profile: match self.toolchain.profile.as_deref() {
+                None | Some("debug") => PlaygroundRustProfile::Debug,
+                Some("release") => PlaygroundRustProfile::Release,
+                Some(p) => return Err(invalid("toolchain.profile", format!("unknown `{p}`"))),
+            },
+        };
+        let playground_timeout = Duration::from_secs(self.playground.timeout.unwrap_or(60));
+        let playground_backends = match self.playground.backend.as_deref() {
+            None | Some("remote") => vec![RawFailoverBackend {
+                url: Some(
+                    self.playground
+                        .url
+                        .unwrap_or_else(|| "https://play.rust-lang.org".into()),
+                ),
+                ..Default::default()
+            }
+            .validate("playground.url")?],
+            Some("local") => vec![RawFailoverBackend {
+                local: true,
+                ..Default::default()
+            }
+            .validate("playground.backend")?],
+            Some("failover") => {
+                let backends = self
+                    .playground
+                    .failover
+                    .into_iter()
+                    .map(|backend| backend.validate("playground.failover"))
+                    .collect::<ConfigResult<Vec<_>>>()?;
+                if backends.is_empty() {
+                    return Err(ConfigError::Missing("playground.failover"));
+                }
+                if let Some(dup) = backends
+                    .iter()
+                    .enumerate()
+                    .find(|&(i, b)| backends[..i].iter().any(|b1| b1.name == b.name))
+                {
+                    return Err(invalid(
+                        "playground.failover",
+                        format!("duplicate name `{}`", dup.1.name),
+                    ));
+                }
+                backends
+            }
+            Some(b) => return Err(invalid("playground.backend", format!("unknown `{b}`"))),
+        };
+        let circuit_breaker = CircuitBreakerConfig {
+            failures: match self.playground.circuit_failures {
+                Some(0) => {
+                    return Err(invalid(
+                        "playground.circuit_failures",
+                        "must be at least 1".into(),
+                    ))
+                }
+                failures => failures.unwrap_or(3),
+            },
+            cooldown: Duration::from_secs(self.playground.circuit_cooldown.unwrap_or(60)),
+        };
+        let sandbox = if playground_backends.iter().any(|b| b.url.is_none()) {
+            Some(
+                self.sandbox
+                    .validate(default_toolchain.channel, playground_timeout)?,
+            )
+        } else {
+            None
+        };
+        let log_format = match self.log.format.as_deref() {
+            None | Some("text") => LogFormat::Text,
+            Some("json") => LogFormat::Json,
+            Some(f) => return Err(invalid("log.format", format!("unknown `{f}`"))),
+        };
+        let chat_policy = ChatPolicy {
+            allow_by_default: match self.chats.default.as_deref() {
+                None | Some("allow") => true,
+                Some("deny") => false,
+                Some(d) => return Err(invalid("chats.default", format!("unknown `{d}`"))),
+            },
+            allow: self.chats.allow,
+            deny: self.chats.deny,
+        };
+        Ok(Config {
+            db_path: self.db_path.unwrap_or_else(|| "ebrz.db".into()).into(),
+            shutdown_timeout: Duration::from_secs(self.shutdown_timeout.unwrap_or(10)),
+            tg_api_key,
+            tg_api_url,
+            tg_env,
+            tg_local_mode,
+            webhook,
+            playground_backends,
+            playground_timeout,
+            circuit_breaker,
+            sandbox,
+            default_toolchain,
+            log_format,
+            log_level: parse_value("log.level", self.log.level.as_deref().unwrap_or("info"))?,
+            chat_policy,
+        })
+    }
+}
+
+impl RawFailoverBackend {
+    fn validate(self, key: &'static str) -> ConfigResult<PlaygroundBackendConfig> {
+        let url = match (self.url, self.local) {
+            (Some(url), false) => Some(parse_base_url(key, url)?),
+            (None, true) => None,
+            _ => return Err(invalid(key, "needs either `url` or `local = true`".into())),
+        };
+        let name = self.name.unwrap_or_else(|| match &url {
+            // Named after the host by default
+            Some(url) => url
+                .split_once("://")
+                .map_or(&**url, |(_, host)| host)
+                .into(),
+            None => "local".into(),
+        });
+        Ok(PlaygroundBackendConfig { name, url })
+    }
+}
+
+impl RawSandboxConfig {
+    fn validate(
+        self,
+        default_channel: PlaygroundRustChannel,
+        timeout: Duration,
+    ) -> ConfigResult<SandboxConfig> {
+        let toolchains: HashMap<_, _> = [
+            (PlaygroundRustChannel::Stable, self.toolchains.stable),
+            (PlaygroundRustChannel::Beta, self.toolchains.beta),
+            (PlaygroundRustChannel::Nightly, self.toolchains.nightly),
+        ]
+        .into_iter()
+        .filter_map(|(channel, path)| Some((channel.as_str(), PathBuf::from(path?))))
+        .collect();
+        if !toolchains.contains_key(default_channel.as_str()) {
+            return Err(invalid(
+                "sandbox.toolchains",
+                format!(
+                    "no toolchain for the default `{}` channel",
+                    default_channel.as_str()
+                ),
+            ));
+        }
+        let memory = scale_limit(
+            "sandbox.memory_limit",
+            self.memory_limit.unwrap_or(2048),
+            20,
+        )?;
+        let output = scale_limit("sandbox.output_limit", self.output_limit.unwrap_or(64), 10)?;
+        let max_jobs = self.max_jobs.unwrap_or(4);
+        if max_jobs == 0 {
+            return Err(invalid("sandbox.max_jobs", "must be at least 1".into()));
+        }
+        let ro_binds = self.ro_binds.unwrap_or_else(|| {
+            [
+                "/usr",
+                "/bin",
+                "/lib",
+                "/lib64",
+                "/etc/alternatives",
+                "/etc/ld.so.cache",
+            ]
+            .map(String::from)
+            .into()
+        });
+        Ok(SandboxConfig {
+            bwrap: self.bwrap.unwrap_or_else(|| "bwrap".into()).into(),
+            work_dir: self
+                .work_dir
+                .map(PathBuf::from)
+                .unwrap_or_else(|| env::temp_dir().join("ebrz-sandbox")),
+            toolchains,
+            ro_binds: ro_binds.into_iter().map(PathBuf::from).collect(),
+            limits: SandboxLimits {
+                memory,
+                cpu_time: self.cpu_limit.unwrap_or(30),
+                output,
+                processes: self.process_limit.unwrap_or(256),
+            },
+            timeout,
+            max_jobs,
+        })
+    }
+}
+
+/// Converts a limit given in KiB or MiB to bytes.
+fn scale_limit(key: &'static str, value: u64, shift: u32) -> ConfigResult<u64> {
+    value
+        .checked_mul(1 << shift)
+        .ok_or_else(|| invalid(key, format!("{value} is too large")))
+}
+
+fn invalid(key: &'static str, reason: String) -> ConfigError {
+    ConfigError::Invalid { key, reason }
+}
+
+fn parse_value<T: std::str::FromStr>(key: &'static str, value: &str) -> ConfigResult<T>
+where
+    T::Err: std::fmt::Display,
+{
+    value
+        .trim()
+        .parse()
+        .map_err(|e: T::Err| invalid(key, format!("`{value}`: {e}")))
+}
+
+/// Parses a comma separated list, an empty value giving an empty list.
+fn parse_list<T: std::str::FromStr>(key: &'static str, value: &str) -> ConfigResult<Vec<T>>
+where
+    T::Err: std::fmt::Display,
+{
+    value
+        .split(',')
+        .filter(|item| !item.trim().is_empty())
+        .map(|item| parse_value(key, item))
+        .collect()
+}
+
+fn check_url(key: &'static str, url: String) -> ConfigResult<String> {
+    if !(url.starts_with("https://") || url.starts_with("http://")) {
+        return Err(invalid(key, format!("`{url}` is not an http(s) URL")));
+    }
+    Ok(url)
+}
+
+/// Drops the trailing slash, as paths are appended to base URLs.
+fn parse_base_url(key: &'static str, url: String) -> ConfigResult<String> {
+    Ok(check_url(key, url)?.trim_end_matches('/').to_owned())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_env_only() {
+        let config = Config::from_sources(None, |key| match key {
+            "EBRZ_TG_ENV" => Some("test".into()),
+            "EBRZ_TG_API_KEY" => Some("123:abc".into()),
+            _ => None,
+        })
+        .unwrap();
+        assert_eq!(config.tg_env, TgEnv::Test);
+        assert_eq!(config.db_path, PathBuf::from("ebrz.db"));
+        assert_eq!(
+            config.playground_backends,
+            [PlaygroundBackendConfig {
+                name: "play.rust-lang.org".into(),
+                url: Some("https://play.rust-lang.org".into()),
+            }]
+        );
+        assert_eq!(config.default_toolchain, PlaygroundRustToolchain::default());
+        assert!(config.webhook.is_none());
+        assert!(config.sandbox.is_none());
+        assert!(config.chat_policy.allows(42));
+    }
+
+    #[test]
+    fn test_sandbox() {
+        let file = r#"
+            [telegram]
+            env = "prod"
+            api_key = "123:abc"
+
+            [playground]
+            backend = "local"
+            timeout = 20
+
+            [sandbox]
+            work_dir = "/var/lib/ebrz/sandbox"
+            memory_limit = 1024
+
+            [sandbox.toolchains]
+            stable = "/opt/rust/1.83"
+            nightly = "/opt/rust/nightly"
+        "#;
+        let config = Config::from_sources(Some(file), |_| None).unwrap();
+        let sandbox = config.sandbox.unwrap();
+        assert_eq!(sandbox.bwrap, PathBuf::from("bwrap"));
+        assert_eq!(sandbox.work_dir, PathBuf::from("/var/lib/ebrz/sandbox"));
+        assert_eq!(
+            sandbox.toolchains,
+            HashMap::from([
+                ("stable", PathBuf::from("/opt/rust/1.83")),
+                ("nightly", PathBuf::from("/opt/rust/nightly")),
+            ])
+        );
+        assert_eq!(sandbox.limits.memory, 1 << 30);
+        assert_eq!(sandbox.limits.output, 64 << 10);
+        assert_eq!(sandbox.timeout, Duration::from_secs(20));
+        assert_eq!(sandbox.max_jobs, 4);
+
+        let res = Config::from_sources(Some(file), |key| {
+            (key == "EBRZ_PLAYGROUND_BACKEND").then(|| "remote".into())
+        });
+        assert!(res.unwrap().sandbox.is_none());
+        let res = Config::from_sources(Some(&file.replace("stable = ", "beta = ")), |_| None);
+        assert!(matches!(
+            res,
+            Err(ConfigError::Invalid {
+                key: "sandbox.toolchains",
+                ..
+            })
+        ));
+        let res = Config::from_sources(
+            Some(&file.replace("memory_limit", "max_jobs = 0\n#")),
+            |_| None,
+        );
+        assert!(matches!(
+            res,
+            Err(ConfigError::Invalid {
+                key: "sandbox.max_jobs",
+                ..
+            })
+        ));
+        let res =
+            Config::from_sources(Some(&file.replace("1024", &i64::MAX.to_string())), |_| None);
+        assert!(matches!(
+            res,
+            Err(ConfigError::Invalid {
+                key: "sandbox.memory_limit",
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn test_local_mode() {
+        let env = |api_url: &'static str| {
+            move |key: &str| match key {
+                "EBRZ_TG_ENV" => Some("prod".into()),
+                "EBRZ_TG_API_KEY" => Some("123:abc".into()),
+                "EBRZ_TG_API_URL" => Some(api_url.into()),
+                "EBRZ_TG_LOCAL_MODE" => Some("true".into()),
+                _ => None,
+            }
+        };
+        let config = Config::from_sources(None, env("http://127.0.0.1:8081/")).unwrap();
+        assert!(config.tg_local_mode);
+        assert_eq!(config.tg_api_url, "http://127.0.0.1:8081");
+        let res = Config::from_sources(None, env("https://api.telegram.org"));
+        assert!(matches!(
+            res,
+            Err(ConfigError::Invalid {
+                key: "telegram.local_mode",
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn test_failover() {
+        let file = r#"
+            [telegram]
+            env = "prod"
+            api_key = "123:abc"
+
+            [playground]
+            backend = "failover"
+            circuit_cooldown = 300
+
+            [[playground.failover]]
+            url = "https://play.rust-lang.org"
+
+            [[playground.failover]]
+            name = "mirror"
+            url = "https://play.example.com/"
+
+            [[playground.failover]]
+            local = true
+
+            [sandbox.toolchains]
+            stable = "/opt/rust/stable"
+        "#;
+        let config = Config::from_sources(Some(file), |_| None).unwrap();
+        let backends = config
+            .playground_backends
+            .iter()
+            .map(|b| (&*b.name, b.url.as_deref()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            backends,
+            [
+                ("play.rust-lang.org", Some("https://play.rust-lang.org")),
+                ("mirror", Some("https://play.example.com")),
+                ("local", None),
+            ]
+        );
+        assert_eq!(
+            config.circuit_breaker,
+            CircuitBreakerConfig {
+                failures: 3,
+                cooldown: Duration::from_secs(300),
+            }
+        );
+        assert!(config.sandbox.is_some());
+
+        let res = Config::from_sources(
+            Some(&file.replace("name = \"mirror\"", "name = \"local\"")),
+            |_| None,
+        );
+        assert!(matches!(
+            res,
+            Err(ConfigError::Invalid {
+                key: "playground.failover",
+                ..
+            })
+        ));
+        let res = Config::from_sources(Some(&file.replace("local = true", "")), |_| None);
+        assert!(matches!(
+            res,
+            Err(ConfigError::Invalid {
+                key: "playground.failover",
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn test_file_with_overrides() {
+        let file = r#"
+            db_path = "staging.db"
+
+            [telegram]
+            env = "prod"
+            api_key = "123:abc"
+
+            [webhook]
+            url = "https://bot.example.com/hook"
+            secret = "s3cret"
+
+            [playground]
+            url = "https://play.example.com/"
+            timeout = 30
+
+            [toolchain]
+            channel = "nightly"
+            edition = "2024"
+
+            [chats]
+            default = "deny"
+            allow = [-100123]
+        "#;
+        let config = Config::from_sources(Some(file), |key| {
+            (key == "EBRZ_DB_PATH").then(|| "prod.db".into())
+        })
+        .unwrap();
+        assert_eq!(config.db_path, PathBuf::from("prod.db"));
+        assert_eq!(
+            config.playground_backends[0].url.as_deref(),
+            Some("https://play.example.com")
+        );
+        assert_eq!(config.playground_timeout, Duration::from_secs(30));
+        assert_eq!(
+            config.default_toolchain.channel,
+            PlaygroundRustChannel::Nightly
+        );
+        let webhook = config.webhook.unwrap();
+        assert_eq!(webhook.listen_addr, "127.0.0.1:8080".parse().unwrap());
+        assert_eq!(webhook.listen_path, None);
+        assert!(config.chat_policy.allows(-100123));
+        assert!(!config.chat_policy.allows(42));
+    }
+
+    #[test]
+    fn test_env_overrides() {
+        let file = r#"
+            shutdown_timeout = 10
+
+            [telegram]
+            env = "prod"
+            api_key = "123:abc"
+
+            [toolchain]
+            channel = "nightly"
+
+            [chats]
+            allow = [-100123]
+        "#;
+        let config = Config::from_sources(Some(file), |key| match key {
+            "EBRZ_SHUTDOWN_TIMEOUT" => Some("30".into()),
+            "EBRZ_TOOLCHAIN_CHANNEL" => Some("beta".into()),
+            "EBRZ_TOOLCHAIN_EDITION" => Some("2024".into()),
+            "EBRZ_CHATS_DEFAULT" => Some("deny".into()),
+            "EBRZ_CHATS_ALLOW" => Some("-1001, -1002".into()),
+            "EBRZ_CHATS_DENY" => Some("".into()),
+            _ => None,
+        })
+        .unwrap();
+        assert_eq!(config.shutdown_timeout, Duration::from_secs(30));
+        assert_eq!(
+            config.default_toolchain,
+            PlaygroundRustToolchain {
+                channel: PlaygroundRustChannel::Beta,
+                edition: PlaygroundRustEdition::Rust2024,
+                ..Default::default()
+            }
+        );
+        assert!(config.chat_policy.allows(-1002));
+        assert!(!config.chat_policy.allows(-100123));
+        let res = Config::from_sources(Some(file), |key| {
+            (key == "EBRZ_CHATS_ALLOW").then(|| "-1001,x".into())
+        });
+        assert!(matches!(
+            res,
+            Err(ConfigError::Invalid {
+                key: "EBRZ_CHATS_ALLOW",
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn test_invalid() {
+        let res = Config::from_sources(Some("[telegram]\nenv = \"prod\""), |_| None);
+        assert!(matches!(res, Err(ConfigError::Missing("telegram.api_key"))));
+        let res = Config::from_sources(
+            Some("[telegram]\nenv = \"staging\"\napi_key = \"k\""),
+            |_| None,
+        );
+        assert!(matches!(
+            res,
+            Err(ConfigError::Invalid {
+                key: "telegram.env",
+                ..
+            })
+        ));
+        let res = Config::from_sources(Some("unknown = 1"), |_| None);
+        assert!(matches!(res, Err(ConfigError::Parse(_))));
+    }
+}
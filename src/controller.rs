@@ -1,21 +1,36 @@
 use std::{
+    borrow::Cow,
     fmt::{self, Debug},
     future::Future,
 };
 
 mod delete_eval;
+mod diagnostics;
 mod eval_state;
 mod get_eval_link;
 mod new_eval;
 mod page_data;
+mod pending_evals;
+mod remap;
+mod test_summary;
+mod toolchain_flags;
 mod update_msg_id;
+mod update_offset;
 mod wait_eval;
+mod wrap;
+
+#[cfg(test)]
+mod tests;
 
 use event_listener::EventListener;
 
 use crate::{
-    repository::playground_record::{
-        CreateRevisionUpsertRecordResult, IPlaygroundRecordRepository,
+    repository::{
+        bot_update::IBotUpdateRepository,
+        playground_artifact::PlaygroundArtifactKind,
+        playground_record::{
+            CreateRevisionUpsertRecordResult, IPlaygroundRecordRepository, PlaygroundRustToolchain,
+        },
     },
     service::playground::IPlaygrounService,
 };
@@ -23,11 +38,13 @@ use crate::{
 pub trait IController {
     type EvalProcessingImpl: WaitForEvalResult + UpdateEvalMsgId;
     type RevertDeleteEvalImpl: RevertDeleteEval;
+    type LazyToolImpl: WaitForEvalResult;
     fn new_eval(
         &self,
         chat_id: i64,
         user_msg_id: i64,
         created_by_user_id: i64,
+        kind: EvalKind,
         code: String,
     ) -> impl Future<Output = EvalResponse<EvalProcessingResponse<Self::EvalProcessingImpl>>>;
     fn switch_eval_state(
@@ -36,7 +53,8 @@ pub trait IController {
         request_user_id: i64,
         revision_id: i64,
         request_page_state: EvalPageState,
-    ) -> impl Future<Output = ShowEvalOutputResponse>;
+        page: u32,
+    ) -> impl Future<Output = ShowEvalOutputResponse<Self::LazyToolImpl>>;
     fn request_delete_eval(
         &self,
         eval_msg_id: i64,
@@ -44,6 +62,15 @@ pub trait IController {
         revision_id: i64,
     ) -> impl Future<Output = RequestDeleteEvalResponse<Self::RevertDeleteEvalImpl>>;
     fn get_eval_link(&self, revision_id: i64) -> impl Future<Output = GetEvalLinkResponse>;
+    /// Evals left unfinished by a previous run, unfinished ones that nobody waits for anymore
+    /// are settled on the way.
+    fn pending_evals(
+        &self,
+    ) -> impl Future<Output = Vec<PendingEval<EvalProcessingResponse<Self::EvalProcessingImpl>>>>;
+    /// Where polling should resume, as persisted by `mark_update_handled`.
+    fn update_offset(&self) -> impl Future<Output = Option<i64>>;
+    fn is_update_handled(&self, update_id: i64) -> impl Future<Output = bool>;
+    fn mark_update_handled(&self, update_id: i64) -> impl Future<Output = ()>;
 }
 
 #[derive(Clone)]
@@ -76,6 +103,18 @@ impl<I: UpdateEvalMsgId> UpdateEvalMsgId for EvalProcessingResponse<I> {
     }
 }
 
+#[derive(Debug, Clone)]
+pub enum PendingEval<R> {
+    /// Runs again, the eval message should be updated as for a new eval
+    Resume {
+        chat_id: i64,
+        eval_msg_id: i64,
+        processing: R,
+    },
+    /// Too old to run again, the eval message should tell so
+    Abandoned { chat_id: i64, eval_msg_id: i64 },
+}
+
 #[derive(Debug, Clone)]
 pub enum EvalResponse<R> {
     Processing(R),
@@ -89,9 +128,14 @@ pub struct EvalPageData {
     pub has_error: bool,
     pub has_fatal_error: bool,
     pub diagnostic_count: u32,
+    pub lint_count: Option<u32>,
+    pub artifacts: Vec<EvalArtifact>,
     pub revision: u32,
     pub revision_id: i64,
-    pub title: &'static str,
+    pub state: EvalPageState,
+    /// Requested page of the content, may be past the last one.
+    pub page: u32,
+    pub title: Cow<'static, str>,
     pub content: String,
 }
 
@@ -119,16 +163,70 @@ pub enum EvalResultResponse {
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
-pub enum ShowEvalOutputResponse {
+pub enum ShowEvalOutputResponse<R> {
     Ok(EvalPageData),
+    /// The page needs a tool to run first, waiting for `R` gives the page once it is done
+    Processing(R),
     SenderMismatch,
     Err(String),
 }
 
-#[derive(Debug, Clone, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EvalKind {
+    Eval,
+    Run,
+    Miri,
+    Clippy,
+    Format,
+    Test,
+    Artifact(EvalArtifact),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum EvalPageState {
     Output,
     Build,
+    Miri,
+    Clippy,
+    Format,
+    Artifact(EvalArtifact),
+}
+
+/// What the code compiles to, as opposed to what it prints.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EvalArtifact {
+    Asm,
+    LlvmIr,
+    Mir,
+    Hir,
+    Wasm,
+    MacroExpansion,
+}
+
+impl From<EvalArtifact> for PlaygroundArtifactKind {
+    fn from(artifact: EvalArtifact) -> Self {
+        match artifact {
+            EvalArtifact::Asm => PlaygroundArtifactKind::Asm,
+            EvalArtifact::LlvmIr => PlaygroundArtifactKind::LlvmIr,
+            EvalArtifact::Mir => PlaygroundArtifactKind::Mir,
+            EvalArtifact::Hir => PlaygroundArtifactKind::Hir,
+            EvalArtifact::Wasm => PlaygroundArtifactKind::Wasm,
+            EvalArtifact::MacroExpansion => PlaygroundArtifactKind::MacroExpansion,
+        }
+    }
+}
+
+impl From<PlaygroundArtifactKind> for EvalArtifact {
+    fn from(kind: PlaygroundArtifactKind) -> Self {
+        match kind {
+            PlaygroundArtifactKind::Asm => EvalArtifact::Asm,
+            PlaygroundArtifactKind::LlvmIr => EvalArtifact::LlvmIr,
+            PlaygroundArtifactKind::Mir => EvalArtifact::Mir,
+            PlaygroundArtifactKind::Hir => EvalArtifact::Hir,
+            PlaygroundArtifactKind::Wasm => EvalArtifact::Wasm,
+            PlaygroundArtifactKind::MacroExpansion => EvalArtifact::MacroExpansion,
+        }
+    }
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
@@ -149,32 +247,37 @@ pub enum GetEvalLinkResponse {
 pub struct Controller<R, P> {
     repo: R,
     playground: P,
+    default_toolchain: PlaygroundRustToolchain,
 }
 
 impl<R, P> Controller<R, P> {
-    pub fn new(repo: R, service: P) -> Self {
+    pub fn new(repo: R, service: P, default_toolchain: PlaygroundRustToolchain) -> Self {
         Self {
             repo,
             playground: service,
+            default_toolchain,
         }
     }
 }
 
-impl<R: IPlaygroundRecordRepository, P: IPlaygrounService> IController for Controller<R, P>
+impl<R: IPlaygroundRecordRepository + IBotUpdateRepository, P: IPlaygrounService> IController
+    for Controller<R, P>
 where
     Self: Clone,
 {
     type EvalProcessingImpl = wait_eval::EvalProcessingResponseImpl<R, P>;
     type RevertDeleteEvalImpl = delete_eval::RevertDeleteEvalImpl<R, P>;
+    type LazyToolImpl = eval_state::LazyToolImpl<R, P>;
 
     fn new_eval(
         &self,
         chat_id: i64,
         user_msg_id: i64,
         created_by_user_id: i64,
+        kind: EvalKind,
         code: String,
     ) -> impl Future<Output = EvalResponse<EvalProcessingResponse<Self::EvalProcessingImpl>>> {
-        self.new_eval(chat_id, user_msg_id, created_by_user_id, code)
+        self.new_eval(chat_id, user_msg_id, created_by_user_id, kind, code)
     }
 
     fn request_delete_eval(
@@ -192,16 +295,37 @@ where
         request_user_id: i64,
         revision_id: i64,
         request_page_state: EvalPageState,
-    ) -> impl Future<Output = ShowEvalOutputResponse> {
+        page: u32,
+    ) -> impl Future<Output = ShowEvalOutputResponse<Self::LazyToolImpl>> {
         self.eval_state(
             eval_msg_id,
             request_user_id,
             revision_id,
             request_page_state,
+            page,
         )
     }
 
     async fn get_eval_link(&self, revision_id: i64) -> GetEvalLinkResponse {
         self.get_eval_link(revision_id).await
     }
+
+    fn pending_evals(
+        &self,
+    ) -> impl Future<Output = Vec<PendingEval<EvalProcessingResponse<Self::EvalProcessingImpl>>>>
+    {
+        self.pending_evals()
+    }
+
+    async fn update_offset(&self) -> Option<i64> {
+        self.update_offset().await
+    }
+
+    async fn is_update_handled(&self, update_id: i64) -> bool {
+        self.is_update_handled(update_id).await
+    }
+
+    async fn mark_update_handled(&self, update_id: i64) {
+        self.mark_update_handled(update_id).await
+    }
 }
@@ -0,0 +1,245 @@
+use crate::repository::playground_diagnostic::{
+    PlaygroundDiagnostic, PlaygroundDiagnosticLevel, PlaygroundDiagnosticSpan,
+};
+
+/// Parses the human readable rustc output produced by cargo on the playground. Parsing stops once
+/// cargo starts running the compiled program so that its own stderr is never mistaken for
+/// diagnostics.
+pub(super) fn parse_diagnostics(stderr: &str) -> Vec<PlaygroundDiagnostic> {
+    let mut diagnostics: Vec<PlaygroundDiagnostic> = vec![];
+    // Whether the lines being read still belong to the last diagnostic
+    let mut in_diagnostic = false;
+    for line in stderr.lines() {
+        if line.starts_with("     Running `") {
+            break;
+        }
+        if let Some((level, code, message)) = parse_header(line) {
+            match (level, diagnostics.last_mut()) {
+                (PlaygroundDiagnosticLevel::Error | PlaygroundDiagnosticLevel::Warning, _) => {
+                    if is_cargo_summary(message) {
+                        in_diagnostic = false;
+                        continue;
+                    }
+                    diagnostics.push(PlaygroundDiagnostic {
+                        level,
+                        code: code.map(Into::into),
+                        message: message.into(),
+                        spans: vec![],
+                        notes: vec![],
+                    });
+                    in_diagnostic = true;
+                }
+                // Child diagnostics such as `note: required by a bound in ...`
+                (_, Some(diagnostic)) if in_diagnostic => {
+                    diagnostic.notes.push(line.into());
+                }
+                _ => in_diagnostic = false,
+            }
+            continue;
+        }
+        let Some(diagnostic) = diagnostics.last_mut().filter(|_| in_diagnostic) else {
+            continue;
+        };
+        let trimmed = line.trim_start();
+        if let Some(location) = trimmed
+            .strip_prefix("--> ")
+            .or_else(|| trimmed.strip_prefix("::: "))
+        {
+            if let Some(span) = parse_span(location) {
+                diagnostic.spans.push(span);
+            }
+        } else if let Some(note) = trimmed.strip_prefix("= ") {
+            diagnostic.notes.push(note.into());
+        } else if let Some(annotation) = trimmed.strip_prefix('|') {
+            let label = annotation
+                .trim_start_matches([' ', '|', '_', '-'])
+                .trim_start_matches('^')
+                .trim();
+            if let Some(span) = diagnostic.spans.last_mut() {
+                if span.label.is_none() && annotation.contains('^') && !label.is_empty() {
+                    span.label = Some(label.into());
+                }
+            }
+        } else if line.is_empty() {
+            in_diagnostic = false;
+        } else if line.starts_with(char::is_whitespace) && !is_gutter(trimmed) {
+            // Wrapped continuation of a `= note:` line
+            if let Some(note) = diagnostic.notes.last_mut() {
+                note.push('\n');
+                note.push_str(trimmed);
+            }
+        }
+    }
+    diagnostics
+}
+
+/// Counts the errors and warnings among `diagnostics`.
+pub(super) fn count_diagnostics<'a>(
+    diagnostics: impl IntoIterator<Item = &'a PlaygroundDiagnostic>,
+) -> (u32, u32) {
+    diagnostics
+        .into_iter()
+        .fold((0, 0), |(errors, warnings), diagnostic| {
+            match diagnostic.level {
+                PlaygroundDiagnosticLevel::Error => (errors + 1, warnings),
+                PlaygroundDiagnosticLevel::Warning => (errors, warnings + 1),
+                PlaygroundDiagnosticLevel::Note | PlaygroundDiagnosticLevel::Help => {
+                    (errors, warnings)
+                }
+            }
+        })
+}
+
+fn parse_header(line: &str) -> Option<(PlaygroundDiagnosticLevel, Option<&str>, &str)> {
+    let (head, message) = line.split_once(": ")?;
+    let (level, code) = match head.split_once('[') {
+        Some((level, code)) => (level, Some(code.strip_suffix(']')?)),
+        None => (head, None),
+    };
+    let level = match level {
+        "error" => PlaygroundDiagnosticLevel::Error,
+        "warning" => PlaygroundDiagnosticLevel::Warning,
+        "note" => PlaygroundDiagnosticLevel::Note,
+        "help" => PlaygroundDiagnosticLevel::Help,
+        _ => return None,
+    };
+    Some((level, code, message))
+}
+
+fn is_cargo_summary(message: &str) -> bool {
+    message.starts_with("`playground` (")
+        || message.starts_with("could not compile `playground`")
+        || message.starts_with("aborting due to")
+        || message.starts_with("build failed")
+        // rustc's own summary, the local backend runs it without cargo
+        || message
+            .split_once(' ')
+            .is_some_and(|(count, rest)| {
+                count.parse::<u32>().is_ok()
+                    && matches!(rest, "warning emitted" | "warnings emitted")
+            })
+}
+
+fn is_gutter(trimmed: &str) -> bool {
+    trimmed
+        .split_once('|')
+        .is_some_and(|(head, _)| head.trim_end().bytes().all(|b| b.is_ascii_digit()))
+}
+
+fn parse_span(location: &str) -> Option<PlaygroundDiagnosticSpan> {
+    let mut parts = location.trim().rsplitn(3, ':');
+    let column = parts.next()?.parse().ok()?;
+    let line = parts.next()?.parse().ok()?;
+    let file = parts.next()?;
+    Some(PlaygroundDiagnosticSpan {
+        file: file.into(),
+        line,
+        column,
+        label: None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_diagnostics() {
+        let stderr = "   Compiling playground v0.0.1 (/playground)
+warning: unused variable: `a`
+ --> src/main.rs:1:17
+  |
+1 | fn main() { let a = 1; println!(\"Hello, world!\"); }
+  |                 ^ help: if this is intentional, prefix it with an underscore: `_a`
+  |
+  = note: `#[warn(unused_variables)]` on by default
+
+error[E0277]: cannot add `&str` to `{integer}`
+ --> src/main.rs:1:24
+  |
+1 | fn main() { let a = 1;a+\"\"; println!(\"Hello, world!\"); }
+  |                        ^ no implementation for `{integer} + &str`
+  |
+  = help: the trait `Add<&str>` is not implemented for `{integer}`
+  = help: the following other types implement trait `Add<Rhs>`:
+            `&f128` implements `Add<f128>`
+          and 56 others
+note: required by a bound in `foo`
+
+For more information about this error, try `rustc --explain E0277`.
+warning: `playground` (bin \"playground\") generated 1 warning
+error: could not compile `playground` (bin \"playground\") due to 1 previous error; 1 warning emitted
+";
+        let diagnostics = parse_diagnostics(stderr);
+        assert_eq!(
+            diagnostics,
+            vec![
+                PlaygroundDiagnostic {
+                    level: PlaygroundDiagnosticLevel::Warning,
+                    code: None,
+                    message: "unused variable: `a`".into(),
+                    spans: vec![PlaygroundDiagnosticSpan {
+                        file: "src/main.rs".into(),
+                        line: 1,
+                        column: 17,
+                        label: Some(
+                            "help: if this is intentional, prefix it with an underscore: `_a`"
+                                .into()
+                        ),
+                    }],
+                    notes: vec!["note: `#[warn(unused_variables)]` on by default".into()],
+                },
+                PlaygroundDiagnostic {
+                    level: PlaygroundDiagnosticLevel::Error,
+                    code: Some("E0277".into()),
+                    message: "cannot add `&str` to `{integer}`".into(),
+                    spans: vec![PlaygroundDiagnosticSpan {
+                        file: "src/main.rs".into(),
+                        line: 1,
+                        column: 24,
+                        label: Some("no implementation for `{integer} + &str`".into()),
+                    }],
+                    notes: vec![
+                        "help: the trait `Add<&str>` is not implemented for `{integer}`".into(),
+                        "help: the following other types implement trait `Add<Rhs>`:\n`&f128` implements `Add<f128>`\nand 56 others".into(),
+                        "note: required by a bound in `foo`".into(),
+                    ],
+                },
+            ]
+        );
+        assert_eq!(count_diagnostics(&diagnostics), (1, 1));
+    }
+
+    #[test]
+    fn test_rustc_summary() {
+        let stderr = "warning: unused variable: `a`
+ --> src/main.rs:1:17
+  |
+1 | fn main() { let a = 1; let b = 2; }
+  |                 ^ help: if this is intentional, prefix it with an underscore: `_a`
+  |
+  = note: `#[warn(unused_variables)]` on by default
+
+warning: unused variable: `b`
+ --> src/main.rs:1:28
+  |
+1 | fn main() { let a = 1; let b = 2; }
+  |                            ^ help: if this is intentional, prefix it with an underscore: `_b`
+
+warning: 2 warnings emitted
+
+";
+        assert_eq!(count_diagnostics(&parse_diagnostics(stderr)), (0, 2));
+        let stderr = stderr.replace("2 warnings emitted", "1 warning emitted");
+        assert_eq!(count_diagnostics(&parse_diagnostics(&stderr)), (0, 2));
+    }
+
+    #[test]
+    fn test_stops_at_program_output() {
+        let stderr = "    Finished `dev` profile [unoptimized + debuginfo] target(s) in 0.76s
+     Running `target/debug/playground`
+error: printed by the program
+";
+        assert!(parse_diagnostics(stderr).is_empty());
+    }
+}
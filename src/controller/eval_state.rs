@@ -1,23 +1,58 @@
+use std::pin::pin;
+
+use futures::future::{select, Either};
 use tracing::error;
 
 use super::*;
-use crate::repository::playground_record::{PlaygroundRecordPageState, PlaygroundRecordRevisionId};
+use crate::repository::playground_record::{
+    PlaygroundRecordPageState, PlaygroundRecordRevision, PlaygroundRecordRevisionId,
+};
 use page_data::build_page_data;
+use wait_eval::{apply_format_result, apply_miri_result};
+use wrap::render_format_code;
+
+/// A page whose tool has not run for the revision yet, waiting for it runs the tool.
+#[derive(Clone)]
+pub struct LazyToolImpl<R, P> {
+    controller: Controller<R, P>,
+    revision: PlaygroundRecordRevision,
+    page_state: PlaygroundRecordPageState,
+    page: u32,
+}
 
-impl<R: IPlaygroundRecordRepository, P> Controller<R, P> {
+impl<R, P> Debug for LazyToolImpl<R, P> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("LazyTool")
+            .field("revision_id", &self.revision.revision_id)
+            .field("page_state", &self.page_state)
+            .finish()
+    }
+}
+
+impl<R: IPlaygroundRecordRepository, P: IPlaygrounService> Controller<R, P>
+where
+    Self: Clone,
+{
     pub(super) async fn eval_state(
         &self,
         eval_msg_id: i64,
         request_user_id: i64,
         revision_id: i64,
         request_page_state: EvalPageState,
-    ) -> ShowEvalOutputResponse {
+        page: u32,
+    ) -> ShowEvalOutputResponse<LazyToolImpl<R, P>> {
         let Ok(revision_id) = PlaygroundRecordRevisionId::try_from(revision_id) else {
             return ShowEvalOutputResponse::SenderMismatch;
         };
         let request_page_state = match request_page_state {
             EvalPageState::Output => PlaygroundRecordPageState::Output,
             EvalPageState::Build => PlaygroundRecordPageState::Stderr,
+            EvalPageState::Miri => PlaygroundRecordPageState::Miri,
+            EvalPageState::Clippy => PlaygroundRecordPageState::Clippy,
+            EvalPageState::Format => PlaygroundRecordPageState::Format,
+            EvalPageState::Artifact(artifact) => {
+                PlaygroundRecordPageState::Artifact(artifact.into())
+            }
         };
         let res = self
             .repo
@@ -26,11 +61,20 @@ impl<R: IPlaygroundRecordRepository, P> Controller<R, P> {
                 request_user_id,
                 revision_id,
                 request_page_state,
+                page,
             )
             .await;
         match res {
+            Ok(Some(revision)) if needs_lazy_tool(&revision, request_page_state) => {
+                ShowEvalOutputResponse::Processing(LazyToolImpl {
+                    controller: self.clone(),
+                    revision,
+                    page_state: request_page_state,
+                    page,
+                })
+            }
             Ok(Some(revision)) => {
-                ShowEvalOutputResponse::Ok(build_page_data(revision, request_page_state))
+                ShowEvalOutputResponse::Ok(build_page_data(revision, request_page_state, page))
             }
             Ok(None) => ShowEvalOutputResponse::SenderMismatch,
             Err(e) => {
@@ -40,3 +84,92 @@ impl<R: IPlaygroundRecordRepository, P> Controller<R, P> {
         }
     }
 }
+
+fn needs_lazy_tool(
+    revision: &PlaygroundRecordRevision,
+    page_state: PlaygroundRecordPageState,
+) -> bool {
+    if !revision.playground_error.is_empty() {
+        return false;
+    }
+    match page_state {
+        PlaygroundRecordPageState::Miri => revision.miri_success.is_none(),
+        PlaygroundRecordPageState::Format => revision.format_success.is_none(),
+        _ => false,
+    }
+}
+
+impl<R: IPlaygroundRecordRepository, P: IPlaygrounService> WaitForEvalResult
+    for LazyToolImpl<R, P>
+{
+    async fn wait_for_eval_result(mut self, cancel_event: EventListener) -> EvalResultResponse {
+        let Either::Left((res, _)) = select(pin!(self.run_lazy_tool()), cancel_event).await else {
+            return EvalResultResponse::Cancelled;
+        };
+        match res {
+            Ok(()) => {
+                if let Err(e) = self
+                    .controller
+                    .repo
+                    .update_tool_results_for_revision_id(&self.revision)
+                    .await
+                {
+                    error!("Failed to update tool results: {}", e);
+                }
+                EvalResultResponse::Ok(build_page_data(self.revision, self.page_state, self.page))
+            }
+            // Keep the buttons around, so that the tool can be tried again
+            Err(e) => {
+                let mut data = build_page_data(self.revision, self.page_state, self.page);
+                data.title = "Error".into();
+                data.content = e.into();
+                EvalResultResponse::Ok(data)
+            }
+        }
+    }
+}
+
+impl<R, P: IPlaygrounService> LazyToolImpl<R, P> {
+    async fn run_lazy_tool(&mut self) -> Result<(), &'static str> {
+        let revision = &mut self.revision;
+        let playground = &self.controller.playground;
+        match self.page_state {
+            PlaygroundRecordPageState::Miri => {
+                let res = playground
+                    .run_miri(
+                        &revision.rendered_code,
+                        revision.toolchain.edition.as_str(),
+                        // Test code has no `main` to run
+                        revision.tests,
+                    )
+                    .await;
+                match res {
+                    Ok(miri_res) => apply_miri_result(revision, miri_res),
+                    Err(e) => {
+                        error!("Failed to run miri: {}", e);
+                        return Err("Error running Miri");
+                    }
+                }
+            }
+            PlaygroundRecordPageState::Format => {
+                let format_code = render_format_code(&revision.user_code, &revision.rendered_code);
+                let res = playground
+                    .format(
+                        &format_code,
+                        revision.toolchain.channel.as_str(),
+                        revision.toolchain.edition.as_str(),
+                    )
+                    .await;
+                match res {
+                    Ok(format_res) => apply_format_result(revision, format_res),
+                    Err(e) => {
+                        error!("Failed to format code: {}", e);
+                        return Err("Error formatting code");
+                    }
+                }
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+}
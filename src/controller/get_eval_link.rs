@@ -9,7 +9,8 @@ impl<R: IPlaygroundRecordRepository, P: IPlaygrounService> Controller<R, P> {
         let Ok(revision_id) = PlaygroundRecordRevisionId::try_from(revision_id) else {
             return GetEvalLinkResponse::NotFound;
         };
-        let (mut revision, page_state) = match self.repo.get_revision_by_id(revision_id).await {
+        let (mut revision, page_state, page) = match self.repo.get_revision_by_id(revision_id).await
+        {
             Ok(Some(revision)) => revision,
             Ok(None) => return GetEvalLinkResponse::NotFound,
             Err(e) => {
@@ -19,12 +20,17 @@ impl<R: IPlaygroundRecordRepository, P: IPlaygrounService> Controller<R, P> {
         };
 
         if revision.perma_link.is_some() {
-            return GetEvalLinkResponse::Ok(build_page_data(revision, page_state));
+            return GetEvalLinkResponse::Ok(build_page_data(revision, page_state, page));
         }
 
         let res = self
             .playground
-            .generate_link(&revision.rendered_code, "stable", "debug", "2021")
+            .generate_link(
+                &revision.rendered_code,
+                revision.toolchain.channel.as_str(),
+                revision.toolchain.profile.as_str(),
+                revision.toolchain.edition.as_str(),
+            )
             .await;
         match res {
             Ok(link) => {
@@ -36,7 +42,7 @@ impl<R: IPlaygroundRecordRepository, P: IPlaygrounService> Controller<R, P> {
                     error!("Failed to update perma_link: {}", e);
                 }
                 revision.perma_link = Some(link);
-                GetEvalLinkResponse::Ok(build_page_data(revision, page_state))
+                GetEvalLinkResponse::Ok(build_page_data(revision, page_state, page))
             }
             Err(e) => {
                 error!("Failed to generate link: {}", e);
@@ -1,8 +1,12 @@
 use tracing::error;
 
 use super::*;
-use crate::repository::playground_record::PlaygroundRecordPageState;
+use crate::repository::playground_record::{
+    NewPlaygroundRecordRevision, PlaygroundRecordPageState,
+};
+use toolchain_flags::parse_toolchain_flags;
 use wait_eval::EvalProcessingResponseImpl;
+use wrap::render_code;
 
 impl<R: IPlaygroundRecordRepository, P: IPlaygrounService> Controller<R, P>
 where
@@ -13,22 +17,37 @@ where
         chat_id: i64,
         user_msg_id: i64,
         created_by_user_id: i64,
+        kind: EvalKind,
         code: String,
     ) -> EvalResponse<EvalProcessingResponse<EvalProcessingResponseImpl<R, P>>> {
-        let full_code = format!(
-            "fn main() {{ let res = {{
-            {} 
-            }}; println!(\"{{res:?}}\"); }}",
-            &code
-        );
+        let (toolchain, code) = match parse_toolchain_flags(&code, self.default_toolchain) {
+            Ok(res) => res,
+            Err(flag) => return EvalResponse::Err(format!("Unknown flag: +{flag}")),
+        };
+        let full_code = render_code(kind, code);
         let res = match self
             .repo
             .create_revision_upsert_record(
                 chat_id,
                 user_msg_id,
                 created_by_user_id,
-                full_code.clone(),
-                PlaygroundRecordPageState::Output,
+                NewPlaygroundRecordRevision {
+                    user_code: code.to_owned(),
+                    rendered_code: full_code.clone(),
+                    toolchain,
+                    tests: kind == EvalKind::Test,
+                },
+                match kind {
+                    EvalKind::Eval | EvalKind::Run | EvalKind::Test => {
+                        PlaygroundRecordPageState::Output
+                    }
+                    EvalKind::Miri => PlaygroundRecordPageState::Miri,
+                    EvalKind::Clippy => PlaygroundRecordPageState::Clippy,
+                    EvalKind::Format => PlaygroundRecordPageState::Format,
+                    EvalKind::Artifact(artifact) => {
+                        PlaygroundRecordPageState::Artifact(artifact.into())
+                    }
+                },
             )
             .await
         {
@@ -41,7 +60,10 @@ where
         EvalResponse::Processing(EvalProcessingResponse {
             eval_msg_id: res.eval_msg_id,
             imp: EvalProcessingResponseImpl {
+                user_code: code.to_owned(),
                 rendered_code: full_code,
+                kind,
+                toolchain,
                 controller: self.clone(),
                 upsert_result: res,
             },
@@ -1,34 +1,131 @@
-use super::EvalPageData;
-use crate::repository::playground_record::{PlaygroundRecordPageState, PlaygroundRecordRevision};
+use super::{test_summary::parse_test_summary, EvalPageData, EvalPageState};
+use crate::repository::{
+    playground_artifact::PlaygroundArtifactKind,
+    playground_record::{PlaygroundRecordPageState, PlaygroundRecordRevision},
+};
 
 pub(super) fn build_page_data(
     revision: PlaygroundRecordRevision,
     page_state: PlaygroundRecordPageState,
+    page: u32,
 ) -> EvalPageData {
     let mut data = EvalPageData {
         perma_link: revision.perma_link,
         has_warning: revision.warning_count > 0,
-        has_error: !revision.result_success,
+        has_error: revision.error_count > 0 || !revision.result_success,
         has_fatal_error: !revision.playground_error.is_empty(),
         diagnostic_count: revision.error_count + revision.warning_count,
+        lint_count: revision
+            .clippy_success
+            .map(|_| revision.clippy_warning_count + revision.clippy_error_count),
+        artifacts: revision.artifacts.iter().map(|a| a.kind.into()).collect(),
         revision: revision.record_revision_count,
         revision_id: revision.revision_id.0.get(),
-        title: "",
+        state: match page_state {
+            PlaygroundRecordPageState::Output => EvalPageState::Output,
+            PlaygroundRecordPageState::Stderr => EvalPageState::Build,
+            PlaygroundRecordPageState::Miri => EvalPageState::Miri,
+            PlaygroundRecordPageState::Clippy => EvalPageState::Clippy,
+            PlaygroundRecordPageState::Format => EvalPageState::Format,
+            PlaygroundRecordPageState::Artifact(kind) => EvalPageState::Artifact(kind.into()),
+        },
+        page,
+        title: "".into(),
         content: "".into(),
     };
     match (revision.playground_error.is_empty(), page_state) {
+        (true, PlaygroundRecordPageState::Output) if revision.tests => {
+            match parse_test_summary(&revision.result_stdout) {
+                Some(summary) => {
+                    data.title = summary.title().into();
+                    if !summary.failures.is_empty() {
+                        data.content = summary.failures.join("\n\n");
+                    }
+                }
+                None => data.title = "Tests".into(),
+            }
+            if data.content.is_empty() {
+                data.content = revision.result_stdout;
+            }
+        }
         (true, PlaygroundRecordPageState::Output) => {
-            data.title = "Output";
+            data.title = "Output".into();
             data.content = revision.result_stdout;
         }
         (true, PlaygroundRecordPageState::Stderr) => {
-            data.title = "Stderr";
+            data.title = "Stderr".into();
             data.content = revision.result_stderr;
         }
+        (true, PlaygroundRecordPageState::Miri) => {
+            data.title = match revision.miri_success {
+                Some(true) | None => "Miri",
+                Some(false) => "Miri (failed)",
+            }
+            .into();
+            data.content = match (
+                revision.miri_success,
+                revision.miri_stdout.is_empty(),
+                revision.miri_stderr.is_empty(),
+            ) {
+                (None, _, _) => "Not run under Miri yet".into(),
+                (_, true, _) => revision.miri_stderr,
+                (_, false, true) => revision.miri_stdout,
+                (_, false, false) => format!("{}\n{}", revision.miri_stdout, revision.miri_stderr),
+            };
+        }
+        (true, PlaygroundRecordPageState::Clippy) => {
+            data.title = "Clippy".into();
+            data.content = match revision.clippy_success {
+                Some(_) => revision.clippy_stderr,
+                None => "Not checked with Clippy yet".into(),
+            };
+        }
+        (true, PlaygroundRecordPageState::Format) => {
+            data.title = match revision.format_success {
+                Some(true) | None => "Formatted",
+                Some(false) => "Format (failed)",
+            }
+            .into();
+            data.content = match revision.format_success {
+                Some(_) => revision.code_formatted,
+                None => "Not formatted yet".into(),
+            };
+        }
+        (true, PlaygroundRecordPageState::Artifact(kind)) => {
+            let artifact = revision.artifacts.into_iter().find(|a| a.kind == kind);
+            data.title = artifact_title(kind, artifact.as_ref().map(|a| a.success)).into();
+            data.content = match artifact {
+                Some(artifact) if artifact.success => artifact.code,
+                Some(artifact) => artifact.stderr,
+                None => "Not compiled yet".into(),
+            };
+        }
         _ => {
-            data.title = "Error";
+            data.title = "Error".into();
             data.content = revision.playground_error;
         }
     }
+    if !revision.result_backend.is_empty()
+        && matches!(data.state, EvalPageState::Output | EvalPageState::Build)
+    {
+        data.title = format!("{} · {}", data.title, revision.result_backend).into();
+    }
     data
 }
+
+fn artifact_title(kind: PlaygroundArtifactKind, success: Option<bool>) -> &'static str {
+    match (kind, success) {
+        (PlaygroundArtifactKind::Asm, Some(false)) => "Assembly (failed)",
+        (PlaygroundArtifactKind::Asm, _) => "Assembly",
+        (PlaygroundArtifactKind::LlvmIr, Some(false)) => "LLVM IR (failed)",
+        (PlaygroundArtifactKind::LlvmIr, _) => "LLVM IR",
+        (PlaygroundArtifactKind::Mir, Some(false)) => "MIR (failed)",
+        (PlaygroundArtifactKind::Mir, _) => "MIR",
+        (PlaygroundArtifactKind::Hir, Some(false)) => "HIR (failed)",
+        (PlaygroundArtifactKind::Hir, _) => "HIR",
+        (PlaygroundArtifactKind::Wasm, Some(false)) => "WebAssembly (failed)",
+        (PlaygroundArtifactKind::Wasm, _) => "WebAssembly",
+        (PlaygroundArtifactKind::MacroExpansion, Some(false)) => "Macro expansion (failed)",
+        (PlaygroundArtifactKind::MacroExpansion, _) => "Macro expansion",
+    }
+}
@@ -0,0 +1,93 @@
+use chrono::{Duration, Utc};
+use tracing::error;
+
+use super::*;
+use crate::repository::playground_record::{
+    PendingPlaygroundRevision, PlaygroundRecordPageState, PlaygroundRevisionStatus,
+};
+use wait_eval::EvalProcessingResponseImpl;
+
+/// Older evals are not worth re-running, people have moved on by then.
+const RESUME_MAX_AGE_HOURS: i64 = 1;
+
+impl<R: IPlaygroundRecordRepository, P: IPlaygrounService> Controller<R, P>
+where
+    Self: Clone,
+{
+    pub(super) async fn pending_evals(
+        &self,
+    ) -> Vec<PendingEval<EvalProcessingResponse<EvalProcessingResponseImpl<R, P>>>> {
+        let pending = match self.repo.get_pending_revisions().await {
+            Ok(pending) => pending,
+            Err(e) => {
+                error!("Failed to get pending revisions: {}", e);
+                return vec![];
+            }
+        };
+        let cutoff = Utc::now() - Duration::hours(RESUME_MAX_AGE_HOURS);
+        let mut res = vec![];
+        for pending in pending {
+            let revision_id = pending.revision.revision_id;
+            match pending.eval_msg_id {
+                Some(eval_msg_id) if pending.is_latest && pending.created_at > cutoff => {
+                    res.push(self.resume_eval(pending, eval_msg_id));
+                    continue;
+                }
+                Some(eval_msg_id) if pending.is_latest => res.push(PendingEval::Abandoned {
+                    chat_id: pending.chat_id,
+                    eval_msg_id,
+                }),
+                // Superseded by a newer revision or deleted, nobody is waiting for it
+                _ => {}
+            }
+            if let Err(e) = self
+                .repo
+                .update_status_for_revision_id(revision_id, PlaygroundRevisionStatus::Abandoned)
+                .await
+            {
+                error!("Failed to abandon revision: {}", e);
+            }
+        }
+        res
+    }
+
+    fn resume_eval(
+        &self,
+        pending: PendingPlaygroundRevision,
+        eval_msg_id: i64,
+    ) -> PendingEval<EvalProcessingResponse<EvalProcessingResponseImpl<R, P>>> {
+        let revision = pending.revision;
+        // Whether the code was wrapped is already baked into `rendered_code`
+        let kind = match pending.page_state {
+            PlaygroundRecordPageState::Miri => EvalKind::Miri,
+            PlaygroundRecordPageState::Clippy => EvalKind::Clippy,
+            PlaygroundRecordPageState::Format => EvalKind::Format,
+            PlaygroundRecordPageState::Artifact(kind) => EvalKind::Artifact(kind.into()),
+            PlaygroundRecordPageState::Output | PlaygroundRecordPageState::Stderr
+                if revision.tests =>
+            {
+                EvalKind::Test
+            }
+            PlaygroundRecordPageState::Output | PlaygroundRecordPageState::Stderr => EvalKind::Eval,
+        };
+        PendingEval::Resume {
+            chat_id: pending.chat_id,
+            eval_msg_id,
+            processing: EvalProcessingResponse {
+                eval_msg_id: Some(eval_msg_id),
+                imp: EvalProcessingResponseImpl {
+                    user_code: revision.user_code,
+                    rendered_code: revision.rendered_code,
+                    kind,
+                    toolchain: revision.toolchain,
+                    controller: self.clone(),
+                    upsert_result: CreateRevisionUpsertRecordResult {
+                        revision_id: revision.revision_id,
+                        eval_msg_id: Some(eval_msg_id),
+                        page_state: pending.page_state,
+                    },
+                },
+            },
+        }
+    }
+}
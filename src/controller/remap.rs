@@ -0,0 +1,310 @@
+use super::wrap::{wrapped_segments, Segment};
+
+const MAIN_RS: &str = "src/main.rs";
+
+/// Rewrites rustc and panic locations in `stderr` so that they point into the code the user
+/// wrote, hiding source lines and locations that only belong to the wrapper.
+pub(super) fn remap_stderr(user_code: &str, rendered_code: &str, stderr: String) -> String {
+    let Some(source_map) = SourceMap::new(user_code, rendered_code) else {
+        return stderr;
+    };
+
+    let mut remapped = String::with_capacity(stderr.len());
+    let mut hiding_snippet = false;
+    // Set for a diagnostic pointing into the wrapper, none of its snippet is worth showing
+    let mut hiding_diagnostic = false;
+    let mut last_empty_gutter = false;
+    for line in stderr.split_inclusive('\n') {
+        if let Some(location) = line.trim_start().strip_prefix("--> ") {
+            hiding_diagnostic = location
+                .strip_prefix(MAIN_RS)
+                .and_then(parse_location)
+                .is_some_and(|(line_no, column, _)| {
+                    source_map.map_position(line_no, column).is_none()
+                });
+            if hiding_diagnostic {
+                continue;
+            }
+        }
+        let gutter = parse_gutter(line);
+        if hiding_diagnostic && gutter.is_some() {
+            continue;
+        }
+        hiding_diagnostic = false;
+        let is_empty_gutter =
+            matches!(gutter, Some((None, _, rest)) if rest[1..].trim().is_empty());
+        let line = match gutter {
+            Some((Some(line_no), width, rest)) => match source_map.map_line(line_no) {
+                Some(user_line_no) => {
+                    hiding_snippet = false;
+                    format!("{user_line_no:<width$}{rest}")
+                }
+                None => {
+                    hiding_snippet = true;
+                    continue;
+                }
+            },
+            Some((None, ..)) => {
+                // Annotations of hidden lines go with them, and the gaps they leave are merged
+                if hiding_snippet && !is_empty_gutter || is_empty_gutter && last_empty_gutter {
+                    continue;
+                }
+                hiding_snippet = false;
+                line.to_owned()
+            }
+            None => {
+                hiding_snippet = false;
+                line.to_owned()
+            }
+        };
+        last_empty_gutter = is_empty_gutter;
+        remap_locations(&mut remapped, &line, &source_map);
+    }
+    remapped
+}
+
+/// Whether a rustc location in the rendered code points into the wrapper rather than at anything
+/// the user wrote.
+pub(super) fn is_wrapper_location(
+    user_code: &str,
+    rendered_code: &str,
+    file: &str,
+    line_no: usize,
+    column: usize,
+) -> bool {
+    file == MAIN_RS
+        && SourceMap::new(user_code, rendered_code)
+            .is_some_and(|source_map| source_map.map_position(line_no, column).is_none())
+}
+
+/// Splits `12  | code` into the line number, the width of the number column and the rest of the
+/// line starting at `|`. Annotation lines such as `   |   ^^^` have no line number.
+fn parse_gutter(line: &str) -> Option<(Option<usize>, usize, &str)> {
+    let pipe = line.find('|')?;
+    let (head, rest) = line.split_at(pipe);
+    let digits = head.trim_end();
+    if digits.is_empty() {
+        return (!head.is_empty()).then_some((None, head.len(), rest));
+    }
+    if head.len() == digits.len() || !digits.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    Some((Some(digits.parse().ok()?), head.len(), rest))
+}
+
+fn remap_locations(out: &mut String, mut line: &str, source_map: &SourceMap<'_>) {
+    while let Some(idx) = line.find(MAIN_RS) {
+        let (before, after) = line.split_at(idx + MAIN_RS.len());
+        out.push_str(before);
+        line = after;
+        let Some((line_no, column, rem)) = parse_location(after) else {
+            continue;
+        };
+        if let Some((user_line_no, user_column)) = source_map.map_position(line_no, column) {
+            out.push_str(&format!(":{user_line_no}:{user_column}"));
+        }
+        line = rem;
+    }
+    out.push_str(line);
+}
+
+fn parse_location(s: &str) -> Option<(usize, usize, &str)> {
+    fn parse_number(s: &str) -> Option<(usize, &str)> {
+        let s = s.strip_prefix(':')?;
+        let end = s.find(|c: char| !c.is_ascii_digit()).unwrap_or(s.len());
+        Some((s[..end].parse().ok()?, &s[end..]))
+    }
+    let (line_no, s) = parse_number(s)?;
+    let (column, s) = parse_number(s)?;
+    Some((line_no, column, s))
+}
+
+struct LineIndex<'a> {
+    text: &'a str,
+    line_starts: Vec<usize>,
+}
+
+impl<'a> LineIndex<'a> {
+    fn new(text: &'a str) -> Self {
+        let line_starts = std::iter::once(0)
+            .chain(text.match_indices('\n').map(|(idx, _)| idx + 1))
+            .collect();
+        Self { text, line_starts }
+    }
+
+    /// Byte range of a 1-based line, including its trailing newline.
+    fn line_range(&self, line_no: usize) -> Option<(usize, usize)> {
+        let start = *self.line_starts.get(line_no.checked_sub(1)?)?;
+        let end = self
+            .line_starts
+            .get(line_no)
+            .copied()
+            .unwrap_or(self.text.len());
+        Some((start, end))
+    }
+
+    fn offset(&self, line_no: usize, column: usize) -> Option<usize> {
+        let (start, end) = self.line_range(line_no)?;
+        let column_len: usize = self.text[start..end]
+            .chars()
+            .take(column.checked_sub(1)?)
+            .map(char::len_utf8)
+            .sum();
+        Some(start + column_len)
+    }
+
+    fn position(&self, offset: usize) -> (usize, usize) {
+        let line_idx = self.line_starts.partition_point(|&start| start <= offset) - 1;
+        let column = self.text[self.line_starts[line_idx]..offset]
+            .chars()
+            .count();
+        (line_idx + 1, column + 1)
+    }
+}
+
+struct SourceMap<'a> {
+    user: LineIndex<'a>,
+    rendered: LineIndex<'a>,
+    segments: Vec<Segment>,
+}
+
+impl<'a> SourceMap<'a> {
+    fn new(user_code: &'a str, rendered_code: &'a str) -> Option<Self> {
+        if user_code.is_empty() {
+            return None;
+        }
+        Some(Self {
+            user: LineIndex::new(user_code),
+            rendered: LineIndex::new(rendered_code),
+            segments: wrapped_segments(user_code, rendered_code)?,
+        })
+    }
+
+    fn user_offset(&self, rendered_offset: usize) -> Option<usize> {
+        self.segments
+            .iter()
+            .find(|s| (s.rendered..s.rendered + s.len).contains(&rendered_offset))
+            .map(|s| s.user + rendered_offset - s.rendered)
+    }
+
+    fn map_position(&self, line_no: usize, column: usize) -> Option<(usize, usize)> {
+        let offset = self.rendered.offset(line_no, column)?;
+        Some(self.user.position(self.user_offset(offset)?))
+    }
+
+    /// Maps a rendered line to the user line it mostly shows, preferring the first non-blank
+    /// character that came from the user.
+    fn map_line(&self, line_no: usize) -> Option<usize> {
+        let (start, end) = self.rendered.line_range(line_no)?;
+        let mut first_user_offset = None;
+        for (idx, c) in self.rendered.text[start..end].char_indices() {
+            let Some(user_offset) = self.user_offset(start + idx) else {
+                continue;
+            };
+            if !c.is_whitespace() {
+                return Some(self.user.position(user_offset).0);
+            }
+            first_user_offset.get_or_insert(user_offset);
+        }
+        first_user_offset.map(|offset| self.user.position(offset).0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::controller::{wrap::render_code, EvalKind};
+
+    #[test]
+    fn test_remap_stderr() {
+        let code = "use std::fmt;\nlet a: u8 = \"\";\na";
+        let rendered = render_code(EvalKind::Eval, code);
+        assert_eq!(rendered.lines().nth(4), Some("let a: u8 = \"\";"));
+        let stderr = "   Compiling playground v0.0.1 (/playground)
+error[E0308]: mismatched types
+ --> src/main.rs:5:13
+  |
+5 | let a: u8 = \"\";
+  |        --   ^^ expected `u8`, found `&str`
+  |        |
+  |        expected due to this
+
+error[E0277]: `u8` doesn't implement `Foo`
+ --> src/main.rs:7:20
+  |
+7 | }; println!(\"{res:?}\"); }
+  |                    ^^^^^ `u8` cannot be formatted
+  |
+";
+        assert_eq!(
+            remap_stderr(code, &rendered, stderr.into()),
+            "   Compiling playground v0.0.1 (/playground)
+error[E0308]: mismatched types
+ --> src/main.rs:2:13
+  |
+2 | let a: u8 = \"\";
+  |        --   ^^ expected `u8`, found `&str`
+  |        |
+  |        expected due to this
+
+error[E0277]: `u8` doesn't implement `Foo`
+"
+        );
+    }
+
+    #[test]
+    fn test_remap_stderr_hidden_lines() {
+        let code = "struct A;\nA";
+        let rendered = render_code(EvalKind::Eval, code);
+        assert_eq!(rendered.lines().nth(5), Some("}; println!(\"{res:?}\"); }"));
+        let stderr = "error[E0277]: `A` doesn't implement `Debug`
+ --> src/main.rs:5:1
+  |
+5 | A
+  | ^ `A` cannot be formatted
+6 | }; println!(\"{res:?}\"); }
+  |              ----- required by this formatting parameter
+  |
+  = help: the trait `Debug` is not implemented for `A`
+";
+        assert_eq!(
+            remap_stderr(code, &rendered, stderr.into()),
+            "error[E0277]: `A` doesn't implement `Debug`
+ --> src/main.rs:2:1
+  |
+2 | A
+  | ^ `A` cannot be formatted
+  |
+  = help: the trait `Debug` is not implemented for `A`
+"
+        );
+    }
+
+    #[test]
+    fn test_is_wrapper_location() {
+        let code = "struct A;\nA";
+        let rendered = render_code(EvalKind::Eval, code);
+        assert!(!is_wrapper_location(code, &rendered, MAIN_RS, 5, 1));
+        assert!(is_wrapper_location(code, &rendered, MAIN_RS, 6, 14));
+        assert!(!is_wrapper_location(code, &rendered, "src/lib.rs", 6, 14));
+        assert!(!is_wrapper_location(code, code, MAIN_RS, 6, 14));
+    }
+
+    #[test]
+    fn test_remap_stderr_verbatim() {
+        let code = "fn main() { let a: u8 = \"\"; }";
+        let stderr = " --> src/main.rs:1:25\n".to_string();
+        assert_eq!(remap_stderr(code, code, stderr.clone()), stderr);
+    }
+
+    #[test]
+    fn test_remap_panic_location() {
+        let code = "let v: Vec<u8> = vec![];\nv[1]";
+        let rendered = render_code(EvalKind::Eval, code);
+        let stderr = "thread 'main' panicked at src/main.rs:5:1:\nindex out of bounds\n";
+        assert_eq!(
+            remap_stderr(code, &rendered, stderr.into()),
+            "thread 'main' panicked at src/main.rs:2:1:\nindex out of bounds\n"
+        );
+    }
+}
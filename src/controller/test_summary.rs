@@ -0,0 +1,111 @@
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub(super) struct TestSummary<'a> {
+    pub(super) passed: u32,
+    pub(super) failed: u32,
+    pub(super) ignored: u32,
+    /// The captured output of each failing test.
+    pub(super) failures: Vec<&'a str>,
+}
+
+impl TestSummary<'_> {
+    pub(super) fn title(&self) -> String {
+        let mut title = format!("Tests: {} passed, {} failed", self.passed, self.failed);
+        if self.ignored > 0 {
+            title.push_str(&format!(", {} ignored", self.ignored));
+        }
+        title
+    }
+}
+
+/// Parses libtest's human readable output. Returns `None` if no test binary finished.
+pub(super) fn parse_test_summary(stdout: &str) -> Option<TestSummary<'_>> {
+    let mut summary = TestSummary::default();
+    let mut finished = false;
+    let mut failure_start = None;
+    let mut offset = 0;
+    for line in stdout.split_inclusive('\n') {
+        let line_start = offset;
+        offset += line.len();
+        let line = line.trim_end();
+        let is_failure_header = line.starts_with("---- ") && line.ends_with(" ----");
+        // A failure section runs until the next one or the `failures:` list that follows them
+        if is_failure_header || line == "failures:" {
+            if let Some(start) = failure_start.take() {
+                summary.failures.push(stdout[start..line_start].trim_end());
+            }
+        }
+        if is_failure_header {
+            failure_start = Some(line_start);
+        } else if let Some(result) = line.strip_prefix("test result: ") {
+            finished = true;
+            for part in result.split(['.', ';']) {
+                let mut words = part.split_whitespace();
+                let (Some(count), Some(label)) = (words.next(), words.next()) else {
+                    continue;
+                };
+                let Ok(count) = count.parse::<u32>() else {
+                    continue;
+                };
+                match label {
+                    "passed" => summary.passed += count,
+                    "failed" => summary.failed += count,
+                    "ignored" => summary.ignored += count,
+                    _ => {}
+                }
+            }
+        }
+    }
+    if let Some(start) = failure_start {
+        summary.failures.push(stdout[start..].trim_end());
+    }
+    finished.then_some(summary)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_test_summary() {
+        let stdout = "
+running 4 tests
+test tests::a ... ok
+test tests::b ... FAILED
+test tests::c ... ignored
+test tests::d ... ok
+
+failures:
+
+---- tests::b stdout ----
+
+thread 'tests::b' panicked at src/main.rs:9:9:
+assertion `left == right` failed
+  left: 1
+ right: 2
+note: run with `RUST_BACKTRACE=1` environment variable to display a backtrace
+
+
+failures:
+    tests::b
+
+test result: FAILED. 2 passed; 1 failed; 1 ignored; 0 measured; 0 filtered out; finished in 0.00s
+
+";
+        let summary = parse_test_summary(stdout).unwrap();
+        assert_eq!((summary.passed, summary.failed, summary.ignored), (2, 1, 1));
+        assert_eq!(summary.title(), "Tests: 2 passed, 1 failed, 1 ignored");
+        assert_eq!(summary.failures.len(), 1);
+        assert!(summary.failures[0].starts_with("---- tests::b stdout ----"));
+        assert!(summary.failures[0].ends_with("display a backtrace"));
+    }
+
+    #[test]
+    fn test_parse_test_summary_unfinished() {
+        assert_eq!(parse_test_summary("\nrunning 1 test\n"), None);
+        let summary = parse_test_summary(
+            "test result: ok. 3 passed; 0 failed; 0 ignored; 0 measured; 0 filtered out; finished in 0.00s\n",
+        )
+        .unwrap();
+        assert_eq!(summary.title(), "Tests: 3 passed, 0 failed");
+    }
+}
@@ -0,0 +1,412 @@
+use std::time::Duration;
+
+use event_listener::Event;
+use futures::future::join;
+
+use super::*;
+use crate::{
+    service::playground::{PlaygroundCompileResult, PlaygroundFormatResult},
+    testing::{MemoryRepository, MockPlayground, MockResponse},
+};
+
+const CHAT_ID: i64 = -100;
+const USER_ID: i64 = 42;
+const OTHER_USER_ID: i64 = 43;
+const EVAL_MSG_ID: i64 = 1000;
+
+type TestController = Controller<MemoryRepository, MockPlayground>;
+type Processing =
+    EvalProcessingResponse<wait_eval::EvalProcessingResponseImpl<MemoryRepository, MockPlayground>>;
+
+fn controller() -> (TestController, MockPlayground) {
+    let playground = MockPlayground::default();
+    let controller = Controller::new(
+        MemoryRepository::default(),
+        playground.clone(),
+        PlaygroundRustToolchain::default(),
+    );
+    (controller, playground)
+}
+
+async fn new_eval(
+    controller: &TestController,
+    user_msg_id: i64,
+    kind: EvalKind,
+    code: &str,
+) -> Processing {
+    match controller
+        .new_eval(CHAT_ID, user_msg_id, USER_ID, kind, code.into())
+        .await
+    {
+        EvalResponse::Processing(processing) => processing,
+        EvalResponse::Err(e) => panic!("new_eval failed: {e}"),
+    }
+}
+
+async fn wait(processing: impl WaitForEvalResult) -> EvalResultResponse {
+    let cancel_event = Event::new();
+    processing.wait_for_eval_result(cancel_event.listen()).await
+}
+
+/// Runs an eval to the end as the handler does, with its result sent as `EVAL_MSG_ID`.
+async fn eval(
+    controller: &TestController,
+    user_msg_id: i64,
+    kind: EvalKind,
+    code: &str,
+) -> EvalPageData {
+    let processing = new_eval(controller, user_msg_id, kind, code).await;
+    processing.update_eval_msg_id(EVAL_MSG_ID).await;
+    match wait(processing).await {
+        EvalResultResponse::Ok(data) => data,
+        res => panic!("eval failed: {res:?}"),
+    }
+}
+
+#[compio::test]
+async fn test_new_eval() {
+    let (controller, playground) = controller();
+    playground.respond("run_code", MockResponse::stdout("hi\n"));
+    let processing = new_eval(&controller, 1, EvalKind::Eval, "println!(\"hi\");").await;
+    assert_eq!(processing.eval_msg_id, None);
+    processing.update_eval_msg_id(EVAL_MSG_ID).await;
+    let EvalResultResponse::Ok(data) = wait(processing).await else {
+        panic!("eval failed");
+    };
+    assert_eq!(data.state, EvalPageState::Output);
+    assert_eq!(data.title, "Output");
+    assert_eq!(data.content, "hi\n");
+    assert_eq!(data.revision, 1);
+    assert!(!data.has_error);
+    let calls = playground.calls("run_code");
+    assert_eq!(calls.len(), 1);
+    assert!(calls[0].contains("fn main()"));
+    assert!(calls[0].contains("println!(\"hi\");"));
+}
+
+#[compio::test]
+async fn test_backend_in_title() {
+    let (controller, playground) = controller();
+    let MockResponse::Execute(mut res) = MockResponse::stdout("hi\n") else {
+        unreachable!();
+    };
+    res.result_backend = "mirror".into();
+    playground.respond("run_code", MockResponse::Execute(res));
+    let data = eval(&controller, 1, EvalKind::Eval, "println!(\"hi\");").await;
+    assert_eq!(data.title, "Output · mirror");
+    let res = controller
+        .switch_eval_state(
+            EVAL_MSG_ID,
+            USER_ID,
+            data.revision_id,
+            EvalPageState::Build,
+            0,
+        )
+        .await;
+    assert!(matches!(res, ShowEvalOutputResponse::Ok(data) if data.title == "Stderr · mirror"));
+    let res = controller
+        .switch_eval_state(
+            EVAL_MSG_ID,
+            USER_ID,
+            data.revision_id,
+            EvalPageState::Clippy,
+            0,
+        )
+        .await;
+    assert!(matches!(res, ShowEvalOutputResponse::Ok(data) if data.title == "Clippy"));
+}
+
+#[compio::test]
+async fn test_edit_keeps_eval_message() {
+    let (controller, playground) = controller();
+    playground.respond("run_code", MockResponse::stdout("1\n"));
+    playground.respond("run_code", MockResponse::stdout("2\n"));
+    eval(&controller, 1, EvalKind::Eval, "println!(\"1\");").await;
+    let processing = new_eval(&controller, 1, EvalKind::Eval, "println!(\"2\");").await;
+    assert_eq!(processing.eval_msg_id, Some(EVAL_MSG_ID));
+    let EvalResultResponse::Ok(data) = wait(processing).await else {
+        panic!("eval failed");
+    };
+    assert_eq!(data.content, "2\n");
+    assert_eq!(data.revision, 2);
+}
+
+#[compio::test]
+async fn test_unknown_flag() {
+    let (controller, playground) = controller();
+    let res = controller
+        .new_eval(CHAT_ID, 1, USER_ID, EvalKind::Eval, "+2027 1".into())
+        .await;
+    assert!(matches!(res, EvalResponse::Err(e) if e == "Unknown flag: +2027"));
+    assert!(playground.calls("run_code").is_empty());
+}
+
+#[compio::test]
+async fn test_request_outdated() {
+    let (controller, playground) = controller();
+    playground.respond("run_code", MockResponse::stdout("1\n"));
+    playground.respond("run_code", MockResponse::stdout("2\n"));
+    let first = new_eval(&controller, 1, EvalKind::Eval, "println!(\"1\");").await;
+    let second = new_eval(&controller, 1, EvalKind::Eval, "println!(\"2\");").await;
+    assert_eq!(wait(first).await, EvalResultResponse::RequestOutdated);
+    let EvalResultResponse::Ok(data) = wait(second).await else {
+        panic!("eval failed");
+    };
+    assert_eq!(data.content, "2\n");
+}
+
+#[compio::test]
+async fn test_request_outdated_while_running() {
+    let (controller, playground) = controller();
+    playground.respond_after(
+        "run_code",
+        Duration::from_millis(50),
+        MockResponse::stdout("1\n"),
+    );
+    playground.respond("run_code", MockResponse::stdout("2\n"));
+    let first = new_eval(&controller, 1, EvalKind::Eval, "println!(\"1\");").await;
+    // The edit arrives while the first run is still going
+    let (first_res, second_res) = join(wait(first), async {
+        let second = new_eval(&controller, 1, EvalKind::Eval, "println!(\"2\");").await;
+        wait(second).await
+    })
+    .await;
+    assert_eq!(first_res, EvalResultResponse::RequestOutdated);
+    assert!(matches!(second_res, EvalResultResponse::Ok(data) if data.content == "2\n"));
+}
+
+#[compio::test]
+async fn test_cancelled() {
+    let (controller, playground) = controller();
+    playground.respond_after(
+        "run_code",
+        Duration::from_secs(60),
+        MockResponse::stdout("1\n"),
+    );
+    let processing = new_eval(&controller, 1, EvalKind::Eval, "1").await;
+    let cancel_event = Event::new();
+    let listener = cancel_event.listen();
+    let (res, _) = join(processing.wait_for_eval_result(listener), async {
+        cancel_event.notify(usize::MAX);
+    })
+    .await;
+    assert_eq!(res, EvalResultResponse::Cancelled);
+}
+
+#[compio::test]
+async fn test_playground_error() {
+    let (controller, playground) = controller();
+    playground.respond("run_code", MockResponse::Timeout);
+    playground.respond("run_code", MockResponse::Malformed);
+    let data = eval(&controller, 1, EvalKind::Eval, "1").await;
+    assert!(data.has_fatal_error);
+    assert_eq!(data.title, "Error");
+    assert_eq!(data.content, "timeout");
+    let data = eval(&controller, 2, EvalKind::Eval, "1").await;
+    assert_eq!(data.content, "invalid response");
+}
+
+#[compio::test]
+async fn test_tool_evals() {
+    let (controller, playground) = controller();
+    playground.respond("run_code", MockResponse::stdout(""));
+    playground.respond(
+        "format",
+        MockResponse::Format(PlaygroundFormatResult {
+            success: true,
+            code: "fn main() {}\n".into(),
+            stderr: "".into(),
+        }),
+    );
+    let data = eval(&controller, 1, EvalKind::Format, "fn main(){}").await;
+    assert_eq!(data.state, EvalPageState::Format);
+    assert_eq!(data.title, "Formatted");
+    assert_eq!(data.content, "fn main() {}\n");
+
+    playground.respond("run_code", MockResponse::stdout(""));
+    playground.respond(
+        "compile",
+        MockResponse::Compile(PlaygroundCompileResult {
+            success: true,
+            exit_detail: "".into(),
+            code: "square:\n\tret".into(),
+            stderr: "".into(),
+        }),
+    );
+    let data = eval(
+        &controller,
+        2,
+        EvalKind::Artifact(EvalArtifact::Asm),
+        "pub fn square() {}",
+    )
+    .await;
+    assert_eq!(data.state, EvalPageState::Artifact(EvalArtifact::Asm));
+    assert_eq!(data.artifacts, [EvalArtifact::Asm]);
+    assert_eq!(data.content, "square:\n\tret");
+}
+
+#[compio::test]
+async fn test_switch_state() {
+    let (controller, playground) = controller();
+    playground.respond("run_code", MockResponse::stdout("1\n"));
+    let data = eval(&controller, 1, EvalKind::Eval, "1").await;
+    let res = controller
+        .switch_eval_state(
+            EVAL_MSG_ID,
+            USER_ID,
+            data.revision_id,
+            EvalPageState::Build,
+            0,
+        )
+        .await;
+    assert!(matches!(res, ShowEvalOutputResponse::Ok(data) if data.state == EvalPageState::Build));
+    let res = controller
+        .switch_eval_state(
+            EVAL_MSG_ID,
+            OTHER_USER_ID,
+            data.revision_id,
+            EvalPageState::Output,
+            0,
+        )
+        .await;
+    assert!(matches!(res, ShowEvalOutputResponse::SenderMismatch));
+    let res = controller
+        .switch_eval_state(
+            EVAL_MSG_ID + 1,
+            USER_ID,
+            data.revision_id,
+            EvalPageState::Output,
+            0,
+        )
+        .await;
+    assert!(matches!(res, ShowEvalOutputResponse::SenderMismatch));
+
+    // Buttons of a superseded revision stop working
+    playground.respond("run_code", MockResponse::stdout("2\n"));
+    eval(&controller, 1, EvalKind::Eval, "2").await;
+    let res = controller
+        .switch_eval_state(
+            EVAL_MSG_ID,
+            USER_ID,
+            data.revision_id,
+            EvalPageState::Output,
+            0,
+        )
+        .await;
+    assert!(matches!(res, ShowEvalOutputResponse::SenderMismatch));
+}
+
+#[compio::test]
+async fn test_lazy_miri() {
+    let (controller, playground) = controller();
+    playground.respond("run_code", MockResponse::stdout("1\n"));
+    playground.respond("run_miri", MockResponse::stdout("1\n"));
+    let data = eval(&controller, 1, EvalKind::Eval, "1").await;
+    let switch = || {
+        controller.switch_eval_state(
+            EVAL_MSG_ID,
+            USER_ID,
+            data.revision_id,
+            EvalPageState::Miri,
+            0,
+        )
+    };
+    // Miri runs once the handler waits for it
+    let ShowEvalOutputResponse::Processing(processing) = switch().await else {
+        panic!("Miri did not run lazily");
+    };
+    assert!(playground.calls("run_miri").is_empty());
+    let EvalResultResponse::Ok(miri) = wait(processing).await else {
+        panic!("Miri failed");
+    };
+    assert_eq!(miri.title, "Miri");
+    assert_eq!(miri.content, "1\n");
+    // The result is kept with the revision
+    let res = switch().await;
+    let ShowEvalOutputResponse::Ok(miri) = res else {
+        panic!("switch failed: {res:?}");
+    };
+    assert_eq!(miri.content, "1\n");
+    assert_eq!(playground.calls("run_miri").len(), 1);
+}
+
+#[compio::test]
+async fn test_lazy_tool_error() {
+    let (controller, playground) = controller();
+    playground.respond("run_code", MockResponse::stdout("1\n"));
+    playground.respond("format", MockResponse::Timeout);
+    let data = eval(&controller, 1, EvalKind::Eval, "1").await;
+    let res = controller
+        .switch_eval_state(
+            EVAL_MSG_ID,
+            USER_ID,
+            data.revision_id,
+            EvalPageState::Format,
+            0,
+        )
+        .await;
+    let ShowEvalOutputResponse::Processing(processing) = res else {
+        panic!("rustfmt did not run lazily");
+    };
+    let EvalResultResponse::Ok(page) = wait(processing).await else {
+        panic!("no page");
+    };
+    assert_eq!(page.title, "Error");
+    assert_eq!(page.content, "Error formatting code");
+    assert_eq!(page.revision_id, data.revision_id);
+}
+
+#[compio::test]
+async fn test_delete_and_revert() {
+    let (controller, playground) = controller();
+    playground.respond("run_code", MockResponse::stdout("1\n"));
+    let data = eval(&controller, 1, EvalKind::Eval, "1").await;
+    let res = controller
+        .request_delete_eval(EVAL_MSG_ID, OTHER_USER_ID, data.revision_id)
+        .await;
+    assert!(matches!(res, RequestDeleteEvalResponse::SenderMismatch));
+    let res = controller
+        .request_delete_eval(EVAL_MSG_ID, USER_ID, data.revision_id)
+        .await;
+    let RequestDeleteEvalResponse::Approved(revert) = res else {
+        panic!("delete not approved");
+    };
+    let res = controller
+        .request_delete_eval(EVAL_MSG_ID, USER_ID, data.revision_id)
+        .await;
+    assert!(matches!(res, RequestDeleteEvalResponse::SenderMismatch));
+
+    // Deleting the message failed, so the eval is back
+    revert.revert_delete_eval().await;
+    let res = controller
+        .request_delete_eval(EVAL_MSG_ID, USER_ID, data.revision_id)
+        .await;
+    assert!(matches!(res, RequestDeleteEvalResponse::Approved(_)));
+}
+
+#[compio::test]
+async fn test_get_eval_link() {
+    let (controller, playground) = controller();
+    let link = "https://play.rust-lang.org/?version=stable&mode=debug&edition=2021&gist=1";
+    playground.respond("run_code", MockResponse::stdout("1\n"));
+    playground.respond("generate_link", MockResponse::Timeout);
+    playground.respond("generate_link", MockResponse::Link(link.into()));
+    let data = eval(&controller, 1, EvalKind::Eval, "1").await;
+    assert_eq!(data.perma_link, None);
+    assert_eq!(
+        controller.get_eval_link(data.revision_id).await,
+        GetEvalLinkResponse::Err("Error generating link".into())
+    );
+    for _ in 0..2 {
+        let GetEvalLinkResponse::Ok(data) = controller.get_eval_link(data.revision_id).await else {
+            panic!("no link");
+        };
+        assert_eq!(data.perma_link.as_deref(), Some(link));
+        assert_eq!(data.state, EvalPageState::Output);
+    }
+    assert_eq!(playground.calls("generate_link").len(), 2);
+    assert_eq!(
+        controller.get_eval_link(data.revision_id + 1).await,
+        GetEvalLinkResponse::NotFound
+    );
+}
@@ -0,0 +1,115 @@
+use crate::repository::playground_record::{
+    PlaygroundRustChannel, PlaygroundRustEdition, PlaygroundRustProfile, PlaygroundRustToolchain,
+};
+
+/// Applies leading `+flag`s on top of `default`, returning the rest of the code. Only words
+/// followed by whitespace or the end of the message count as flags, anything else starting
+/// with `+` is left as code.
+pub(super) fn parse_toolchain_flags(
+    code: &str,
+    default: PlaygroundRustToolchain,
+) -> Result<(PlaygroundRustToolchain, &str), &str> {
+    let mut toolchain = default;
+    let mut rem = code.trim_start();
+    while let Some(flags) = rem.strip_prefix('+') {
+        let (flag, next) = flags.split_once(char::is_whitespace).unwrap_or((flags, ""));
+        if flag.is_empty() || !flag.chars().all(|c| c.is_ascii_alphanumeric()) {
+            break;
+        }
+        match flag {
+            "stable" => toolchain.channel = PlaygroundRustChannel::Stable,
+            "beta" => toolchain.channel = PlaygroundRustChannel::Beta,
+            "nightly" => toolchain.channel = PlaygroundRustChannel::Nightly,
+            "debug" => toolchain.profile = PlaygroundRustProfile::Debug,
+            "release" => toolchain.profile = PlaygroundRustProfile::Release,
+            "2015" => toolchain.edition = PlaygroundRustEdition::Rust2015,
+            "2018" => toolchain.edition = PlaygroundRustEdition::Rust2018,
+            "2021" => toolchain.edition = PlaygroundRustEdition::Rust2021,
+            "2024" => toolchain.edition = PlaygroundRustEdition::Rust2024,
+            // A number is more likely code than a mistyped edition
+            _ if flag.starts_with(|c: char| c.is_ascii_digit()) => break,
+            // So is an unknown word ending the message, such as `+x`
+            _ if next.is_empty() => break,
+            _ => return Err(flag),
+        }
+        rem = next.trim_start();
+    }
+    Ok((toolchain, rem))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_no_flags() {
+        let (toolchain, code) =
+            parse_toolchain_flags("1 + 1", PlaygroundRustToolchain::default()).unwrap();
+        assert_eq!(toolchain, PlaygroundRustToolchain::default());
+        assert_eq!(code, "1 + 1");
+    }
+
+    #[test]
+    fn test_flags() {
+        let (toolchain, code) = parse_toolchain_flags(
+            "+nightly +release\n+2024 1 + 1",
+            PlaygroundRustToolchain::default(),
+        )
+        .unwrap();
+        assert_eq!(
+            toolchain,
+            PlaygroundRustToolchain {
+                edition: PlaygroundRustEdition::Rust2024,
+                channel: PlaygroundRustChannel::Nightly,
+                profile: PlaygroundRustProfile::Release,
+            }
+        );
+        assert_eq!(code, "1 + 1");
+    }
+
+    #[test]
+    fn test_flags_only() {
+        let (toolchain, code) =
+            parse_toolchain_flags("+nightly", PlaygroundRustToolchain::default()).unwrap();
+        assert_eq!(toolchain.channel, PlaygroundRustChannel::Nightly);
+        assert_eq!(code, "");
+        let (toolchain, code) =
+            parse_toolchain_flags("+release +2018", PlaygroundRustToolchain::default()).unwrap();
+        assert_eq!(toolchain.profile, PlaygroundRustProfile::Release);
+        assert_eq!(toolchain.edition, PlaygroundRustEdition::Rust2018);
+        assert_eq!(code, "");
+    }
+
+    #[test]
+    fn test_default_toolchain() {
+        let default = PlaygroundRustToolchain {
+            channel: PlaygroundRustChannel::Nightly,
+            ..Default::default()
+        };
+        let (toolchain, _) = parse_toolchain_flags("+2024 1", default).unwrap();
+        assert_eq!(toolchain.channel, PlaygroundRustChannel::Nightly);
+        assert_eq!(toolchain.edition, PlaygroundRustEdition::Rust2024);
+    }
+
+    #[test]
+    fn test_unknown_flag() {
+        assert_eq!(
+            parse_toolchain_flags("+beta +nightyl 1", PlaygroundRustToolchain::default()),
+            Err("nightyl")
+        );
+    }
+
+    #[test]
+    fn test_plus_code() {
+        for code in ["+1", "+1 + 2", "+x", "+(1)", "+ 1", "+a_b 1"] {
+            let (toolchain, rem) =
+                parse_toolchain_flags(code, PlaygroundRustToolchain::default()).unwrap();
+            assert_eq!(toolchain, PlaygroundRustToolchain::default());
+            assert_eq!(rem, code);
+        }
+        let (toolchain, rem) =
+            parse_toolchain_flags("+release +1", PlaygroundRustToolchain::default()).unwrap();
+        assert_eq!(toolchain.profile, PlaygroundRustProfile::Release);
+        assert_eq!(rem, "+1");
+    }
+}
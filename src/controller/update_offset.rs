@@ -0,0 +1,31 @@
+use tracing::error;
+
+use super::*;
+
+impl<R: IBotUpdateRepository, P> Controller<R, P> {
+    pub(super) async fn update_offset(&self) -> Option<i64> {
+        match self.repo.get_update_offset().await {
+            Ok(offset) => offset,
+            Err(e) => {
+                error!("Failed to get update offset: {}", e);
+                None
+            }
+        }
+    }
+
+    pub(super) async fn is_update_handled(&self, update_id: i64) -> bool {
+        match self.repo.is_update_handled(update_id).await {
+            Ok(handled) => handled,
+            Err(e) => {
+                error!("Failed to check handled update: {}", e);
+                false
+            }
+        }
+    }
+
+    pub(super) async fn mark_update_handled(&self, update_id: i64) {
+        if let Err(e) = self.repo.mark_update_handled(update_id).await {
+            error!("Failed to mark update handled: {}", e);
+        }
+    }
+}
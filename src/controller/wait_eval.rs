@@ -1,23 +1,52 @@
 use std::pin::pin;
 
-use futures::future::{select, Either};
+use futures::future::{join, select, Either};
 use tracing::error;
 
 use super::*;
-use crate::repository::playground_record::{PlaygroundRecordPageState, PlaygroundRecordRevision};
+use crate::{
+    repository::{
+        playground_artifact::{PlaygroundArtifact, PlaygroundArtifactKind},
+        playground_record::{
+            PlaygroundRecordPageState, PlaygroundRecordRevision, PlaygroundRustChannel,
+            PlaygroundRustToolchain,
+        },
+    },
+    service::playground::{
+        PlaygroundCompileResult, PlaygroundExecuteResult, PlaygroundFormatResult, PlaygroundResult,
+    },
+};
+use diagnostics::{count_diagnostics, parse_diagnostics};
 use page_data::build_page_data;
+use remap::{is_wrapper_location, remap_stderr};
+use wrap::{render_format_code, unwrap_formatted_code};
 
 #[derive(Clone)]
 pub struct EvalProcessingResponseImpl<R, P> {
+    pub(super) user_code: String,
     pub(super) rendered_code: String,
+    pub(super) kind: EvalKind,
+    pub(super) toolchain: PlaygroundRustToolchain,
     pub(super) controller: Controller<R, P>,
     pub(super) upsert_result: CreateRevisionUpsertRecordResult,
 }
 
+enum ToolResult {
+    Miri(PlaygroundResult<PlaygroundExecuteResult>),
+    Clippy(PlaygroundResult<PlaygroundExecuteResult>),
+    Format(PlaygroundResult<PlaygroundFormatResult>),
+    Artifact(
+        PlaygroundArtifactKind,
+        PlaygroundResult<PlaygroundCompileResult>,
+    ),
+}
+
 impl<R, P> Debug for EvalProcessingResponseImpl<R, P> {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         f.debug_struct("EvalProcessingResponse")
             .field("code", &self.rendered_code)
+            .field("kind", &self.kind)
+            .field("toolchain", &self.toolchain)
             .field("upsert_result", &self.upsert_result)
             .finish()
     }
@@ -27,12 +56,89 @@ impl<R: IPlaygroundRecordRepository, P: IPlaygrounService> WaitForEvalResult
     for EvalProcessingResponseImpl<R, P>
 {
     async fn wait_for_eval_result(self, cancel_event: EventListener) -> EvalResultResponse {
-        let Either::Left((res, _)) = select(
+        let Either::Left(((res, tool_res), _)) = select(
             pin!(async {
-                self.controller
-                    .playground
-                    .run_code(&self.rendered_code, "stable", "debug", "2021")
-                    .await
+                let playground = &self.controller.playground;
+                let run_code_fut = playground.run_code(
+                    &self.rendered_code,
+                    self.toolchain.channel.as_str(),
+                    self.toolchain.profile.as_str(),
+                    self.toolchain.edition.as_str(),
+                    self.kind == EvalKind::Test,
+                );
+                match self.kind {
+                    EvalKind::Eval | EvalKind::Run | EvalKind::Test => (run_code_fut.await, None),
+                    EvalKind::Miri => {
+                        let (res, miri_res) = join(
+                            run_code_fut,
+                            playground.run_miri(
+                                &self.rendered_code,
+                                self.toolchain.edition.as_str(),
+                                false,
+                            ),
+                        )
+                        .await;
+                        (res, Some(ToolResult::Miri(miri_res)))
+                    }
+                    EvalKind::Clippy => {
+                        let (res, clippy_res) = join(
+                            run_code_fut,
+                            playground.clippy(
+                                &self.rendered_code,
+                                self.toolchain.channel.as_str(),
+                                self.toolchain.edition.as_str(),
+                            ),
+                        )
+                        .await;
+                        (res, Some(ToolResult::Clippy(clippy_res)))
+                    }
+                    EvalKind::Format => {
+                        let format_code = render_format_code(&self.user_code, &self.rendered_code);
+                        let (res, format_res) = join(
+                            run_code_fut,
+                            playground.format(
+                                &format_code,
+                                self.toolchain.channel.as_str(),
+                                self.toolchain.edition.as_str(),
+                            ),
+                        )
+                        .await;
+                        (res, Some(ToolResult::Format(format_res)))
+                    }
+                    EvalKind::Artifact(artifact) => {
+                        let kind = PlaygroundArtifactKind::from(artifact);
+                        let channel = if kind.requires_nightly() {
+                            PlaygroundRustChannel::Nightly
+                        } else {
+                            self.toolchain.channel
+                        };
+                        let artifact_fut = async {
+                            match kind.target() {
+                                Some(target) => {
+                                    playground
+                                        .compile(
+                                            &self.rendered_code,
+                                            target,
+                                            channel.as_str(),
+                                            self.toolchain.profile.as_str(),
+                                            self.toolchain.edition.as_str(),
+                                        )
+                                        .await
+                                }
+                                None => {
+                                    playground
+                                        .macro_expansion(
+                                            &self.rendered_code,
+                                            self.toolchain.edition.as_str(),
+                                        )
+                                        .await
+                                }
+                            }
+                        };
+                        let (res, artifact_res) = join(run_code_fut, artifact_fut).await;
+                        (res, Some(ToolResult::Artifact(kind, artifact_res)))
+                    }
+                }
             }),
             cancel_event,
         )
@@ -44,24 +150,21 @@ impl<R: IPlaygroundRecordRepository, P: IPlaygrounService> WaitForEvalResult
         let mut revision = match res {
             Err(e) => PlaygroundRecordRevision {
                 revision_id: self.upsert_result.revision_id,
+                user_code: self.user_code,
                 rendered_code: self.rendered_code,
                 playground_error: e.to_string(),
+                toolchain: self.toolchain,
+                tests: self.kind == EvalKind::Test,
                 ..Default::default()
             },
             Ok(res) => {
-                let (mut error_count, mut warning_count) = (0, 0);
-                for line in res.result_stderr.lines() {
-                    if line.starts_with("error:") {
-                        error_count += 1;
-                    } else if line.starts_with("warning:") {
-                        warning_count += 1;
-                    }
-                }
-                if error_count > 1 {
-                    error_count -= 1;
-                }
+                let result_stderr =
+                    remap_stderr(&self.user_code, &self.rendered_code, res.result_stderr);
+                let diagnostics = parse_diagnostics(&result_stderr);
+                let (error_count, warning_count) = count_diagnostics(&diagnostics);
                 PlaygroundRecordRevision {
                     revision_id: self.upsert_result.revision_id,
+                    user_code: self.user_code,
                     rendered_code: self.rendered_code,
                     warning_count,
                     error_count,
@@ -69,16 +172,52 @@ impl<R: IPlaygroundRecordRepository, P: IPlaygrounService> WaitForEvalResult
                     result_code: res.result_code,
                     result_exit_detail: res.result_exit_detail,
                     result_stdout: res.result_stdout,
-                    result_stderr: res.result_stderr,
+                    result_stderr,
+                    result_backend: res.result_backend,
                     playground_error: "".to_string(),
+                    toolchain: self.toolchain,
+                    tests: self.kind == EvalKind::Test,
+                    diagnostics,
                     ..Default::default()
                 }
             }
         };
-        let init_page_state = if revision.result_stdout.is_empty() {
-            PlaygroundRecordPageState::Stderr
-        } else {
-            self.upsert_result.page_state
+        match tool_res {
+            Some(ToolResult::Miri(Ok(miri_res))) => apply_miri_result(&mut revision, miri_res),
+            Some(ToolResult::Clippy(Ok(clippy_res))) => {
+                apply_clippy_result(&mut revision, clippy_res)
+            }
+            Some(ToolResult::Format(Ok(format_res))) => {
+                apply_format_result(&mut revision, format_res)
+            }
+            Some(ToolResult::Artifact(kind, Ok(compile_res))) => {
+                apply_artifact_result(&mut revision, kind, compile_res)
+            }
+            Some(
+                ToolResult::Miri(Err(e))
+                | ToolResult::Clippy(Err(e))
+                | ToolResult::Format(Err(e))
+                | ToolResult::Artifact(_, Err(e)),
+            ) => error!("Failed to run {:?}: {}", self.kind, e),
+            None => {}
+        }
+        let init_page_state = match self.upsert_result.page_state {
+            PlaygroundRecordPageState::Miri if revision.miri_success.is_some() => {
+                PlaygroundRecordPageState::Miri
+            }
+            PlaygroundRecordPageState::Clippy if revision.clippy_success.is_some() => {
+                PlaygroundRecordPageState::Clippy
+            }
+            PlaygroundRecordPageState::Format if revision.format_success.is_some() => {
+                PlaygroundRecordPageState::Format
+            }
+            PlaygroundRecordPageState::Artifact(kind)
+                if revision.artifacts.iter().any(|a| a.kind == kind) =>
+            {
+                PlaygroundRecordPageState::Artifact(kind)
+            }
+            _ if revision.result_stdout.is_empty() => PlaygroundRecordPageState::Stderr,
+            _ => PlaygroundRecordPageState::Output,
         };
         match self
             .controller
@@ -94,7 +233,85 @@ impl<R: IPlaygroundRecordRepository, P: IPlaygrounService> WaitForEvalResult
                 EvalResultResponse::Err("Failed to update revision".into())
             }
             Ok(false) => EvalResultResponse::RequestOutdated,
-            Ok(true) => EvalResultResponse::Ok(build_page_data(revision, init_page_state)),
+            Ok(true) => EvalResultResponse::Ok(build_page_data(revision, init_page_state, 0)),
         }
     }
 }
+
+pub(super) fn apply_miri_result(
+    revision: &mut PlaygroundRecordRevision,
+    miri_res: PlaygroundExecuteResult,
+) {
+    revision.miri_success = Some(miri_res.result_success);
+    revision.miri_exit_detail = miri_res.result_exit_detail;
+    revision.miri_stdout = miri_res.result_stdout;
+    revision.miri_stderr = remap_stderr(
+        &revision.user_code,
+        &revision.rendered_code,
+        miri_res.result_stderr,
+    );
+}
+
+fn apply_clippy_result(
+    revision: &mut PlaygroundRecordRevision,
+    clippy_res: PlaygroundExecuteResult,
+) {
+    // Clippy also lints the wrapper, which is none of the user's business
+    let diagnostics = parse_diagnostics(&clippy_res.result_stderr);
+    let (error_count, warning_count) = count_diagnostics(diagnostics.iter().filter(|d| {
+        // The first span is the primary one
+        !d.spans.first().is_some_and(|span| {
+            is_wrapper_location(
+                &revision.user_code,
+                &revision.rendered_code,
+                &span.file,
+                span.line as usize,
+                span.column as usize,
+            )
+        })
+    }));
+    let clippy_stderr = remap_stderr(
+        &revision.user_code,
+        &revision.rendered_code,
+        clippy_res.result_stderr,
+    );
+    revision.clippy_success = Some(clippy_res.result_success);
+    revision.clippy_warning_count = warning_count;
+    revision.clippy_error_count = error_count;
+    revision.clippy_stderr = clippy_stderr;
+}
+
+pub(super) fn apply_format_result(
+    revision: &mut PlaygroundRecordRevision,
+    format_res: PlaygroundFormatResult,
+) {
+    revision.format_success = Some(format_res.success);
+    // Keep rustfmt's complaints so that the page can tell why formatting failed
+    revision.code_formatted = if format_res.success {
+        unwrap_formatted_code(
+            &revision.user_code,
+            &revision.rendered_code,
+            format_res.code,
+        )
+    } else {
+        format_res.stderr
+    };
+}
+
+fn apply_artifact_result(
+    revision: &mut PlaygroundRecordRevision,
+    kind: PlaygroundArtifactKind,
+    compile_res: PlaygroundCompileResult,
+) {
+    let stderr = remap_stderr(
+        &revision.user_code,
+        &revision.rendered_code,
+        compile_res.stderr,
+    );
+    revision.artifacts.push(PlaygroundArtifact {
+        kind,
+        success: compile_res.success,
+        code: compile_res.code,
+        stderr,
+    });
+}
@@ -0,0 +1,297 @@
+use std::{collections::HashSet, ops::Range};
+
+use proc_macro2::{LineColumn, TokenStream, TokenTree};
+use syn::{
+    parse::{ParseStream, Parser},
+    spanned::Spanned,
+    Attribute, Block, Item, Stmt,
+};
+
+use super::EvalKind;
+
+pub(super) fn render_code(kind: EvalKind, code: &str) -> String {
+    // Test harnesses bring their own entry point
+    if matches!(kind, EvalKind::Run | EvalKind::Test) {
+        return code.to_owned();
+    }
+    match wrap_layout(code) {
+        Some(layout) => render_layout(code, &layout).0,
+        None => code.to_owned(),
+    }
+}
+
+/// A span of user code copied verbatim into the rendered code.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) struct Segment {
+    pub(super) rendered: usize,
+    pub(super) user: usize,
+    pub(super) len: usize,
+}
+
+/// Returns where each piece of `code` ended up in `rendered_code`, or `None` if the code was sent
+/// verbatim.
+pub(super) fn wrapped_segments(code: &str, rendered_code: &str) -> Option<Vec<Segment>> {
+    if code == rendered_code {
+        return None;
+    }
+    let (rendered, segments) = render_layout(code, &wrap_layout(code)?);
+    (rendered == rendered_code).then_some(segments)
+}
+
+const FORMAT_WRAPPER_PREFIX: &str = "fn __snippet() {\n";
+
+/// Snippets that are not full programs are formatted as the body of a throwaway function.
+pub(super) fn render_format_code(code: &str, rendered_code: &str) -> String {
+    if code.is_empty() || code == rendered_code {
+        return rendered_code.to_owned();
+    }
+    format!("{FORMAT_WRAPPER_PREFIX}{code}\n}}\n")
+}
+
+pub(super) fn unwrap_formatted_code(code: &str, rendered_code: &str, formatted: String) -> String {
+    if code.is_empty() || code == rendered_code {
+        return formatted;
+    }
+    if formatted.starts_with("fn __snippet() {}") {
+        return String::new();
+    }
+    let Some(body) = formatted
+        .strip_prefix(FORMAT_WRAPPER_PREFIX)
+        .and_then(|body| body.trim_end().strip_suffix('}'))
+    else {
+        return formatted;
+    };
+    // Lines inside multi-line string literals were never indented by rustfmt
+    let mut literal_lines = HashSet::new();
+    if let Ok(tokens) = formatted.parse() {
+        collect_literal_lines(tokens, &mut literal_lines);
+    }
+    body.lines()
+        .enumerate()
+        // The body starts on the second line of `formatted`
+        .map(|(idx, line)| {
+            if literal_lines.contains(&(idx + 2)) {
+                line
+            } else {
+                line.strip_prefix("    ").unwrap_or(line)
+            }
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Collects the lines that continue a literal started on an earlier line.
+fn collect_literal_lines(tokens: TokenStream, lines: &mut HashSet<usize>) {
+    for token in tokens {
+        match token {
+            TokenTree::Group(group) => collect_literal_lines(group.stream(), lines),
+            TokenTree::Literal(literal) => {
+                let span = literal.span();
+                lines.extend(span.start().line + 1..=span.end().line);
+            }
+            TokenTree::Ident(_) | TokenTree::Punct(_) => {}
+        }
+    }
+}
+
+fn wrap_layout(code: &str) -> Option<SnippetLayout> {
+    match parse_snippet(code) {
+        Some(layout) if layout.has_main_fn => None,
+        Some(layout) => Some(layout),
+        None if has_main_fn(code) => None,
+        // Let rustc report syntax errors against the plain template
+        None => Some(SnippetLayout {
+            body: std::iter::once(0..code.len()).collect(),
+            ..Default::default()
+        }),
+    }
+}
+
+#[derive(Debug, Default)]
+struct SnippetLayout {
+    inner_attrs: Range<usize>,
+    items: Vec<Range<usize>>,
+    body: Vec<Range<usize>>,
+    has_main_fn: bool,
+}
+
+fn parse_snippet(code: &str) -> Option<SnippetLayout> {
+    fn parse(input: ParseStream) -> syn::Result<(Vec<Attribute>, Vec<Stmt>)> {
+        let attrs = input.call(Attribute::parse_inner)?;
+        let stmts = input.call(Block::parse_within)?;
+        Ok((attrs, stmts))
+    }
+
+    let (attrs, stmts) = parse.parse_str(code).ok()?;
+    let line_starts: Vec<_> = std::iter::once(0)
+        .chain(code.match_indices('\n').map(|(idx, _)| idx + 1))
+        .collect();
+    let byte_offset = |pos: LineColumn| {
+        let line_start = line_starts[pos.line - 1];
+        let column_len: usize = code[line_start..]
+            .chars()
+            .take(pos.column)
+            .map(char::len_utf8)
+            .sum();
+        line_start + column_len
+    };
+
+    let mut cursor = attrs
+        .last()
+        .map_or(0, |attr| byte_offset(attr.span().end()));
+    let mut layout = SnippetLayout {
+        inner_attrs: 0..cursor,
+        ..Default::default()
+    };
+    // Every byte of the snippet lands in exactly one chunk, so comments travel
+    // with the statement that follows them
+    for stmt in &stmts {
+        let end = byte_offset(stmt.span().end());
+        match stmt {
+            Stmt::Item(Item::Fn(item_fn)) if item_fn.sig.ident == "main" => {
+                layout.has_main_fn = true;
+                layout.items.push(cursor..end);
+            }
+            Stmt::Item(_) => layout.items.push(cursor..end),
+            Stmt::Macro(stmt_macro) if stmt_macro.mac.path.is_ident("macro_rules") => {
+                layout.items.push(cursor..end)
+            }
+            _ => layout.body.push(cursor..end),
+        }
+        cursor = end;
+    }
+    layout.body.push(cursor..code.len());
+    Some(layout)
+}
+
+fn render_layout(code: &str, layout: &SnippetLayout) -> (String, Vec<Segment>) {
+    let mut rendered = String::with_capacity(code.len() + 64);
+    let mut segments = Vec::with_capacity(layout.items.len() + layout.body.len() + 1);
+    let mut push_chunk = |rendered: &mut String, range: &Range<usize>| {
+        segments.push(Segment {
+            rendered: rendered.len(),
+            user: range.start,
+            len: range.len(),
+        });
+        rendered.push_str(&code[range.clone()]);
+    };
+    push_chunk(&mut rendered, &layout.inner_attrs);
+    rendered.push('\n');
+    for item in &layout.items {
+        push_chunk(&mut rendered, item);
+    }
+    rendered.push_str("\nfn main() { let res = {\n");
+    for stmt in &layout.body {
+        push_chunk(&mut rendered, stmt);
+    }
+    rendered.push_str("\n}; println!(\"{res:?}\"); }\n");
+    segments.retain(|segment| segment.len > 0);
+    (rendered, segments)
+}
+
+fn has_main_fn(code: &str) -> bool {
+    code.match_indices("fn").any(|(idx, _)| {
+        let before = &code[..idx];
+        let after = &code[idx + 2..];
+        if before.ends_with(|c: char| c.is_alphanumeric() || c == '_') {
+            return false;
+        }
+        let Some(after) = after
+            .strip_prefix(char::is_whitespace)
+            .map(str::trim_start)
+            .and_then(|s| s.strip_prefix("main"))
+        else {
+            return false;
+        };
+        after.trim_start().starts_with(['(', '<'])
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_has_main_fn() {
+        assert!(has_main_fn("fn main() {}"));
+        assert!(has_main_fn("struct A;\npub fn  main () {}"));
+        assert!(!has_main_fn("fn main_loop() {}"));
+        assert!(!has_main_fn("1 + 1"));
+        assert!(!has_main_fn("defn main() {}"));
+    }
+
+    #[test]
+    fn test_render_code() {
+        assert_eq!(render_code(EvalKind::Run, "struct A;"), "struct A;");
+        assert_eq!(
+            render_code(EvalKind::Test, "#[test] fn a() {}"),
+            "#[test] fn a() {}"
+        );
+        assert_eq!(render_code(EvalKind::Eval, "fn main() {}"), "fn main() {}");
+        assert_eq!(
+            render_code(EvalKind::Eval, "1 + 1"),
+            "\n\nfn main() { let res = {\n1 + 1\n}; println!(\"{res:?}\"); }\n"
+        );
+    }
+
+    #[test]
+    fn test_render_code_hoists_items() {
+        let code = "#![allow(unused)]\nuse std::fmt;\n// a struct\nstruct A;\nlet a = A;\nmacro_rules! m { () => { 1 } }\nimpl fmt::Debug for A { fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result { write!(f, \"ä\") } }\nm!()";
+        assert_eq!(
+            render_code(EvalKind::Eval, code),
+            "#![allow(unused)]\n\nuse std::fmt;\n// a struct\nstruct A;\nmacro_rules! m { () => { 1 } }\nimpl fmt::Debug for A { fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result { write!(f, \"ä\") } }\nfn main() { let res = {\n\nlet a = A;\nm!()\n}; println!(\"{res:?}\"); }\n"
+        );
+    }
+
+    #[test]
+    fn test_wrapped_segments() {
+        let code = "use std::fmt;\nlet a = 1; a";
+        let rendered = render_code(EvalKind::Eval, code);
+        let segments = wrapped_segments(code, &rendered).unwrap();
+        for segment in &segments {
+            assert_eq!(
+                &rendered[segment.rendered..][..segment.len],
+                &code[segment.user..][..segment.len]
+            );
+        }
+        assert_eq!(segments.iter().map(|s| s.len).sum::<usize>(), code.len());
+        assert_eq!(wrapped_segments("fn main() {}", "fn main() {}"), None);
+    }
+
+    #[test]
+    fn test_unwrap_formatted_code() {
+        let code = "let a=1;a";
+        let rendered = render_code(EvalKind::Eval, code);
+        assert_eq!(
+            render_format_code(code, &rendered),
+            "fn __snippet() {\nlet a=1;a\n}\n"
+        );
+        assert_eq!(
+            unwrap_formatted_code(
+                code,
+                &rendered,
+                "fn __snippet() {\n    let a = 1;\n    a\n}\n".into()
+            ),
+            "let a = 1;\na"
+        );
+        let code = "let s = \"a\n    b\";\nlet r = r#\"\n    c\"#;s";
+        assert_eq!(
+            unwrap_formatted_code(
+                code,
+                &render_code(EvalKind::Eval, code),
+                "fn __snippet() {\n    let s = \"a\n    b\";\n    let r = r#\"\n    c\"#;\n    s\n}\n"
+                    .into()
+            ),
+            "let s = \"a\n    b\";\nlet r = r#\"\n    c\"#;\ns"
+        );
+        assert_eq!(
+            render_format_code("fn main(){}", "fn main(){}"),
+            "fn main(){}"
+        );
+    }
+
+    #[test]
+    fn test_render_code_syntax_error() {
+        assert!(render_code(EvalKind::Eval, "let a = ;").contains("let a = ;"));
+    }
+}
@@ -1,18 +1,35 @@
 mod callback_query;
+mod document;
 mod error;
 mod r#loop;
 mod new_message;
+mod pending;
+mod rate_limit;
 mod render;
+mod source_file;
+mod task_tracker;
 mod tg_client;
+mod tg_method;
+mod webhook;
+
+#[cfg(test)]
+mod tests;
 
 use std::sync::Arc;
 
 use event_listener::Event;
+
+use crate::config::ChatPolicy;
 pub(crate) use r#loop::run_loop;
+pub(crate) use task_tracker::TaskTracker;
 pub(crate) use tg_client::{TgClient, TgEnv};
+pub(crate) use tg_method::GetMe;
+pub(crate) use webhook::{run_webhook, WebhookConfig};
 
 pub struct Handler<'e, C> {
     pub client: Arc<TgClient>,
     pub controller: C,
     pub cancel_event: &'e Event,
+    pub tasks: TaskTracker,
+    pub chat_policy: ChatPolicy,
 }
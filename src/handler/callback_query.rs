@@ -1,6 +1,6 @@
 use telegram_types::bot::{
-    methods::{AnswerCallbackQuery, ChatTarget, DeleteMessage, EditMessageText, TelegramResult},
-    types::{CallbackQuery, Message, ParseMode},
+    methods::{AnswerCallbackQuery, ChatTarget, DeleteMessage, EditMessageText},
+    types::{CallbackQuery, ParseMode},
 };
 use tracing::error;
 
@@ -9,9 +9,17 @@ use crate::controller::{
     ShowEvalOutputResponse,
 };
 
-use super::{error::HandlerResult, render::render_page_data, Handler};
+use super::{
+    error::{ignore_not_modified, HandlerResult},
+    new_message::{continue_processing, PROCESSING_MESSAGE_TEXT},
+    render::{parse_state, render_page_data},
+    Handler,
+};
 
-impl<'e, C: IController> Handler<'e, C> {
+impl<'e, C: IController> Handler<'e, C>
+where
+    C::LazyToolImpl: 'static,
+{
     pub(super) async fn handle_message_callback_query(
         &mut self,
         query: CallbackQuery,
@@ -27,10 +35,7 @@ impl<'e, C: IController> Handler<'e, C> {
             }
         };
         self.client
-            .call_method_with_param::<_, bool>(
-                "answerCallbackQuery",
-                &AnswerCallbackQuery::new(query_id).text(text.into()),
-            )
+            .call(&AnswerCallbackQuery::new(query_id).text(text.into()))
             .await?;
         Ok(())
     }
@@ -48,6 +53,7 @@ impl<'e, C: IController> Handler<'e, C> {
         match action {
             "del" => self.handle_delete_eval(rem, query).await,
             "state" => self.handle_eval_state(rem, query).await,
+            "page" => self.handle_eval_page(rem, query).await,
             "genlink" => self.handle_gen_link(rem, query).await,
             _ => Ok(false),
         }
@@ -69,15 +75,12 @@ impl<'e, C: IController> Handler<'e, C> {
             RequestDeleteEvalResponse::Approved(rev) => {
                 let res = self
                     .client
-                    .call_method_with_param::<_, bool>(
-                        "deleteMessage",
-                        &DeleteMessage {
-                            chat_id: ChatTarget::id(msg.chat.id.0),
-                            message_id: msg.message_id,
-                        },
-                    )
+                    .call(&DeleteMessage {
+                        chat_id: ChatTarget::id(msg.chat.id.0),
+                        message_id: msg.message_id,
+                    })
                     .await;
-                if !matches!(res, Ok(TelegramResult { ok: true, .. })) {
+                if !matches!(res, Ok(true)) {
                     error!(
                         msg_id = %msg.message_id.0,
                         from_id = %from_id,
@@ -94,41 +97,115 @@ impl<'e, C: IController> Handler<'e, C> {
             }
             RequestDeleteEvalResponse::Err(e) => query_response.text(e).show_alert(true),
         };
-        self.client
-            .call_method_with_param::<_, bool>("answerCallbackQuery", &query_response)
-            .await?;
+        self.client.call(&query_response).await?;
         Ok(true)
     }
 
     async fn handle_eval_state(&mut self, rem: &str, query: CallbackQuery) -> HandlerResult<bool> {
-        let Some(msg) = query.message.as_ref() else {
+        let Some((request_page_state, rem)) = rem
+            .split_once(':')
+            .and_then(|(state, rem)| Some((parse_state(state)?, rem)))
+        else {
             return Ok(false);
         };
-        let (request_page_state, rem) = match rem.split_once(':') {
-            Some(("output", rem)) => (EvalPageState::Output, rem),
-            Some(("build", rem)) => (EvalPageState::Build, rem),
-            _ => return Ok(false),
-        };
         let Some(revision_id) = rem.parse::<i64>().ok() else {
             return Ok(false);
         };
+        self.switch_eval_state(query, request_page_state, None, revision_id)
+            .await
+    }
+
+    async fn handle_eval_page(&mut self, rem: &str, query: CallbackQuery) -> HandlerResult<bool> {
+        let mut parts = rem.splitn(3, ':');
+        let (Some(state), Some(page), Some(revision_id)) =
+            (parts.next(), parts.next(), parts.next())
+        else {
+            return Ok(false);
+        };
+        let (Some(request_page_state), Ok(page), Ok(revision_id)) = (
+            parse_state(state),
+            page.parse::<u32>(),
+            revision_id.parse::<i64>(),
+        ) else {
+            return Ok(false);
+        };
+        self.switch_eval_state(query, request_page_state, Some(page), revision_id)
+            .await
+    }
+
+    async fn switch_eval_state(
+        &mut self,
+        query: CallbackQuery,
+        request_page_state: EvalPageState,
+        page_number: Option<u32>,
+        revision_id: i64,
+    ) -> HandlerResult<bool> {
+        let Some(msg) = query.message.as_ref() else {
+            return Ok(false);
+        };
         let from_id = query.from.id.0;
         let res = self
             .controller
-            .switch_eval_state(msg.message_id.0, from_id, revision_id, request_page_state)
+            .switch_eval_state(
+                msg.message_id.0,
+                from_id,
+                revision_id,
+                request_page_state,
+                page_number.unwrap_or(0),
+            )
             .await;
         let mut query_response = AnswerCallbackQuery::new(query.id);
         query_response = match res {
             ShowEvalOutputResponse::Ok(page_data) => {
-                let (text, keyboard) = render_page_data(page_data);
-                self.client
-                    .call_method_with_param::<_, Message>(
-                        "editMessageText",
-                        &EditMessageText::new(ChatTarget::id(msg.chat.id.0), msg.message_id, text)
-                            .parse_mode(ParseMode::HTML)
-                            .reply_markup(keyboard),
+                let page = render_page_data(page_data);
+                let res = self
+                    .client
+                    .call(
+                        &EditMessageText::new(
+                            ChatTarget::id(msg.chat.id.0),
+                            msg.message_id,
+                            page.text,
+                        )
+                        .parse_mode(ParseMode::HTML)
+                        .reply_markup(page.keyboard),
+                    )
+                    .await;
+                ignore_not_modified(res)?;
+                query_response
+            }
+            // The tool may take a while, so it finishes outside of the update loop
+            ShowEvalOutputResponse::Processing(processing) => {
+                let chat_target = ChatTarget::Id(msg.chat.id);
+                let res = self
+                    .client
+                    .call(
+                        &EditMessageText::new(
+                            chat_target.clone(),
+                            msg.message_id,
+                            PROCESSING_MESSAGE_TEXT,
+                        )
+                        .parse_mode(ParseMode::HTML),
                     )
-                    .await?;
+                    .await;
+                ignore_not_modified(res)?;
+                let client = self.client.clone();
+                let eval_msg_id = msg.message_id.0;
+                let cancel_event = self.cancel_event.listen();
+                self.tasks.spawn(async move {
+                    let res = continue_processing(
+                        chat_target,
+                        eval_msg_id,
+                        client,
+                        processing,
+                        cancel_event,
+                        // Pages shown from buttons are never sent as a file
+                        false,
+                    )
+                    .await;
+                    if let Err(e) = res {
+                        error!("Error in continue_processing: {:?}", e);
+                    }
+                });
                 query_response
             }
             ShowEvalOutputResponse::SenderMismatch => {
@@ -136,9 +213,7 @@ impl<'e, C: IController> Handler<'e, C> {
             }
             ShowEvalOutputResponse::Err(e) => query_response.text(e).show_alert(true),
         };
-        self.client
-            .call_method_with_param::<_, bool>("answerCallbackQuery", &query_response)
-            .await?;
+        self.client.call(&query_response).await?;
         Ok(true)
     }
 
@@ -153,15 +228,20 @@ impl<'e, C: IController> Handler<'e, C> {
         let mut query_response = AnswerCallbackQuery::new(query.id);
         query_response = match res {
             GetEvalLinkResponse::Ok(page_data) => {
-                let (text, keyboard) = render_page_data(page_data);
-                self.client
-                    .call_method_with_param::<_, Message>(
-                        "editMessageText",
-                        &EditMessageText::new(ChatTarget::id(msg.chat.id.0), msg.message_id, text)
-                            .parse_mode(ParseMode::HTML)
-                            .reply_markup(keyboard),
+                let page = render_page_data(page_data);
+                let res = self
+                    .client
+                    .call(
+                        &EditMessageText::new(
+                            ChatTarget::id(msg.chat.id.0),
+                            msg.message_id,
+                            page.text,
+                        )
+                        .parse_mode(ParseMode::HTML)
+                        .reply_markup(page.keyboard),
                     )
-                    .await?;
+                    .await;
+                ignore_not_modified(res)?;
                 query_response.text("Link generated in-place".into())
             }
             GetEvalLinkResponse::NotFound => query_response
@@ -169,9 +249,7 @@ impl<'e, C: IController> Handler<'e, C> {
                 .show_alert(true),
             GetEvalLinkResponse::Err(e) => query_response.text(e).show_alert(true),
         };
-        self.client
-            .call_method_with_param::<_, bool>("answerCallbackQuery", &query_response)
-            .await?;
+        self.client.call(&query_response).await?;
         Ok(true)
     }
 }
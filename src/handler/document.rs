@@ -0,0 +1,53 @@
+use serde::Serialize;
+use telegram_types::bot::{
+    methods::ChatTarget,
+    types::{Message, MessageId},
+};
+use tracing::warn;
+
+use super::{error::HandlerResult, render::PageDocument, tg_method::tg_method, TgClient};
+
+#[derive(Debug, Serialize)]
+struct SendDocument<'a> {
+    chat_id: ChatTarget<'a>,
+    caption: &'a str,
+    reply_parameters: ReplyParameters,
+}
+
+#[derive(Debug, Serialize)]
+struct ReplyParameters {
+    message_id: MessageId,
+}
+
+tg_method!(SendDocument<'_>, "sendDocument", Message);
+
+/// Sends the full content of a truncated page as a reply to the eval message.
+pub(super) async fn send_page_document(
+    client: &TgClient,
+    chat_target: ChatTarget<'_>,
+    eval_msg_id: MessageId,
+    document: PageDocument,
+) -> HandlerResult<()> {
+    if document.content.len() as u64 > client.upload_limit() {
+        warn!(
+            len = document.content.len(),
+            "page document too large to send"
+        );
+        return Ok(());
+    }
+    client
+        .call_with_file(
+            &SendDocument {
+                chat_id: chat_target,
+                caption: &document.caption,
+                reply_parameters: ReplyParameters {
+                    message_id: eval_msg_id,
+                },
+            },
+            "document",
+            &document.file_name,
+            document.content.as_bytes(),
+        )
+        .await?;
+    Ok(())
+}
@@ -1,12 +1,29 @@
+use std::io;
+
 use telegram_types::bot::methods::ApiError;
 use thiserror::Error;
 
 #[derive(Debug, Error)]
-pub(super) enum HandlerError {
+pub(crate) enum HandlerError {
     #[error("request error: {0}")]
     Request(#[from] cyper::Error),
     #[error("api error: {0}")]
     Telegram(#[from] ApiError),
+    #[error("io error: {0}")]
+    Io(#[from] io::Error),
+    #[error("file download failed with status {0}")]
+    Download(u16),
 }
 
-pub(super) type HandlerResult<T> = Result<T, HandlerError>;
+pub(crate) type HandlerResult<T> = Result<T, HandlerError>;
+
+/// Telegram refuses edits that would leave a message as it is, which is fine when a button for
+/// the page already shown is pressed.
+pub(super) fn ignore_not_modified<T>(res: HandlerResult<T>) -> HandlerResult<()> {
+    match res {
+        Err(HandlerError::Telegram(e)) if e.to_string().contains("message is not modified") => {
+            Ok(())
+        }
+        res => res.map(|_| ()),
+    }
+}
@@ -1,35 +1,59 @@
 use std::{pin::pin, time::Duration};
 
 use compio::time::sleep;
+use event_listener::EventListener;
 use futures::future::{select, Either};
 use telegram_types::bot::{
     methods::GetUpdates,
     types::{Message, Update, UpdateContent, UpdateId},
 };
-use tracing::{debug, error};
+use tracing::{debug, error, info};
 
-use crate::controller::IController;
+use crate::controller::{EvalArtifact, EvalKind, IController};
 
 use super::error::HandlerResult;
 
+const EVAL_COMMANDS: &[(&str, EvalKind)] = &[
+    ("/bval", EvalKind::Eval),
+    ("/brun", EvalKind::Run),
+    ("/bmiri", EvalKind::Miri),
+    ("/bclippy", EvalKind::Clippy),
+    ("/bfmt", EvalKind::Format),
+    ("/btest", EvalKind::Test),
+    ("/basm", EvalKind::Artifact(EvalArtifact::Asm)),
+    ("/bllvm", EvalKind::Artifact(EvalArtifact::LlvmIr)),
+    ("/bmir", EvalKind::Artifact(EvalArtifact::Mir)),
+    ("/bhir", EvalKind::Artifact(EvalArtifact::Hir)),
+    ("/bwasm", EvalKind::Artifact(EvalArtifact::Wasm)),
+    ("/bexpand", EvalKind::Artifact(EvalArtifact::MacroExpansion)),
+];
+
 pub async fn run_loop<C: IController>(mut handler: super::Handler<'_, C>)
 where
     C::EvalProcessingImpl: 'static,
+    C::LazyToolImpl: 'static,
 {
-    let mut update_offset = None;
-    while let Err(e) = run_loop_inner(&mut handler, &mut update_offset).await {
+    let mut update_offset = handler.controller.update_offset().await.map(UpdateId);
+    let mut cancel_listener = handler.cancel_event.listen();
+    while let Err(e) = run_loop_inner(&mut handler, &mut update_offset, &mut cancel_listener).await
+    {
         error!(?e, "loop error");
-        sleep(Duration::from_secs(5)).await;
+        let sleep_fut = pin!(sleep(Duration::from_secs(5)));
+        if let Either::Right(_) = select(sleep_fut, &mut cancel_listener).await {
+            break;
+        }
     }
+    info!("stopped polling updates");
 }
 async fn run_loop_inner<'e, C: IController>(
     handler: &mut super::Handler<'e, C>,
     update_offset: &mut Option<UpdateId>,
+    cancel_listener: &mut EventListener,
 ) -> HandlerResult<()>
 where
     C::EvalProcessingImpl: 'static,
+    C::LazyToolImpl: 'static,
 {
-    let mut cancel_listener = handler.cancel_event.listen();
     loop {
         let updates = {
             let get_updates_req = GetUpdates {
@@ -37,11 +61,9 @@ where
                 timeout: Some(30),
                 ..Default::default()
             };
-            let get_updates_fut = pin!(handler
-                .client
-                .call_method_with_param::<_, Vec<Update>>("getUpdates", &get_updates_req,));
-            match select(get_updates_fut, &mut cancel_listener).await {
-                Either::Left((res, _)) => res?.into_result()?,
+            let get_updates_fut = pin!(handler.client.call(&get_updates_req));
+            match select(get_updates_fut, &mut *cancel_listener).await {
+                Either::Left((res, _)) => res?,
                 Either::Right(_) => return Ok(()),
             }
         };
@@ -51,66 +73,138 @@ where
             *update_offset = update_offset
                 .map(|o| o.max(next_offset))
                 .or(Some(next_offset));
-            let Some(content) = update.content else {
-                continue;
-            };
-            let msg_id;
-            let chat_id;
-            let text;
-            let msg_from;
-            match content {
-                UpdateContent::Message(Message {
-                    message_id,
-                    chat,
-                    text: msg_text,
-                    from: Some(from),
-                    ..
-                }) => {
-                    msg_id = message_id;
-                    chat_id = chat.id;
-                    text = msg_text.unwrap_or_default();
-                    msg_from = from;
-                    debug!(?chat, ?msg_id, ?text, "message");
-                }
-                UpdateContent::EditedMessage(Message {
-                    message_id,
-                    chat,
-                    text: msg_text,
-                    from: Some(from),
-                    ..
-                }) => {
-                    msg_id = message_id;
-                    chat_id = chat.id;
-                    text = msg_text.unwrap_or_default();
-                    msg_from = from;
-                    debug!(?chat, ?msg_id, ?text, "edited message");
-                }
-                UpdateContent::CallbackQuery(query) => {
-                    if let Err(e) = handler.handle_message_callback_query(query).await {
-                        error!(?e, "handle_message_callback_query error");
-                    }
-                    continue;
-                }
-                _ => continue,
-            };
-            let text = text.trim();
-            if text.is_empty() {
-                continue;
+            dispatch_update(handler, update).await;
+        }
+    }
+}
+
+/// Handles an update from either polling or the webhook, once.
+pub(super) async fn dispatch_update<C: IController>(
+    handler: &mut super::Handler<'_, C>,
+    update: Update,
+) where
+    C::EvalProcessingImpl: 'static,
+    C::LazyToolImpl: 'static,
+{
+    let update_id = update.update_id.0;
+    if handler.controller.is_update_handled(update_id).await {
+        debug!(?update_id, "skipping handled update");
+        return;
+    }
+    if let Some(content) = update.content {
+        handle_update_content(handler, content).await;
+    }
+    handler.controller.mark_update_handled(update_id).await;
+}
+
+async fn handle_update_content<C: IController>(
+    handler: &mut super::Handler<'_, C>,
+    content: UpdateContent,
+) where
+    C::EvalProcessingImpl: 'static,
+    C::LazyToolImpl: 'static,
+{
+    let msg_id;
+    let chat_id;
+    let text;
+    let msg_from;
+    let source_file;
+    match content {
+        UpdateContent::Message(Message {
+            message_id,
+            chat,
+            text: msg_text,
+            caption,
+            document,
+            from: Some(from),
+            ..
+        }) => {
+            msg_id = message_id;
+            chat_id = chat.id;
+            // A source file can be sent with the command as its caption
+            text = msg_text.or(caption).unwrap_or_default();
+            msg_from = from;
+            source_file = document;
+            debug!(?chat, ?msg_id, ?text, "message");
+        }
+        UpdateContent::EditedMessage(Message {
+            message_id,
+            chat,
+            text: msg_text,
+            caption,
+            document,
+            from: Some(from),
+            ..
+        }) => {
+            msg_id = message_id;
+            chat_id = chat.id;
+            text = msg_text.or(caption).unwrap_or_default();
+            msg_from = from;
+            source_file = document;
+            debug!(?chat, ?msg_id, ?text, "edited message");
+        }
+        UpdateContent::CallbackQuery(query) => {
+            if let Some(chat) = query
+                .message
+                .as_ref()
+                .map(|msg| &msg.chat)
+                .filter(|chat| !handler.chat_policy.allows(chat.id.0))
+            {
+                debug!(chat_id = ?chat.id, "chat not allowed");
+                return;
+            }
+            if let Err(e) = handler.handle_message_callback_query(query).await {
+                error!(?e, "handle_message_callback_query error");
+            }
+            return;
+        }
+        _ => return,
+    };
+    if !handler.chat_policy.allows(chat_id.0) {
+        debug!(?chat_id, "chat not allowed");
+        return;
+    }
+    let text = text.trim();
+    if text.is_empty() {
+        return;
+    }
+    let Some((kind, command)) = EVAL_COMMANDS
+        .iter()
+        .find_map(|&(name, kind)| Some((kind, strip_command(text, name)?)))
+    else {
+        return;
+    };
+    let mut command = command.trim_start().to_owned();
+    if let Some(document) = source_file {
+        match handler.read_source_file(chat_id, msg_id, &document).await {
+            Ok(Some(code)) => {
+                command.push('\n');
+                command.push_str(&code);
             }
-            if let Some(mut command) = text.trim().strip_prefix("/bval") {
-                if command.starts_with('@') {
-                    command = command
-                        .split_once([' ', '\r', '\n'])
-                        .map(|(_, rest)| rest)
-                        .unwrap_or_default();
-                }
-                if let Err(e) = handler
-                    .handle_new_message(chat_id, msg_id, msg_from.id, command.trim_start())
-                    .await
-                {
-                    error!(?e, "handle_new_message error");
-                }
+            Ok(None) => return,
+            Err(e) => {
+                error!(?e, "read_source_file error");
+                return;
             }
         }
     }
+    if let Err(e) = handler
+        .handle_new_message(chat_id, msg_id, msg_from.id, kind, &command)
+        .await
+    {
+        error!(?e, "handle_new_message error");
+    }
+}
+
+fn strip_command<'t>(text: &'t str, name: &str) -> Option<&'t str> {
+    let mut command = text.strip_prefix(name)?;
+    if command.starts_with('@') {
+        command = command
+            .split_once([' ', '\r', '\n'])
+            .map(|(_, rest)| rest)
+            .unwrap_or_default();
+    } else if !command.is_empty() && !command.starts_with(char::is_whitespace) {
+        return None;
+    }
+    Some(command)
 }
@@ -1,20 +1,25 @@
 use std::sync::Arc;
 
-use compio::runtime::spawn;
 use event_listener::EventListener;
 use telegram_types::bot::{
     methods::{ChatTarget, EditMessageText, SendMessage},
-    types::{ChatId, Message, MessageId, ParseMode, UserId},
+    types::{ChatId, MessageId, ParseMode, UserId},
 };
-use tracing::error;
+use tracing::{debug, error};
 
 use crate::controller::{
-    EvalResponse, EvalResultResponse, IController, UpdateEvalMsgId, WaitForEvalResult,
+    EvalKind, EvalResponse, EvalResultResponse, IController, UpdateEvalMsgId, WaitForEvalResult,
 };
 
-use super::{error::HandlerResult, render::render_page_data, Handler, TgClient};
+use super::{
+    document::send_page_document,
+    error::{ignore_not_modified, HandlerError, HandlerResult},
+    render::render_page_data,
+    Handler, TgClient,
+};
 
-const PROCESSING_MESSAGE_TEXT: &str = "<i>Processing...</i>";
+pub(super) const PROCESSING_MESSAGE_TEXT: &str = "<i>Processing...</i>";
+const CANCELLED_MESSAGE_TEXT: &str = "<i>Bot restarting, please re-edit to retry</i>";
 
 impl<'e, C: IController> Handler<'e, C>
 where
@@ -25,20 +30,20 @@ where
         chat_id: ChatId,
         user_msg_id: MessageId,
         user_id: UserId,
+        kind: EvalKind,
         command: &str,
     ) -> HandlerResult<()> {
         let command = command.to_owned();
         let res = self
             .controller
-            .new_eval(chat_id.0, user_msg_id.0, user_id.0, command.clone())
+            .new_eval(chat_id.0, user_msg_id.0, user_id.0, kind, command.clone())
             .await;
         let chat_target = ChatTarget::Id(chat_id);
         let mut processing = match res {
             EvalResponse::Processing(p) => p,
             EvalResponse::Err(e) => {
                 self.client
-                    .call_method_with_param::<_, Message>(
-                        "sendMessage",
+                    .call(
                         &SendMessage::new(
                             chat_target.clone(),
                             format!("<i>Fatal error: {}</i>", htmlize::escape_text(e)),
@@ -55,8 +60,7 @@ where
                 Some(eval_msg_id) => {
                     let edit_res = self
                         .client
-                        .call_method_with_param::<_, Message>(
-                            "editMessageText",
+                        .call(
                             &EditMessageText::new(
                                 chat_target.clone(),
                                 MessageId(eval_msg_id),
@@ -64,24 +68,26 @@ where
                             )
                             .parse_mode(ParseMode::HTML),
                         )
-                        .await?;
-                    if edit_res.result.is_none() {
-                        processing.eval_msg_id = None;
-                        continue;
+                        .await;
+                    match ignore_not_modified(edit_res) {
+                        Ok(()) => break eval_msg_id,
+                        // The eval message is gone, send a new one
+                        Err(HandlerError::Telegram(e)) => {
+                            debug!(?e, "cannot edit eval message");
+                            processing.eval_msg_id = None;
+                        }
+                        Err(e) => return Err(e),
                     }
-                    break eval_msg_id;
                 }
                 None => {
                     let send_msg_res = self
                         .client
-                        .call_method_with_param::<_, Message>(
-                            "sendMessage",
+                        .call(
                             &SendMessage::new(chat_target.clone(), PROCESSING_MESSAGE_TEXT)
                                 .parse_mode(ParseMode::HTML)
                                 .reply(user_msg_id),
                         )
-                        .await?
-                        .into_result()?;
+                        .await?;
                     let eval_msg_id = send_msg_res.message_id.0;
                     processing.update_eval_msg_id(eval_msg_id).await;
                     break eval_msg_id;
@@ -90,36 +96,50 @@ where
         };
         let client = self.client.clone();
         let cancel_event = self.cancel_event.listen();
-        spawn(async move {
-            let res =
-                continue_processing(chat_target, eval_msg_id, client, processing, cancel_event)
-                    .await;
+        self.tasks.spawn(async move {
+            let res = continue_processing(
+                chat_target,
+                eval_msg_id,
+                client,
+                processing,
+                cancel_event,
+                true,
+            )
+            .await;
             if let Err(e) = res {
                 error!("Error in continue_processing: {:?}", e);
             }
-        })
-        .detach();
+        });
         Ok(())
     }
 }
 
-async fn continue_processing(
+pub(super) async fn continue_processing(
     chat_target: ChatTarget<'static>,
     eval_msg_id: i64,
     client: Arc<TgClient>,
     wait_for_eval_result: impl WaitForEvalResult,
     cancel_event: EventListener,
+    send_document: bool,
 ) -> HandlerResult<()> {
     let eval_msg_id = MessageId(eval_msg_id);
     let res = wait_for_eval_result
         .wait_for_eval_result(cancel_event)
         .await;
     let data = match res {
-        EvalResultResponse::Cancelled | EvalResultResponse::RequestOutdated => return Ok(()),
+        EvalResultResponse::RequestOutdated => return Ok(()),
+        EvalResultResponse::Cancelled => {
+            client
+                .call_paced(
+                    &EditMessageText::new(chat_target, eval_msg_id, CANCELLED_MESSAGE_TEXT)
+                        .parse_mode(ParseMode::HTML),
+                )
+                .await?;
+            return Ok(());
+        }
         EvalResultResponse::Err(e) => {
             client
-                .call_method_with_param::<_, Message>(
-                    "editMessageText",
+                .call_paced(
                     &EditMessageText::new(
                         chat_target,
                         eval_msg_id,
@@ -132,14 +152,16 @@ async fn continue_processing(
         }
         EvalResultResponse::Ok(data) => data,
     };
-    let (text, keyboard) = render_page_data(data);
+    let page = render_page_data(data);
     client
-        .call_method_with_param::<_, Message>(
-            "editMessageText",
-            &EditMessageText::new(chat_target, eval_msg_id, text)
+        .call_paced(
+            &EditMessageText::new(chat_target.clone(), eval_msg_id, page.text)
                 .parse_mode(ParseMode::HTML)
-                .reply_markup(keyboard),
+                .reply_markup(page.keyboard),
         )
         .await?;
+    if let Some(document) = page.document.filter(|_| send_document) {
+        send_page_document(&client, chat_target, eval_msg_id, document).await?;
+    }
     Ok(())
 }
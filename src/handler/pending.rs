@@ -0,0 +1,76 @@
+use telegram_types::bot::{
+    methods::{ChatTarget, EditMessageText},
+    types::{ChatId, MessageId, ParseMode},
+};
+use tracing::{error, info};
+
+use crate::controller::{EvalProcessingResponse, IController, PendingEval};
+
+use super::{
+    error::HandlerResult,
+    new_message::{continue_processing, PROCESSING_MESSAGE_TEXT},
+    Handler,
+};
+
+const ABANDONED_MESSAGE_TEXT: &str = "<i>Evaluation was interrupted, please re-edit to retry</i>";
+
+impl<'e, C: IController> Handler<'e, C>
+where
+    C::EvalProcessingImpl: 'static,
+{
+    /// Picks up evals that a previous run left at "Processing...".
+    pub(crate) async fn resume_pending_evals(&self) {
+        let pending = self.controller.pending_evals().await;
+        if !pending.is_empty() {
+            info!(count = pending.len(), "settling pending evals");
+        }
+        for pending in pending {
+            if let Err(e) = self.settle_pending_eval(pending).await {
+                error!(?e, "Error settling pending eval");
+            }
+        }
+    }
+
+    async fn settle_pending_eval(
+        &self,
+        pending: PendingEval<EvalProcessingResponse<C::EvalProcessingImpl>>,
+    ) -> HandlerResult<()> {
+        let (chat_id, eval_msg_id, text) = match &pending {
+            PendingEval::Resume {
+                chat_id,
+                eval_msg_id,
+                ..
+            } => (*chat_id, *eval_msg_id, PROCESSING_MESSAGE_TEXT),
+            PendingEval::Abandoned {
+                chat_id,
+                eval_msg_id,
+            } => (*chat_id, *eval_msg_id, ABANDONED_MESSAGE_TEXT),
+        };
+        let chat_target = ChatTarget::Id(ChatId(chat_id));
+        self.client
+            .call(
+                &EditMessageText::new(chat_target.clone(), MessageId(eval_msg_id), text)
+                    .parse_mode(ParseMode::HTML),
+            )
+            .await?;
+        if let PendingEval::Resume { processing, .. } = pending {
+            let client = self.client.clone();
+            let cancel_event = self.cancel_event.listen();
+            self.tasks.spawn(async move {
+                let res = continue_processing(
+                    chat_target,
+                    eval_msg_id,
+                    client,
+                    processing,
+                    cancel_event,
+                    true,
+                )
+                .await;
+                if let Err(e) = res {
+                    error!("Error in continue_processing: {:?}", e);
+                }
+            });
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,156 @@
+use std::{
+    collections::HashMap,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use compio::time::sleep;
+
+/// Chats whose state is kept before idle ones get dropped.
+const MAX_TRACKED_CHATS: usize = 1024;
+
+/// Allows `count` events per `period`, in bursts of up to `count`.
+#[derive(Debug, Clone, Copy)]
+struct Rate {
+    count: u32,
+    period: Duration,
+}
+
+/// Telegram's documented limits for bots sending messages.
+const GLOBAL_RATE: Rate = Rate {
+    count: 30,
+    period: Duration::from_secs(1),
+};
+const GROUP_RATE: Rate = Rate {
+    count: 20,
+    period: Duration::from_secs(60),
+};
+const PRIVATE_RATE: Rate = Rate {
+    count: 1,
+    period: Duration::from_secs(1),
+};
+
+/// A generic cell rate limiter, tracking when the next event would be due at the steady rate.
+#[derive(Debug, Clone, Copy)]
+struct RateLimiter {
+    rate: Rate,
+    theoretical_arrival: Option<Instant>,
+}
+
+impl RateLimiter {
+    fn new(rate: Rate) -> Self {
+        Self {
+            rate,
+            theoretical_arrival: None,
+        }
+    }
+
+    /// Reserves a slot for an event, returning how long to wait before it may happen.
+    fn reserve(&mut self, now: Instant) -> Duration {
+        let interval = self.rate.period / self.rate.count;
+        let tolerance = self.rate.period - interval;
+        let arrival = self.theoretical_arrival.map_or(now, |t| t.max(now));
+        self.theoretical_arrival = Some(arrival + interval);
+        arrival
+            .checked_sub(tolerance)
+            .unwrap_or(now)
+            .saturating_duration_since(now)
+    }
+
+    fn is_idle(&self, now: Instant) -> bool {
+        self.theoretical_arrival.is_none_or(|t| t <= now)
+    }
+}
+
+/// Spaces out outgoing messages so that Telegram does not start answering with 429.
+#[derive(Debug)]
+pub(super) struct TgRateLimits {
+    inner: Mutex<TgRateLimitsInner>,
+}
+
+#[derive(Debug)]
+struct TgRateLimitsInner {
+    global: RateLimiter,
+    chats: HashMap<i64, RateLimiter>,
+}
+
+impl Default for TgRateLimits {
+    fn default() -> Self {
+        Self {
+            inner: Mutex::new(TgRateLimitsInner {
+                global: RateLimiter::new(GLOBAL_RATE),
+                chats: HashMap::new(),
+            }),
+        }
+    }
+}
+
+impl TgRateLimits {
+    /// Waits until a message may be sent to `chat_id`.
+    pub(super) async fn acquire(&self, chat_id: i64) {
+        let wait = self.reserve(chat_id, Instant::now());
+        if !wait.is_zero() {
+            sleep(wait).await;
+        }
+    }
+
+    /// Counts a message sent to `chat_id` without waiting for its turn.
+    pub(super) fn record(&self, chat_id: i64) {
+        self.reserve(chat_id, Instant::now());
+    }
+
+    fn reserve(&self, chat_id: i64, now: Instant) -> Duration {
+        let mut inner = self.inner.lock().unwrap();
+        if inner.chats.len() >= MAX_TRACKED_CHATS {
+            inner.chats.retain(|_, limiter| !limiter.is_idle(now));
+        }
+        // Group and channel ids are negative
+        let rate = if chat_id < 0 {
+            GROUP_RATE
+        } else {
+            PRIVATE_RATE
+        };
+        let chat_wait = inner
+            .chats
+            .entry(chat_id)
+            .or_insert_with(|| RateLimiter::new(rate))
+            .reserve(now);
+        chat_wait.max(inner.global.reserve(now))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rate_limiter() {
+        let now = Instant::now();
+        let mut limiter = RateLimiter::new(GROUP_RATE);
+        for _ in 0..20 {
+            assert_eq!(limiter.reserve(now), Duration::ZERO);
+        }
+        assert_eq!(limiter.reserve(now), Duration::from_secs(3));
+        assert_eq!(limiter.reserve(now), Duration::from_secs(6));
+        assert!(!limiter.is_idle(now + Duration::from_secs(60)));
+        assert!(limiter.is_idle(now + Duration::from_secs(66)));
+    }
+
+    #[test]
+    fn test_private_chat() {
+        let now = Instant::now();
+        let limits = TgRateLimits::default();
+        assert_eq!(limits.reserve(42, now), Duration::ZERO);
+        assert_eq!(limits.reserve(42, now), Duration::from_secs(1));
+        assert_eq!(limits.reserve(43, now), Duration::ZERO);
+        let later = now + Duration::from_secs(5);
+        assert_eq!(limits.reserve(42, later), Duration::ZERO);
+    }
+
+    #[test]
+    fn test_record() {
+        let limits = TgRateLimits::default();
+        limits.record(42);
+        assert!(!limits.reserve(42, Instant::now()).is_zero());
+    }
+}
@@ -1,61 +1,368 @@
+use std::borrow::Cow;
+
 use htmlize::escape_text;
 use telegram_types::bot::types::{
     InlineKeyboardButton, InlineKeyboardButtonPressed, InlineKeyboardMarkup,
 };
 
-use crate::controller::EvalPageData;
+use crate::controller::{EvalArtifact, EvalPageData, EvalPageState};
+
+/// Callback state name and button label of each artifact page.
+pub(super) const ARTIFACT_STATES: &[(&str, &str, EvalArtifact)] = &[
+    ("asm", "ASM", EvalArtifact::Asm),
+    ("llvm", "LLVM", EvalArtifact::LlvmIr),
+    ("mir", "MIR", EvalArtifact::Mir),
+    ("hir", "HIR", EvalArtifact::Hir),
+    ("wasm", "WASM", EvalArtifact::Wasm),
+    ("expand", "Expand", EvalArtifact::MacroExpansion),
+];
+
+pub(super) fn state_name(state: EvalPageState) -> &'static str {
+    match state {
+        EvalPageState::Output => "output",
+        EvalPageState::Build => "build",
+        EvalPageState::Miri => "miri",
+        EvalPageState::Clippy => "clippy",
+        EvalPageState::Format => "fmt",
+        EvalPageState::Artifact(artifact) => ARTIFACT_STATES
+            .iter()
+            .find(|(.., a)| *a == artifact)
+            .map_or("", |(name, ..)| name),
+    }
+}
+
+pub(super) fn parse_state(name: &str) -> Option<EvalPageState> {
+    match name {
+        "output" => Some(EvalPageState::Output),
+        "build" => Some(EvalPageState::Build),
+        "miri" => Some(EvalPageState::Miri),
+        "clippy" => Some(EvalPageState::Clippy),
+        "fmt" => Some(EvalPageState::Format),
+        _ => ARTIFACT_STATES
+            .iter()
+            .find(|(n, ..)| *n == name)
+            .map(|&(.., artifact)| EvalPageState::Artifact(artifact)),
+    }
+}
+
+/// Telegram counts the text left after parsing entities, in UTF-16 code units.
+const MESSAGE_TEXT_LIMIT: usize = 4096;
+/// Room kept for the omission marker.
+const OMITTED_MARKER_RESERVE: usize = 64;
+/// Room kept for the title's page counter.
+const PAGE_LABEL_RESERVE: usize = 16;
+
+pub(super) struct RenderedPage {
+    pub(super) text: String,
+    pub(super) keyboard: InlineKeyboardMarkup,
+    /// The full content, when it does not fit into a single message, either cut or spread over
+    /// several pages.
+    pub(super) document: Option<PageDocument>,
+}
+
+pub(super) struct PageDocument {
+    pub(super) file_name: String,
+    pub(super) caption: String,
+    pub(super) content: String,
+}
 
-pub(super) fn render_page_data(data: EvalPageData) -> (String, InlineKeyboardMarkup) {
-    // TODO: trim into 4096 characters
+pub(super) fn render_page_data(data: EvalPageData) -> RenderedPage {
+    let limit = MESSAGE_TEXT_LIMIT.saturating_sub(utf16_len(&data.title) + 1 + PAGE_LABEL_RESERVE);
+    // Program output is paged, everything else keeps its head and tail
+    let mut page_nav = None;
+    let paged = matches!(data.state, EvalPageState::Output | EvalPageState::Build);
+    let body = if paged {
+        let pages = paginate(&data.content, limit);
+        let page = (data.page as usize).min(pages.len() - 1);
+        if pages.len() > 1 {
+            page_nav = Some((page, pages.len()));
+        }
+        Cow::Borrowed(pages[page])
+    } else {
+        truncate_content(&data.content, limit).map_or(Cow::Borrowed(&*data.content), Cow::Owned)
+    };
+    let title = match page_nav {
+        Some((page, count)) => Cow::Owned(format!("{} ({}/{count})", data.title, page + 1)),
+        None => Cow::Borrowed(&*data.title),
+    };
     let text = format!(
         "<b>{}</b>\n<blockquote expandable><code>{}</code></blockquote>",
-        escape_text(data.title),
-        escape_text(data.content)
+        escape_text(title),
+        escape_text(body)
     );
-    let keyboard = InlineKeyboardMarkup {
-        inline_keyboard: vec![vec![
-            InlineKeyboardButton {
-                text: match (data.has_fatal_error, data.has_error, data.has_warning) {
+    let document = (utf16_len(&data.content) > limit).then(|| PageDocument {
+        file_name: format!("eval-{}.txt", data.revision_id),
+        caption: data.title.into_owned(),
+        content: data.content,
+    });
+    let mut buttons = vec![
+        InlineKeyboardButton {
+            text: {
+                let status = match (data.has_fatal_error, data.has_error, data.has_warning) {
                     (true, _, _) => "👻",
                     (false, true, _) => "❌️",
                     (false, false, true) => "⚠️",
                     (false, false, false) => "✅",
+                };
+                match data.diagnostic_count {
+                    0 => status.into(),
+                    count => format!("{status}{count}"),
                 }
-                .into(),
-                pressed: InlineKeyboardButtonPressed::CallbackData(format!(
-                    "v1:state:build:{}",
+            },
+            pressed: InlineKeyboardButtonPressed::CallbackData(format!(
+                "v1:state:build:{}",
+                data.revision_id
+            )),
+        },
+        InlineKeyboardButton {
+            text: match data.revision {
+                0 => "📃".into(),
+                rev => format!("📃{rev}"),
+            },
+            pressed: InlineKeyboardButtonPressed::CallbackData(format!(
+                "v1:state:output:{}",
+                data.revision_id
+            )),
+        },
+        InlineKeyboardButton {
+            text: "🔬".into(),
+            pressed: InlineKeyboardButtonPressed::CallbackData(format!(
+                "v1:state:miri:{}",
+                data.revision_id
+            )),
+        },
+        InlineKeyboardButton {
+            text: "🧹".into(),
+            pressed: InlineKeyboardButtonPressed::CallbackData(format!(
+                "v1:state:fmt:{}",
+                data.revision_id
+            )),
+        },
+        InlineKeyboardButton {
+            text: "🔗".into(),
+            pressed: if let Some(perma_link) = data.perma_link {
+                InlineKeyboardButtonPressed::Url(perma_link)
+            } else {
+                InlineKeyboardButtonPressed::CallbackData(format!(
+                    "v1:genlink:{}",
                     data.revision_id
-                )),
+                ))
             },
+        },
+        InlineKeyboardButton {
+            text: "🗑️".into(),
+            pressed: InlineKeyboardButtonPressed::CallbackData(format!(
+                "v1:del:{}",
+                data.revision_id
+            )),
+        },
+    ];
+    if let Some(lint_count) = data.lint_count {
+        buttons.insert(
+            4,
             InlineKeyboardButton {
-                text: match data.revision {
-                    0 => "📃".into(),
-                    rev => format!("📃{rev}"),
+                text: match lint_count {
+                    0 => "📎".into(),
+                    count => format!("📎{count}"),
                 },
                 pressed: InlineKeyboardButtonPressed::CallbackData(format!(
-                    "v1:state:output:{}",
+                    "v1:state:clippy:{}",
                     data.revision_id
                 )),
             },
-            InlineKeyboardButton {
-                text: "🔗".into(),
-                pressed: if let Some(perma_link) = data.perma_link {
-                    InlineKeyboardButtonPressed::Url(perma_link)
-                } else {
-                    InlineKeyboardButtonPressed::CallbackData(format!(
-                        "v1:genlink:{}",
-                        data.revision_id
-                    ))
-                },
-            },
-            InlineKeyboardButton {
-                text: "🗑️".into(),
+        );
+    }
+    let mut inline_keyboard = vec![buttons];
+    if let Some((page, count)) = page_nav {
+        let state = state_name(data.state);
+        let mut nav_buttons = vec![];
+        if page > 0 {
+            nav_buttons.push(InlineKeyboardButton {
+                text: "◀".into(),
                 pressed: InlineKeyboardButtonPressed::CallbackData(format!(
-                    "v1:del:{}",
+                    "v1:page:{state}:{}:{}",
+                    page - 1,
                     data.revision_id
                 )),
-            },
-        ]],
+            });
+        }
+        if page + 1 < count {
+            nav_buttons.push(InlineKeyboardButton {
+                text: "▶".into(),
+                pressed: InlineKeyboardButtonPressed::CallbackData(format!(
+                    "v1:page:{state}:{}:{}",
+                    page + 1,
+                    data.revision_id
+                )),
+            });
+        }
+        inline_keyboard.push(nav_buttons);
+    }
+    let artifact_buttons: Vec<_> = ARTIFACT_STATES
+        .iter()
+        .filter(|(_, _, artifact)| data.artifacts.contains(artifact))
+        .map(|(state, label, _)| InlineKeyboardButton {
+            text: (*label).into(),
+            pressed: InlineKeyboardButtonPressed::CallbackData(format!(
+                "v1:state:{state}:{}",
+                data.revision_id
+            )),
+        })
+        .collect();
+    if !artifact_buttons.is_empty() {
+        inline_keyboard.push(artifact_buttons);
+    }
+    RenderedPage {
+        text,
+        keyboard: InlineKeyboardMarkup { inline_keyboard },
+        document,
+    }
+}
+
+fn utf16_len(s: &str) -> usize {
+    s.chars().map(char::len_utf16).sum()
+}
+
+/// Splits `content` into pages within `limit`, breaking at line breaks where possible.
+fn paginate(content: &str, limit: usize) -> Vec<&str> {
+    let content = content.trim_end_matches('\n');
+    let mut pages = vec![];
+    let mut start = 0;
+    let mut used = 0;
+    for (idx, c) in content.char_indices() {
+        if used + c.len_utf16() > limit {
+            let end = content[start..idx]
+                .rfind('\n')
+                .map_or(idx, |line_end| start + line_end + 1);
+            pages.push(content[start..end].trim_end_matches('\n'));
+            used = utf16_len(&content[end..idx]);
+            start = end;
+        }
+        used += c.len_utf16();
+    }
+    pages.push(&content[start..]);
+    pages
+}
+
+/// Keeps the head and the tail of `content` within `limit`, cutting at line breaks where
+/// possible. Returns `None` if it already fits.
+fn truncate_content(content: &str, limit: usize) -> Option<String> {
+    if utf16_len(content) <= limit {
+        return None;
+    }
+    let content = content.trim_end_matches('\n');
+    let budget = limit.saturating_sub(OMITTED_MARKER_RESERVE) / 2;
+
+    let mut head_end = 0;
+    let mut used = 0;
+    for (idx, c) in content.char_indices() {
+        used += c.len_utf16();
+        if used > budget {
+            break;
+        }
+        head_end = idx + c.len_utf8();
+    }
+    if let Some(line_end) = content[..head_end].rfind('\n') {
+        head_end = line_end + 1;
+    }
+
+    let mut tail_start = content.len();
+    let mut used = 0;
+    for (idx, c) in content.char_indices().rev() {
+        used += c.len_utf16();
+        if used > budget {
+            break;
+        }
+        tail_start = idx;
+    }
+    if let Some(line_end) = content[tail_start..].find('\n') {
+        tail_start += line_end + 1;
+    }
+
+    let (head, omitted, tail) = (
+        &content[..head_end],
+        &content[head_end..tail_start],
+        &content[tail_start..],
+    );
+    let omitted_lines = omitted.lines().count();
+    let separator = if head.is_empty() || head.ends_with('\n') {
+        ""
+    } else {
+        "\n"
     };
-    (text, keyboard)
+    let plural = if omitted_lines == 1 { "" } else { "s" };
+    Some(format!(
+        "{head}{separator}… {omitted_lines} line{plural} omitted\n{tail}"
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_truncate_content() {
+        assert_eq!(truncate_content("a\nb", 100), None);
+        let content: String = (0..1000).map(|i| format!("line {i}\n")).collect();
+        let truncated = truncate_content(&content, 200).unwrap();
+        assert!(utf16_len(&truncated) <= 200);
+        assert!(truncated.starts_with("line 0\nline 1\n"));
+        assert!(truncated.ends_with("line 998\nline 999"));
+        let omitted = truncated
+            .lines()
+            .find(|line| line.starts_with('…'))
+            .unwrap();
+        let kept = truncated.lines().count() - 1;
+        assert_eq!(omitted, format!("… {} lines omitted", 1000 - kept));
+    }
+
+    #[test]
+    fn test_paginate() {
+        assert_eq!(paginate("a\nb\n", 100), vec!["a\nb"]);
+        assert_eq!(paginate("", 100), vec![""]);
+        let content: String = (0..100).map(|i| format!("line {i}\n")).collect();
+        let pages = paginate(&content, 50);
+        assert!(pages.iter().all(|page| utf16_len(page) <= 50));
+        assert_eq!(pages.join("\n"), content.trim_end());
+        assert_eq!(paginate(&"x".repeat(120), 50).len(), 3);
+    }
+
+    #[test]
+    fn test_page_document() {
+        let page_data = |state| EvalPageData {
+            perma_link: None,
+            has_warning: false,
+            has_error: false,
+            has_fatal_error: false,
+            diagnostic_count: 0,
+            lint_count: None,
+            artifacts: vec![],
+            revision: 1,
+            revision_id: 1,
+            state,
+            page: 0,
+            title: "Miri".into(),
+            content: "line\n".repeat(2000),
+        };
+        let document = render_page_data(page_data(EvalPageState::Miri))
+            .document
+            .unwrap();
+        assert_eq!(document.file_name, "eval-1.txt");
+        assert_eq!(document.content, "line\n".repeat(2000));
+        // Long program output is attached too, rather than only paged through
+        let page = render_page_data(page_data(EvalPageState::Output));
+        assert!(page.text.starts_with("<b>Miri (1/"));
+        assert_eq!(page.document.unwrap().content, "line\n".repeat(2000));
+        let mut short = page_data(EvalPageState::Output);
+        short.content = "line\n".into();
+        assert!(render_page_data(short).document.is_none());
+    }
+
+    #[test]
+    fn test_truncate_long_line() {
+        let content = "😀".repeat(1000);
+        let truncated = truncate_content(&content, 200).unwrap();
+        assert!(utf16_len(&truncated) <= 200);
+        assert!(truncated.contains("\n… 1 line omitted\n"));
+    }
 }
@@ -0,0 +1,71 @@
+use serde::{Deserialize, Serialize};
+use telegram_types::bot::{
+    methods::{ChatTarget, SendMessage},
+    types::{ChatId, Document, FileId, MessageId, ParseMode},
+};
+
+use crate::controller::IController;
+
+use super::{error::HandlerResult, tg_method::tg_method, Handler};
+
+/// Snippets longer than this are not worth sending to the playground.
+const SOURCE_FILE_LIMIT: u64 = 64 * 1024;
+
+#[derive(Debug, Serialize)]
+struct GetFile<'a> {
+    file_id: &'a FileId,
+}
+
+#[derive(Debug, Deserialize)]
+struct File {
+    file_size: Option<u64>,
+    file_path: Option<String>,
+}
+
+tg_method!(GetFile<'_>, "getFile", File);
+
+impl<'e, C: IController> Handler<'e, C> {
+    /// Reads the code of a source file sent with an eval command in its caption, replying with
+    /// the reason if it cannot be used.
+    pub(super) async fn read_source_file(
+        &self,
+        chat_id: ChatId,
+        user_msg_id: MessageId,
+        document: &Document,
+    ) -> HandlerResult<Option<String>> {
+        let reason = match self.fetch_source_file(&document.file_id).await? {
+            Ok(code) => return Ok(Some(code)),
+            Err(reason) => reason,
+        };
+        self.client
+            .call(
+                &SendMessage::new(ChatTarget::Id(chat_id), format!("<i>Error: {reason}</i>"))
+                    .parse_mode(ParseMode::HTML)
+                    .reply(user_msg_id),
+            )
+            .await?;
+        Ok(None)
+    }
+
+    async fn fetch_source_file(
+        &self,
+        file_id: &FileId,
+    ) -> HandlerResult<Result<String, &'static str>> {
+        let limit = self
+            .client
+            .download_limit()
+            .map_or(SOURCE_FILE_LIMIT, |limit| limit.min(SOURCE_FILE_LIMIT));
+        let file = self.client.call(&GetFile { file_id }).await?;
+        if file.file_size.is_some_and(|size| size > limit) {
+            return Ok(Err("Source file is too large"));
+        }
+        let Some(file_path) = file.file_path else {
+            return Ok(Err("Source file is not available"));
+        };
+        let content = self.client.download_file(&file_path).await?;
+        if content.len() as u64 > limit {
+            return Ok(Err("Source file is too large"));
+        }
+        Ok(String::from_utf8(content).map_err(|_| "Source file is not UTF-8 text"))
+    }
+}
@@ -0,0 +1,65 @@
+use std::{
+    future::Future,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+};
+
+use compio::runtime::spawn;
+use event_listener::Event;
+
+/// Keeps count of detached tasks so that shutdown can wait for them to finish.
+#[derive(Clone, Default)]
+pub(crate) struct TaskTracker {
+    inner: Arc<TaskTrackerInner>,
+}
+
+#[derive(Default)]
+struct TaskTrackerInner {
+    running: AtomicUsize,
+    finished: Event,
+}
+
+struct TaskGuard(Arc<TaskTrackerInner>);
+
+impl Drop for TaskGuard {
+    fn drop(&mut self) {
+        self.0.running.fetch_sub(1, Ordering::AcqRel);
+        self.0.finished.notify(usize::MAX);
+    }
+}
+
+impl TaskTracker {
+    pub fn spawn(&self, fut: impl Future<Output = ()> + 'static) {
+        self.inner.running.fetch_add(1, Ordering::AcqRel);
+        let guard = TaskGuard(self.inner.clone());
+        spawn(async move {
+            fut.await;
+            drop(guard);
+        })
+        .detach();
+    }
+
+    pub fn running(&self) -> usize {
+        self.inner.running.load(Ordering::Acquire)
+    }
+
+    pub async fn wait(&self) {
+        self.wait_below(1).await
+    }
+
+    /// Waits until fewer than `limit` tasks are running.
+    pub async fn wait_below(&self, limit: usize) {
+        loop {
+            if self.running() < limit {
+                return;
+            }
+            let listener = self.inner.finished.listen();
+            if self.running() < limit {
+                return;
+            }
+            listener.await;
+        }
+    }
+}
@@ -0,0 +1,331 @@
+use std::{env::temp_dir, fs, future::Future, process, sync::Arc, time::Duration};
+
+use event_listener::Event;
+use futures::future::join;
+use rusqlite::Connection;
+use serde_json::{json, Value};
+
+use crate::{
+    config::ChatPolicy,
+    controller::Controller,
+    repository::{self, playground_record::PlaygroundRustToolchain},
+    service::playground::PlaygroundService,
+    testing::{FakeBotApi, FakePlayground},
+};
+
+use super::{
+    new_message::PROCESSING_MESSAGE_TEXT, run_loop, Handler, TaskTracker, TgClient, TgEnv,
+};
+
+const CHAT_ID: i64 = -100;
+const USER_ID: i64 = 42;
+const OTHER_USER_ID: i64 = 43;
+
+async fn start() -> (FakeBotApi, FakePlayground) {
+    let (bot, playground) = join(FakeBotApi::start(), FakePlayground::start()).await;
+    playground.respond("/execute", execute_response("hi\n"));
+    (bot, playground)
+}
+
+fn execute_response(stdout: &str) -> Value {
+    json!({
+        "success": true,
+        "exitDetail": "",
+        "stdout": stdout,
+        "stderr": "   Compiling playground v0.0.1 (/playground)\n    Finished `dev` profile [unoptimized + debuginfo] target(s) in 0.50s\n     Running `target/debug/playground`\n",
+    })
+}
+
+fn allow_all() -> ChatPolicy {
+    ChatPolicy {
+        allow_by_default: true,
+        ..Default::default()
+    }
+}
+
+/// Runs the bot against the fakes until `scenario` is done.
+async fn run_bot(
+    bot: &FakeBotApi,
+    playground: &FakePlayground,
+    chat_policy: ChatPolicy,
+    scenario: impl Future<Output = ()>,
+) {
+    run_bot_in_mode(bot, playground, chat_policy, false, scenario).await
+}
+
+/// Like [`run_bot`], treating the fake as a local Bot API server if `local_mode` is set.
+async fn run_bot_in_mode(
+    bot: &FakeBotApi,
+    playground: &FakePlayground,
+    chat_policy: ChatPolicy,
+    local_mode: bool,
+    scenario: impl Future<Output = ()>,
+) {
+    let repo = repository::init_db(Connection::open_in_memory().unwrap()).unwrap();
+    let controller = Controller::new(
+        repo,
+        PlaygroundService::new(playground.url.clone()),
+        PlaygroundRustToolchain::default(),
+    );
+    let cancel_event = Event::new();
+    let tasks = TaskTracker::default();
+    let handler = Handler {
+        client: Arc::new(TgClient::new(
+            &bot.url,
+            "123:TEST".into(),
+            TgEnv::Prod,
+            local_mode,
+        )),
+        controller,
+        cancel_event: &cancel_event,
+        tasks: tasks.clone(),
+        chat_policy,
+    };
+    join(run_loop(handler), async {
+        scenario.await;
+        cancel_event.notify(usize::MAX);
+    })
+    .await;
+    tasks.wait().await;
+}
+
+/// Expects the bot to answer an eval command, returning the id and final text of its message.
+async fn expect_eval(bot: &FakeBotApi) -> (i64, Value) {
+    let processing = bot.next_call("sendMessage").await;
+    assert_eq!(processing.params["text"], PROCESSING_MESSAGE_TEXT);
+    let eval_msg_id = processing.result["message_id"].as_i64().unwrap();
+    let output = bot.next_call("editMessageText").await;
+    assert_eq!(output.params["message_id"], eval_msg_id);
+    (eval_msg_id, output.params)
+}
+
+fn text(params: &Value) -> &str {
+    params["text"].as_str().unwrap_or_default()
+}
+
+fn callback_data(params: &Value, prefix: &str) -> String {
+    params["reply_markup"]["inline_keyboard"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .flat_map(|row| row.as_array().into_iter().flatten())
+        .filter_map(|button| button["callback_data"].as_str())
+        .find(|data| data.starts_with(prefix))
+        .unwrap_or_else(|| panic!("no {prefix} button in {params}"))
+        .into()
+}
+
+#[compio::test]
+async fn test_eval() {
+    let (bot, playground) = start().await;
+    bot.push_message(CHAT_ID, USER_ID, 1, "/bval println!(\"hi\");");
+    run_bot(&bot, &playground, allow_all(), async {
+        let (_, output) = expect_eval(&bot).await;
+        assert!(text(&output).contains("<b>Output</b>"));
+        assert!(text(&output).contains("hi"));
+        callback_data(&output, "v1:del:");
+    })
+    .await;
+    let requests = playground.requests("/execute");
+    assert_eq!(requests.len(), 1);
+    assert!(requests[0]["code"]
+        .as_str()
+        .unwrap()
+        .contains("println!(\"hi\");"));
+}
+
+#[compio::test]
+async fn test_edit_reruns_eval() {
+    let (bot, playground) = start().await;
+    bot.push_message(CHAT_ID, USER_ID, 1, "/bval println!(\"hi\");");
+    run_bot(&bot, &playground, allow_all(), async {
+        let (eval_msg_id, _) = expect_eval(&bot).await;
+        playground.respond("/execute", execute_response("bye\n"));
+        bot.push_edited_message(CHAT_ID, USER_ID, 1, "/bval println!(\"bye\");");
+        let processing = bot.next_call("editMessageText").await;
+        assert_eq!(processing.params["message_id"], eval_msg_id);
+        assert_eq!(processing.params["text"], PROCESSING_MESSAGE_TEXT);
+        let output = bot.next_call("editMessageText").await;
+        assert_eq!(output.params["message_id"], eval_msg_id);
+        assert!(text(&output.params).contains("bye"));
+        assert!(bot.pending_calls().is_empty());
+    })
+    .await;
+}
+
+#[compio::test]
+async fn test_edit_after_eval_message_deleted() {
+    let (bot, playground) = start().await;
+    bot.push_message(CHAT_ID, USER_ID, 1, "/bval println!(\"hi\");");
+    run_bot(&bot, &playground, allow_all(), async {
+        let (eval_msg_id, _) = expect_eval(&bot).await;
+        bot.fail_next("editMessageText", "Bad Request: message to edit not found");
+        bot.push_edited_message(CHAT_ID, USER_ID, 1, "/bval println!(\"hi\");");
+        let failed = bot.next_call("editMessageText").await;
+        assert_eq!(failed.params["message_id"], eval_msg_id);
+        let (new_eval_msg_id, output) = expect_eval(&bot).await;
+        assert_ne!(new_eval_msg_id, eval_msg_id);
+        assert!(text(&output).contains("hi"));
+    })
+    .await;
+}
+
+#[compio::test]
+async fn test_switch_state() {
+    let (bot, playground) = start().await;
+    bot.push_message(CHAT_ID, USER_ID, 1, "/bval println!(\"hi\");");
+    run_bot(&bot, &playground, allow_all(), async {
+        let (eval_msg_id, output) = expect_eval(&bot).await;
+        let data = callback_data(&output, "v1:state:build:");
+        bot.push_callback_query(CHAT_ID, USER_ID, eval_msg_id, &data);
+        let build = bot.next_call("editMessageText").await;
+        assert_eq!(build.params["message_id"], eval_msg_id);
+        assert!(text(&build.params).contains("<b>Stderr</b>"));
+        let answer = bot.next_call("answerCallbackQuery").await;
+        assert_eq!(answer.params["text"], Value::Null);
+
+        let data = callback_data(&build.params, "v1:state:output:");
+        bot.push_callback_query(CHAT_ID, OTHER_USER_ID, eval_msg_id, &data);
+        let answer = bot.next_call("answerCallbackQuery").await;
+        assert_eq!(
+            answer.params["text"],
+            "Only the original sender can switch state"
+        );
+        assert!(bot.pending_calls().is_empty());
+    })
+    .await;
+}
+
+#[compio::test]
+async fn test_lazy_miri() {
+    let (bot, playground) = start().await;
+    playground.respond_after(
+        "/miri",
+        Duration::from_millis(200),
+        json!({ "success": true, "stdout": "hi\n", "stderr": "" }),
+    );
+    bot.push_message(CHAT_ID, USER_ID, 1, "/bval println!(\"hi\");");
+    run_bot(&bot, &playground, allow_all(), async {
+        let (eval_msg_id, output) = expect_eval(&bot).await;
+        let data = callback_data(&output, "v1:state:miri:");
+        bot.push_callback_query(CHAT_ID, USER_ID, eval_msg_id, &data);
+        let processing = bot.next_call("editMessageText").await;
+        assert_eq!(processing.params["text"], PROCESSING_MESSAGE_TEXT);
+        // Answered while Miri is still running
+        bot.next_call("answerCallbackQuery").await;
+        assert!(bot.pending_calls().is_empty());
+        let miri = bot.next_call("editMessageText").await;
+        assert_eq!(miri.params["message_id"], eval_msg_id);
+        assert!(text(&miri.params).contains("<b>Miri</b>"));
+        callback_data(&miri.params, "v1:state:output:");
+    })
+    .await;
+}
+
+#[compio::test]
+async fn test_delete() {
+    let (bot, playground) = start().await;
+    bot.push_message(CHAT_ID, USER_ID, 1, "/bval println!(\"hi\");");
+    run_bot(&bot, &playground, allow_all(), async {
+        let (eval_msg_id, output) = expect_eval(&bot).await;
+        let data = callback_data(&output, "v1:del:");
+        bot.push_callback_query(CHAT_ID, OTHER_USER_ID, eval_msg_id, &data);
+        let answer = bot.next_call("answerCallbackQuery").await;
+        assert_eq!(answer.params["text"], "Only the original sender can delete");
+        assert!(bot.pending_calls().is_empty());
+
+        bot.push_callback_query(CHAT_ID, USER_ID, eval_msg_id, &data);
+        let delete = bot.next_call("deleteMessage").await;
+        assert_eq!(delete.params["chat_id"], CHAT_ID);
+        assert_eq!(delete.params["message_id"], eval_msg_id);
+        bot.next_call("answerCallbackQuery").await;
+    })
+    .await;
+}
+
+#[compio::test]
+async fn test_unknown_callback() {
+    let (bot, playground) = start().await;
+    bot.push_callback_query(CHAT_ID, USER_ID, 1, "v0:del:1");
+    run_bot(&bot, &playground, allow_all(), async {
+        let answer = bot.next_call("answerCallbackQuery").await;
+        assert_eq!(answer.params["text"], "Unknown command");
+    })
+    .await;
+}
+
+#[compio::test]
+async fn test_ignored_messages() {
+    let (bot, playground) = start().await;
+    let chat_policy = ChatPolicy {
+        allow: vec![CHAT_ID],
+        ..Default::default()
+    };
+    bot.push_message(CHAT_ID, USER_ID, 1, "hello");
+    bot.push_message(CHAT_ID, USER_ID, 2, "/bvalue 1");
+    bot.push_message(-200, USER_ID, 1, "/bval 1");
+    bot.push_callback_query(-200, USER_ID, 1, "v1:del:1");
+    run_bot(&bot, &playground, chat_policy, async {
+        bot.wait_handled().await;
+        assert!(bot.pending_calls().is_empty());
+    })
+    .await;
+    assert!(playground.requests("/execute").is_empty());
+}
+
+#[compio::test]
+async fn test_source_file() {
+    let (bot, playground) = start().await;
+    bot.add_file("file-1", "documents/file_1.rs", b"println!(\"hi\");");
+    bot.push_document(CHAT_ID, USER_ID, 1, "/bval", "file-1");
+    run_bot(&bot, &playground, allow_all(), async {
+        let (_, output) = expect_eval(&bot).await;
+        assert!(text(&output).contains("hi"));
+        let download = bot.next_call("download").await;
+        assert_eq!(download.params["file_path"], "documents/file_1.rs");
+    })
+    .await;
+    let requests = playground.requests("/execute");
+    assert!(requests[0]["code"]
+        .as_str()
+        .unwrap()
+        .contains("println!(\"hi\");"));
+}
+
+#[compio::test]
+async fn test_local_source_file() {
+    let (bot, playground) = start().await;
+    // A local server hands out paths on the file system it shares with the bot
+    let path = temp_dir().join(format!("ebrz-test-{}.rs", process::id()));
+    let code = b"println!(\"hi\");";
+    fs::write(&path, code).unwrap();
+    bot.add_file("file-1", path.to_str().unwrap(), code);
+    bot.push_document(CHAT_ID, USER_ID, 1, "/bval", "file-1");
+    run_bot_in_mode(&bot, &playground, allow_all(), true, async {
+        let (_, output) = expect_eval(&bot).await;
+        assert!(text(&output).contains("hi"));
+        bot.next_call("getFile").await;
+        assert!(bot.pending_calls().is_empty());
+    })
+    .await;
+    fs::remove_file(&path).unwrap();
+    assert_eq!(playground.requests("/execute").len(), 1);
+}
+
+#[compio::test]
+async fn test_source_file_too_large() {
+    let (bot, playground) = start().await;
+    bot.add_file("file-1", "documents/file_1.rs", &[b'/'; 65 * 1024]);
+    bot.push_document(CHAT_ID, USER_ID, 1, "/bval", "file-1");
+    run_bot(&bot, &playground, allow_all(), async {
+        let reply = bot.next_call("sendMessage").await;
+        assert_eq!(
+            text(&reply.params),
+            "<i>Error: Source file is too large</i>"
+        );
+        bot.next_call("getFile").await;
+        assert!(bot.pending_calls().is_empty());
+    })
+    .await;
+    assert!(playground.requests("/execute").is_empty());
+}
@@ -1,15 +1,56 @@
-use std::fmt::Debug;
+use std::{
+    sync::Arc,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
 
-use cyper::Client;
+use compio::time::sleep;
 use cyper::Error as CyperError;
-use serde::{de::DeserializeOwned, Serialize};
+use cyper::{Client, RequestBuilder};
+use serde::{de::DeserializeOwned, ser::Error as _, Deserialize};
 use telegram_types::bot::methods::TelegramResult;
-use tracing::instrument;
+use tracing::{instrument, warn};
+
+use super::{
+    error::{HandlerError, HandlerResult},
+    rate_limit::TgRateLimits,
+    tg_method::TgMethod,
+};
+
+const CLOUD_UPLOAD_LIMIT: u64 = 50 * 1024 * 1024;
+const CLOUD_DOWNLOAD_LIMIT: u64 = 20 * 1024 * 1024;
+const LOCAL_UPLOAD_LIMIT: u64 = 2000 * 1024 * 1024;
+/// Attempts made for a paced call before giving up on flood control or transient failures.
+const MAX_ATTEMPTS: u32 = 4;
+const RETRY_BACKOFF: Duration = Duration::from_millis(500);
 
 #[derive(Debug, Clone)]
 pub(crate) struct TgClient {
     client: Client,
     server_url: String,
+    file_url: String,
+    /// Talking to a self-hosted Bot API server started with `--local`.
+    local_mode: bool,
+    limits: Arc<TgRateLimits>,
+}
+
+/// The part of a failed response that tells when to retry.
+#[derive(Debug, Default, Deserialize)]
+struct FloodControl {
+    parameters: Option<FloodControlParameters>,
+}
+
+#[derive(Debug, Deserialize)]
+struct FloodControlParameters {
+    retry_after: Option<u64>,
+}
+
+/// How a request deals with rate limits and failures.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Pacing {
+    /// Sent once and right away, for the update loop which must never wait.
+    Immediate,
+    /// Waits for its turn and retries, for tasks spawned off the update loop.
+    Paced,
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
@@ -19,37 +60,230 @@ pub(crate) enum TgEnv {
 }
 
 impl TgClient {
-    pub fn new(tg_api_key: String, env: TgEnv) -> Self {
-        let server_url = match env {
-            TgEnv::Test => format!("https://api.telegram.org/bot{tg_api_key}/test/"),
-            TgEnv::Prod => format!("https://api.telegram.org/bot{tg_api_key}/"),
+    pub fn new(api_url: &str, tg_api_key: String, env: TgEnv, local_mode: bool) -> Self {
+        let (server_url, file_url) = match env {
+            TgEnv::Test => (
+                format!("{api_url}/bot{tg_api_key}/test/"),
+                format!("{api_url}/file/bot{tg_api_key}/test/"),
+            ),
+            TgEnv::Prod => (
+                format!("{api_url}/bot{tg_api_key}/"),
+                format!("{api_url}/file/bot{tg_api_key}/"),
+            ),
         };
         Self {
             client: Client::new(),
             server_url,
+            file_url,
+            local_mode,
+            limits: Arc::default(),
+        }
+    }
+
+    /// The largest file the bot can send.
+    pub fn upload_limit(&self) -> u64 {
+        if self.local_mode {
+            LOCAL_UPLOAD_LIMIT
+        } else {
+            CLOUD_UPLOAD_LIMIT
+        }
+    }
+
+    /// The largest file the bot can download, local servers have no limit.
+    pub fn download_limit(&self) -> Option<u64> {
+        (!self.local_mode).then_some(CLOUD_DOWNLOAD_LIMIT)
+    }
+
+    /// Fetches a file by the `file_path` returned from `getFile`.
+    ///
+    /// A local server returns an absolute path on its own file system, which is expected to be
+    /// shared with the bot, while the cloud API serves files over HTTP.
+    #[instrument(skip(self))]
+    pub(super) async fn download_file(&self, file_path: &str) -> HandlerResult<Vec<u8>> {
+        if self.local_mode {
+            return Ok(compio::fs::read(file_path).await?);
+        }
+        let res = self
+            .client
+            .get(format!("{}{file_path}", &self.file_url))?
+            .send()
+            .await?;
+        if !res.status().is_success() {
+            return Err(HandlerError::Download(res.status().as_u16()));
         }
+        Ok(res.bytes().await?.to_vec())
     }
 
-    #[instrument]
-    pub async fn call_method<R: DeserializeOwned + Debug>(
+    /// Calls a Bot API method, folding `ok: false` responses into
+    /// [`HandlerError::Telegram`](super::error::HandlerError::Telegram).
+    ///
+    /// Never waits on rate limits or retries, so that dispatching updates is not held up. The
+    /// message still counts against the limits that [`call_paced`](Self::call_paced) waits on.
+    #[instrument(skip(self), fields(method = M::NAME))]
+    pub(crate) async fn call<M: TgMethod>(&self, req: &M) -> HandlerResult<M::Response> {
+        self.call_json(req, Pacing::Immediate).await
+    }
+
+    /// Like [`call`](Self::call), but waits until the chat may be sent another message, waits out
+    /// flood control and retries transient failures. Only for tasks spawned off the update loop.
+    #[instrument(skip(self), fields(method = M::NAME))]
+    pub(crate) async fn call_paced<M: TgMethod>(&self, req: &M) -> HandlerResult<M::Response> {
+        self.call_json(req, Pacing::Paced).await
+    }
+
+    async fn call_json<M: TgMethod>(&self, req: &M, pacing: Pacing) -> HandlerResult<M::Response> {
+        let param = serde_json::to_value(req).map_err(CyperError::Json)?;
+        let chat_id = param.get("chat_id").and_then(serde_json::Value::as_i64);
+        let res = self
+            .send::<M::Response>(M::NAME, chat_id, pacing, || {
+                self.client
+                    .post(format!("{}{}", &self.server_url, M::NAME))?
+                    .json(&param)
+            })
+            .await?;
+        Ok(res.into_result()?)
+    }
+
+    /// Calls a method that uploads a file, sending `req` as the other multipart fields. Paced like
+    /// [`call_paced`](Self::call_paced).
+    #[instrument(skip(self, content), fields(method = M::NAME))]
+    pub(crate) async fn call_with_file<M: TgMethod>(
         &self,
-        method: &str,
-    ) -> Result<TelegramResult<R>, CyperError> {
-        let req = self.client.get(format!("{}{method}", &self.server_url))?;
-        let res = req.send().await?;
-        let res = res.json().await?;
-        Ok(res)
+        req: &M,
+        file_field: &str,
+        file_name: &str,
+        content: &[u8],
+    ) -> HandlerResult<M::Response> {
+        let serde_json::Value::Object(fields) =
+            serde_json::to_value(req).map_err(CyperError::Json)?
+        else {
+            let e = serde_json::Error::custom("method parameters must serialize to an object");
+            return Err(CyperError::Json(e).into());
+        };
+        let boundary = format!(
+            "ebrust-{:x}",
+            SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_nanos()
+        );
+        let body = encode_multipart(&boundary, &fields, file_field, file_name, content);
+        let chat_id = fields.get("chat_id").and_then(serde_json::Value::as_i64);
+        let res = self
+            .send::<M::Response>(M::NAME, chat_id, Pacing::Paced, || {
+                Ok(self
+                    .client
+                    .post(format!("{}{}", &self.server_url, M::NAME))?
+                    .header(
+                        "content-type",
+                        format!("multipart/form-data; boundary={boundary}"),
+                    )?
+                    .body(body.clone()))
+            })
+            .await?;
+        Ok(res.into_result()?)
     }
 
-    #[instrument]
-    pub async fn call_method_with_param<Q: Serialize + Debug, R: DeserializeOwned + Debug>(
+    /// Sends the request built by `build`. When paced, waits out Telegram's flood control and
+    /// retries idempotent methods on transient failures.
+    async fn send<R: DeserializeOwned>(
         &self,
         method: &str,
-        param: &Q,
+        chat_id: Option<i64>,
+        pacing: Pacing,
+        build: impl Fn() -> Result<RequestBuilder, CyperError>,
     ) -> Result<TelegramResult<R>, CyperError> {
-        let req = self.client.post(format!("{}{method}", &self.server_url))?;
-        let res = req.json(&param)?.send().await?;
-        let res = res.json().await?;
-        Ok(res)
+        let max_attempts = match pacing {
+            Pacing::Immediate => 1,
+            Pacing::Paced => MAX_ATTEMPTS,
+        };
+        let mut attempt = 1;
+        loop {
+            if let Some(chat_id) = chat_id {
+                match pacing {
+                    Pacing::Immediate => self.limits.record(chat_id),
+                    Pacing::Paced => self.limits.acquire(chat_id).await,
+                }
+            }
+            let retry_delay = match build()?.send().await {
+                Ok(res) => {
+                    let status = res.status();
+                    let body = res.bytes().await?;
+                    let flood_control =
+                        serde_json::from_slice::<FloodControl>(&body).unwrap_or_default();
+                    let retry_after = flood_control
+                        .parameters
+                        .and_then(|p| p.retry_after)
+                        .map(Duration::from_secs);
+                    match retry_after {
+                        // Nothing was done, so any method can be retried
+                        Some(retry_after) if attempt < max_attempts => {
+                            warn!(method, ?retry_after, "flood control, retrying");
+                            retry_after
+                        }
+                        _ if status.is_server_error()
+                            && is_idempotent(method)
+                            && attempt < max_attempts =>
+                        {
+                            warn!(method, %status, attempt, "server error, retrying");
+                            RETRY_BACKOFF * 2u32.pow(attempt - 1)
+                        }
+                        _ => return serde_json::from_slice(&body).map_err(CyperError::Json),
+                    }
+                }
+                Err(e)
+                    if !matches!(e, CyperError::Json(_))
+                        && is_idempotent(method)
+                        && attempt < max_attempts =>
+                {
+                    warn!(?e, method, attempt, "request error, retrying");
+                    RETRY_BACKOFF * 2u32.pow(attempt - 1)
+                }
+                Err(e) => return Err(e),
+            };
+            sleep(retry_delay).await;
+            attempt += 1;
+        }
+    }
+}
+
+/// Methods that are safe to repeat if it is unknown whether the first call took effect.
+fn is_idempotent(method: &str) -> bool {
+    ["get", "set", "delete", "edit"]
+        .iter()
+        .any(|prefix| method.starts_with(prefix))
+        || method == "answerCallbackQuery"
+}
+
+fn encode_multipart(
+    boundary: &str,
+    fields: &serde_json::Map<String, serde_json::Value>,
+    file_field: &str,
+    file_name: &str,
+    content: &[u8],
+) -> Vec<u8> {
+    let mut body = Vec::with_capacity(content.len() + 512);
+    for (name, value) in fields {
+        // Objects such as `reply_markup` are sent JSON-serialized
+        let value = match value {
+            serde_json::Value::Null => continue,
+            serde_json::Value::String(value) => value.clone(),
+            value => value.to_string(),
+        };
+        body.extend_from_slice(
+            format!(
+                "--{boundary}\r\nContent-Disposition: form-data; name=\"{name}\"\r\n\r\n{value}\r\n"
+            )
+            .as_bytes(),
+        );
     }
+    body.extend_from_slice(
+        format!(
+            "--{boundary}\r\nContent-Disposition: form-data; name=\"{file_field}\"; filename=\"{file_name}\"\r\nContent-Type: text/plain; charset=utf-8\r\n\r\n"
+        )
+        .as_bytes(),
+    );
+    body.extend_from_slice(content);
+    body.extend_from_slice(format!("\r\n--{boundary}--\r\n").as_bytes());
+    body
 }
@@ -0,0 +1,37 @@
+use std::fmt::Debug;
+
+use serde::{de::DeserializeOwned, Serialize};
+use telegram_types::bot::{
+    methods::{AnswerCallbackQuery, DeleteMessage, EditMessageText, GetUpdates, SendMessage},
+    types::{Message, Update, User},
+};
+
+/// Binds a request type to its Bot API method name and response type.
+pub(crate) trait TgMethod: Serialize + Debug {
+    const NAME: &'static str;
+    type Response: DeserializeOwned + Debug;
+}
+
+macro_rules! tg_method {
+    ($req:ty, $name:literal, $res:ty) => {
+        impl TgMethod for $req {
+            const NAME: &'static str = $name;
+            type Response = $res;
+        }
+    };
+}
+pub(super) use tg_method;
+
+#[derive(Debug, Serialize)]
+pub(crate) struct GetMe {}
+
+#[derive(Debug, Serialize)]
+pub(super) struct DeleteWebhook {}
+
+tg_method!(GetMe, "getMe", User);
+tg_method!(DeleteWebhook, "deleteWebhook", bool);
+tg_method!(GetUpdates, "getUpdates", Vec<Update>);
+tg_method!(SendMessage<'_>, "sendMessage", Message);
+tg_method!(EditMessageText<'_>, "editMessageText", Message);
+tg_method!(DeleteMessage<'_>, "deleteMessage", bool);
+tg_method!(AnswerCallbackQuery<'_>, "answerCallbackQuery", bool);
@@ -0,0 +1,229 @@
+use std::{net::SocketAddr, pin::pin, rc::Rc, time::Duration};
+
+use compio::{
+    net::{TcpListener, TcpStream},
+    time::{sleep, timeout},
+};
+use flume::Sender;
+use futures::future::{join, select, Either};
+use serde::Serialize;
+use telegram_types::bot::types::Update;
+use tracing::{debug, error, info, warn};
+
+use crate::{
+    controller::IController,
+    http::{read_request, write_response, Request},
+};
+
+use super::{
+    error::HandlerResult,
+    r#loop::dispatch_update,
+    tg_method::{tg_method, DeleteWebhook},
+    Handler, TaskTracker, TgClient,
+};
+
+const SECRET_TOKEN_HEADER: &str = "x-telegram-bot-api-secret-token";
+const ALLOWED_UPDATES: &[&str] = &["message", "edited_message", "callback_query"];
+const MAX_BODY_LEN: usize = 1024 * 1024;
+/// How long a client gets to send its whole request.
+const READ_TIMEOUT: Duration = Duration::from_secs(10);
+/// Connections served at once, more are only accepted as others finish.
+const MAX_CONNECTIONS: usize = 32;
+/// Updates taken in but not dispatched yet.
+const UPDATE_QUEUE_LEN: usize = 64;
+
+#[derive(Debug, Clone)]
+pub(crate) struct WebhookConfig {
+    /// The public URL Telegram posts updates to, usually served by a reverse proxy.
+    pub url: String,
+    pub listen_addr: SocketAddr,
+    /// The path requests arrive at, for proxies that rewrite it. The path of `url` by default.
+    pub listen_path: Option<String>,
+    pub secret_token: String,
+}
+
+#[derive(Debug, Serialize)]
+struct SetWebhook<'a> {
+    url: &'a str,
+    secret_token: &'a str,
+    allowed_updates: &'a [&'a str],
+}
+
+tg_method!(SetWebhook<'_>, "setWebhook", bool);
+
+/// Serves updates posted to `listener`, which must be bound to `config.listen_addr`.
+pub async fn run_webhook<C: IController>(
+    mut handler: Handler<'_, C>,
+    config: WebhookConfig,
+    listener: TcpListener,
+) where
+    C::EvalProcessingImpl: 'static,
+    C::LazyToolImpl: 'static,
+{
+    let mut cancel_listener = handler.cancel_event.listen();
+    while let Err(e) = set_webhook(&handler.client, &config).await {
+        error!(?e, "setWebhook error");
+        let sleep_fut = pin!(sleep(Duration::from_secs(5)));
+        if let Either::Right(_) = select(sleep_fut, &mut cancel_listener).await {
+            return;
+        }
+    }
+    info!(addr = %config.listen_addr, "listening for webhook updates");
+    let client = handler.client.clone();
+    // Connections are served concurrently, but updates are dispatched one at a time in the order
+    // they were taken in
+    let (update_tx, update_rx) = flume::bounded(UPDATE_QUEUE_LEN);
+    let accept = async move {
+        let config = Rc::new(config);
+        let connections = TaskTracker::default();
+        loop {
+            let accept_fut = pin!(async {
+                connections.wait_below(MAX_CONNECTIONS).await;
+                listener.accept().await
+            });
+            let stream = match select(accept_fut, &mut cancel_listener).await {
+                Either::Left((Ok((stream, peer)), _)) => {
+                    debug!(?peer, "webhook connection");
+                    stream
+                }
+                Either::Left((Err(e), _)) => {
+                    error!(?e, "webhook accept error");
+                    continue;
+                }
+                Either::Right(_) => break,
+            };
+            let config = config.clone();
+            let update_tx = update_tx.clone();
+            connections.spawn(async move {
+                if let Err(e) = handle_connection(&config, stream, update_tx).await {
+                    warn!(?e, "webhook connection error");
+                }
+            });
+        }
+    };
+    // Ends once the connections still open have been served and their updates dispatched
+    let dispatch = async {
+        while let Ok(update) = update_rx.recv_async().await {
+            dispatch_update(&mut handler, update).await;
+        }
+    };
+    join(accept, dispatch).await;
+    // Updates queue up on Telegram's side until the webhook is set again
+    if let Err(e) = client.call(&DeleteWebhook {}).await {
+        error!(?e, "deleteWebhook error");
+    }
+    info!("stopped receiving webhook updates");
+}
+
+async fn set_webhook(client: &TgClient, config: &WebhookConfig) -> HandlerResult<()> {
+    let req = SetWebhook {
+        url: &config.url,
+        secret_token: &config.secret_token,
+        allowed_updates: ALLOWED_UPDATES,
+    };
+    client.call(&req).await?;
+    Ok(())
+}
+
+async fn handle_connection(
+    config: &WebhookConfig,
+    mut stream: TcpStream,
+    update_tx: Sender<Update>,
+) -> HandlerResult<()> {
+    let res = match timeout(READ_TIMEOUT, read_request(&mut stream, MAX_BODY_LEN)).await {
+        Ok(res) => res?.and_then(|req| parse_update(req, config)),
+        Err(_) => Err("408 Request Timeout"),
+    };
+    // Answer only once the update is queued, so that Telegram retries updates we failed to take in
+    let status = match res {
+        Ok(update) => match update_tx.send_async(update).await {
+            Ok(()) => "200 OK",
+            Err(_) => "503 Service Unavailable",
+        },
+        Err(status) => {
+            debug!(status, "rejected webhook request");
+            status
+        }
+    };
+    write_response(&mut stream, status, vec![]).await?;
+    Ok(())
+}
+
+/// Checks that a request comes from Telegram, returning the status line to answer with if not.
+fn parse_update(req: Request, config: &WebhookConfig) -> Result<Update, &'static str> {
+    if !is_listen_path(&req.path, config) {
+        return Err("404 Not Found");
+    }
+    if req.method != "POST" {
+        return Err("405 Method Not Allowed");
+    }
+    let secret_token = req.header(SECRET_TOKEN_HEADER).unwrap_or_default();
+    if !constant_time_eq(secret_token.as_bytes(), config.secret_token.as_bytes()) {
+        return Err("401 Unauthorized");
+    }
+    serde_json::from_slice(&req.body).map_err(|e| {
+        warn!(?e, "invalid webhook update");
+        "400 Bad Request"
+    })
+}
+
+/// The path Telegram posts to, `url` having been checked to be an http(s) URL.
+fn url_path(url: &str) -> &str {
+    let rest = url.split_once("://").map_or(url, |(_, rest)| rest);
+    rest.find('/').map_or("/", |idx| &rest[idx..])
+}
+
+/// Whether requests to `path` are meant for the bot, whatever their query string.
+fn is_listen_path(path: &str, config: &WebhookConfig) -> bool {
+    let listen_path = config
+        .listen_path
+        .as_deref()
+        .unwrap_or_else(|| url_path(&config.url));
+    without_query(path) == without_query(listen_path)
+}
+
+fn without_query(path: &str) -> &str {
+    path.split_once('?').map_or(path, |(path, _)| path)
+}
+
+/// Compares without returning early, so that response times give nothing about the secret away.
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_url_path() {
+        assert_eq!(url_path("https://example.com/bot/hook"), "/bot/hook");
+        assert_eq!(url_path("https://example.com/hook?a=1"), "/hook?a=1");
+        assert_eq!(url_path("https://example.com"), "/");
+    }
+
+    #[test]
+    fn test_is_listen_path() {
+        let mut config = WebhookConfig {
+            url: "https://example.com/bot/hook?a=1".into(),
+            listen_addr: "127.0.0.1:8080".parse().unwrap(),
+            listen_path: None,
+            secret_token: "secret".into(),
+        };
+        assert!(is_listen_path("/bot/hook", &config));
+        assert!(is_listen_path("/bot/hook?a=1", &config));
+        assert!(!is_listen_path("/hook", &config));
+        // Behind a proxy stripping the /bot prefix
+        config.listen_path = Some("/hook".into());
+        assert!(is_listen_path("/hook", &config));
+        assert!(!is_listen_path("/bot/hook", &config));
+    }
+
+    #[test]
+    fn test_constant_time_eq() {
+        assert!(constant_time_eq(b"secret", b"secret"));
+        assert!(!constant_time_eq(b"secret", b"secreT"));
+        assert!(!constant_time_eq(b"secret", b"secret1"));
+        assert!(!constant_time_eq(b"", b"secret"));
+    }
+}
@@ -0,0 +1,160 @@
+use std::io;
+
+use compio::{
+    io::{AsyncRead, AsyncWrite, AsyncWriteExt},
+    net::TcpStream,
+    BufResult,
+};
+
+const MAX_HEAD_LEN: usize = 16 * 1024;
+
+/// A request read by [`read_request`], the only kind of HTTP the bot serves.
+#[derive(Debug)]
+pub(crate) struct Request {
+    pub method: String,
+    pub path: String,
+    headers: Vec<(String, String)>,
+    pub body: Vec<u8>,
+}
+
+impl Request {
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(n, _)| n.eq_ignore_ascii_case(name))
+            .map(|(_, value)| &**value)
+    }
+}
+
+/// Reads a single request, returning the status line to answer with if it is rejected.
+pub(crate) async fn read_request(
+    stream: &mut TcpStream,
+    max_body_len: usize,
+) -> io::Result<Result<Request, &'static str>> {
+    let mut buf = Vec::with_capacity(4096);
+    let head = loop {
+        if !read_more(stream, &mut buf).await? {
+            return Ok(Err("400 Bad Request"));
+        }
+        match parse_request_head(&buf) {
+            Ok(Some(head)) => break head,
+            Ok(None) if buf.len() < MAX_HEAD_LEN => {}
+            Ok(None) => return Ok(Err("431 Request Header Fields Too Large")),
+            Err(_) => return Ok(Err("400 Bad Request")),
+        }
+    };
+    let content_length = match head.request.header("content-length") {
+        Some(len) => match len.trim().parse::<usize>() {
+            Ok(len) => len,
+            Err(_) => return Ok(Err("400 Bad Request")),
+        },
+        None => 0,
+    };
+    if content_length > max_body_len {
+        return Ok(Err("413 Content Too Large"));
+    }
+    let body_end = head.head_len + content_length;
+    while buf.len() < body_end {
+        if !read_more(stream, &mut buf).await? {
+            return Ok(Err("400 Bad Request"));
+        }
+    }
+    let mut request = head.request;
+    buf.truncate(body_end);
+    request.body = buf.split_off(head.head_len);
+    Ok(Ok(request))
+}
+
+/// Answers and closes the connection.
+pub(crate) async fn write_response(
+    stream: &mut TcpStream,
+    status: &str,
+    body: Vec<u8>,
+) -> io::Result<()> {
+    let content_type = if body.is_empty() {
+        ""
+    } else {
+        "Content-Type: application/json\r\n"
+    };
+    let mut response = format!(
+        "HTTP/1.1 {status}\r\n{content_type}Content-Length: {}\r\nConnection: close\r\n\r\n",
+        body.len()
+    )
+    .into_bytes();
+    response.extend_from_slice(&body);
+    let BufResult(res, _) = stream.write_all(response).await;
+    res?;
+    stream.shutdown().await
+}
+
+/// Appends what the client sent next to `buf`, returning `false` on EOF.
+async fn read_more(stream: &mut TcpStream, buf: &mut Vec<u8>) -> io::Result<bool> {
+    if buf.len() == buf.capacity() {
+        buf.reserve(4096);
+    }
+    let BufResult(res, read_buf) = stream.read(std::mem::take(buf)).await;
+    *buf = read_buf;
+    Ok(res? > 0)
+}
+
+#[derive(Debug)]
+struct RequestHead {
+    request: Request,
+    head_len: usize,
+}
+
+/// Parses the request line and headers, returning `None` if they are incomplete.
+fn parse_request_head(buf: &[u8]) -> Result<Option<RequestHead>, httparse::Error> {
+    let mut headers = [httparse::EMPTY_HEADER; 32];
+    let mut req = httparse::Request::new(&mut headers);
+    let httparse::Status::Complete(head_len) = req.parse(buf)? else {
+        return Ok(None);
+    };
+    let headers = req
+        .headers
+        .iter()
+        .filter_map(|h| {
+            Some((
+                h.name.to_owned(),
+                std::str::from_utf8(h.value).ok()?.to_owned(),
+            ))
+        })
+        .collect();
+    Ok(Some(RequestHead {
+        request: Request {
+            method: req.method.unwrap_or_default().to_owned(),
+            path: req.path.unwrap_or_default().to_owned(),
+            headers,
+            body: vec![],
+        },
+        head_len,
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_request_head() {
+        let req = b"POST /webhook HTTP/1.1\r\nHost: example.com\r\n\
+            X-Telegram-Bot-Api-Secret-Token: s3cret\r\nContent-Length: 2\r\n\r\n{}";
+        let head = parse_request_head(req).unwrap().unwrap();
+        assert_eq!(head.request.method, "POST");
+        assert_eq!(head.request.path, "/webhook");
+        assert_eq!(head.request.header("content-length"), Some("2"));
+        assert_eq!(
+            head.request.header("x-telegram-bot-api-secret-token"),
+            Some("s3cret")
+        );
+        assert_eq!(head.head_len, req.len() - 2);
+        assert!(parse_request_head(b"POST /webhook HTTP/1.1\r\nHost:")
+            .unwrap()
+            .is_none());
+        let head = parse_request_head(b"GET / HTTP/1.1\r\n\r\n")
+            .unwrap()
+            .unwrap();
+        assert_eq!(head.request.method, "GET");
+        assert_eq!(head.request.header("content-length"), None);
+    }
+}
@@ -1,54 +1,179 @@
-use std::{env, sync::Arc};
+use std::{io, process, sync::Arc};
 
+use compio::{net::TcpListener, time::timeout};
 use event_listener::Event;
-use telegram_types::bot::types::User;
-use tracing::{debug, info, level_filters::LevelFilter};
+use futures::future::{self, join};
+use tracing::{debug, error, info, warn};
 use tracing_subscriber::EnvFilter;
 
+mod config;
 mod controller;
 mod handler;
+mod http;
 mod repository;
 mod service;
+#[cfg(test)]
+mod testing;
 
+use config::{Config, LogFormat};
 use controller::Controller;
-use handler::{run_loop, TgClient, TgEnv};
+use handler::{run_loop, run_webhook, GetMe, TaskTracker, TgClient};
+use service::{
+    backend::PlaygroundBackend, failover::FailoverPlaygroundService, local::LocalPlaygroundService,
+    playground::PlaygroundService,
+};
 
 #[compio::main]
 async fn main() {
+    let config = match Config::load() {
+        Ok(config) => config,
+        Err(e) => {
+            eprintln!("error: {e}");
+            process::exit(1);
+        }
+    };
+
     let env_filter = EnvFilter::builder()
-        .with_default_directive(LevelFilter::INFO.into())
+        .with_default_directive(config.log_level.into())
         .from_env_lossy()
         .add_directive("h2=warn".parse().unwrap());
-    tracing_subscriber::fmt().with_env_filter(env_filter).init();
+    match config.log_format {
+        LogFormat::Text => tracing_subscriber::fmt().with_env_filter(env_filter).init(),
+        LogFormat::Json => tracing_subscriber::fmt()
+            .json()
+            .with_env_filter(env_filter)
+            .init(),
+    }
 
-    let Ok(tg_api_key) = env::var("EBRZ_TG_API_KEY") else {
-        panic!("invalid EBRZ_TG_API_KEY");
+    let repo = match rusqlite::Connection::open(&config.db_path)
+        .map_err(Into::into)
+        .and_then(repository::init_db)
+    {
+        Ok(repo) => repo,
+        Err(e) => {
+            eprintln!(
+                "error: cannot open database {}: {e}",
+                config.db_path.display()
+            );
+            process::exit(1);
+        }
     };
-    let tg_server_url = match env::var("EBRZ_TG_ENV").as_deref() {
-        Ok("test") => TgEnv::Test,
-        Ok("prod") => TgEnv::Prod,
-        _ => panic!("invalid EBRZ_TG_ENV"),
-    };
-
-    let db = rusqlite::Connection::open("ebrz.db").expect("Cannot open database");
-
-    let controller = Controller::new(
-        repository::init_db(db).expect("Cannot initialize repository"),
-        service::playground::PlaygroundService::new("https://play.rust-lang.org".into()),
-    );
+    // Local entries share one service, so that max_jobs holds across them
+    let local = config.sandbox.clone().map(LocalPlaygroundService::new);
+    let backends = config
+        .playground_backends
+        .into_iter()
+        .map(|backend| {
+            info!(
+                name = %backend.name,
+                url = ?backend.url,
+                "using playground backend"
+            );
+            let service = match backend.url {
+                Some(url) => PlaygroundBackend::Remote(PlaygroundService::with_timeout(
+                    url,
+                    config.playground_timeout,
+                )),
+                None => PlaygroundBackend::Local(local.clone().expect("sandbox config")),
+            };
+            (backend.name, service)
+        })
+        .collect();
+    let playground = FailoverPlaygroundService::new(backends, config.circuit_breaker);
+    let controller = Controller::new(repo.clone(), playground, config.default_toolchain);
 
     info!("getting bot info");
-    let client = Arc::new(TgClient::new(tg_api_key, tg_server_url));
-    let me = client.call_method::<User>("getMe").await.unwrap();
+    let client = Arc::new(TgClient::new(
+        &config.tg_api_url,
+        config.tg_api_key,
+        config.tg_env,
+        config.tg_local_mode,
+    ));
+    let me = match client.call(&GetMe {}).await {
+        Ok(me) => me,
+        Err(e) => {
+            eprintln!("error: cannot get bot info: {e}");
+            process::exit(1);
+        }
+    };
     debug!(?me, "bot getMe");
     // TODO: warn when inline not enabled
+    let webhook = match config.webhook {
+        Some(webhook) => match TcpListener::bind(webhook.listen_addr).await {
+            Ok(listener) => Some((webhook, listener)),
+            Err(e) => {
+                eprintln!("error: cannot listen on {}: {e}", webhook.listen_addr);
+                process::exit(1);
+            }
+        },
+        None => None,
+    };
     let cancel_event = Event::new();
+    let tasks = TaskTracker::default();
     let handler = handler::Handler {
         client,
         controller,
         cancel_event: &cancel_event,
+        tasks: tasks.clone(),
+        chat_policy: config.chat_policy,
     };
 
-    // TODO: graceful shutdown
-    run_loop(handler).await;
+    handler.resume_pending_evals().await;
+
+    let shutdown = async {
+        match shutdown_signal().await {
+            Ok(signal) => info!(signal, "shutting down"),
+            Err(e) => {
+                error!(
+                    ?e,
+                    "cannot listen for shutdown signals, running without graceful shutdown"
+                );
+                return future::pending().await;
+            }
+        }
+        cancel_event.notify(usize::MAX);
+    };
+    match webhook {
+        Some((config, listener)) => {
+            join(run_webhook(handler, config, listener), shutdown).await;
+        }
+        None => {
+            join(run_loop(handler), shutdown).await;
+        }
+    }
+
+    info!(running = tasks.running(), "waiting for in-flight evals");
+    if timeout(config.shutdown_timeout, tasks.wait())
+        .await
+        .is_err()
+    {
+        warn!(
+            running = tasks.running(),
+            "gave up waiting for in-flight evals"
+        );
+    }
+    if let Err(e) = repo.flush().await {
+        error!(?e, "failed to flush repository");
+    }
+    info!("shutdown complete");
+}
+
+async fn shutdown_signal() -> io::Result<&'static str> {
+    #[cfg(unix)]
+    {
+        use std::pin::pin;
+
+        use futures::future::{select, Either};
+
+        let ctrl_c = pin!(compio::signal::ctrl_c());
+        let terminate = pin!(compio::signal::unix::signal(libc::SIGTERM));
+        match select(ctrl_c, terminate).await {
+            Either::Left((res, _)) => res.map(|_| "SIGINT"),
+            Either::Right((res, _)) => res.map(|_| "SIGTERM"),
+        }
+    }
+    #[cfg(not(unix))]
+    {
+        compio::signal::ctrl_c().await.map(|_| "Ctrl-C")
+    }
 }
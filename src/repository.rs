@@ -3,7 +3,10 @@ use futures::channel::oneshot;
 use rusqlite::Connection;
 use thiserror::Error;
 
+pub mod bot_update;
 mod id;
+pub mod playground_artifact;
+pub mod playground_diagnostic;
 pub mod playground_record;
 
 #[derive(Debug, Error)]
@@ -12,6 +15,10 @@ pub enum RepositoryError {
     WorkerGone,
     #[error("db error: {0}")]
     Db(#[from] rusqlite::Error),
+    #[error("json error: {0}")]
+    Json(#[from] serde_json::Error),
+    #[error("migration error: {0}")]
+    Migration(#[from] refinery::Error),
 }
 
 pub type RepositoryResult<T> = Result<T, RepositoryError>;
@@ -26,7 +33,7 @@ pub struct Repository {
 refinery::embed_migrations!("migrations");
 
 pub fn init_db(mut conn: Connection) -> RepositoryResult<Repository> {
-    migrations::runner().run(&mut conn).expect("db migration");
+    migrations::runner().run(&mut conn)?;
 
     let (tx, rx) = flume::bounded(10);
     let res = Repository { db_tx: tx };
@@ -40,6 +47,11 @@ pub fn init_db(mut conn: Connection) -> RepositoryResult<Repository> {
 }
 
 impl Repository {
+    /// Waits until the worker has run everything queued before this call.
+    pub async fn flush(&self) -> RepositoryResult<()> {
+        self.with_db(|_| Ok(())).await
+    }
+
     async fn with_db<T: Send + 'static>(
         &self,
         work: impl FnOnce(&mut Connection) -> RepositoryResult<T> + Send + 'static,
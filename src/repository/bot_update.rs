@@ -0,0 +1,77 @@
+use std::future::Future;
+
+use rusqlite::{params, OptionalExtension};
+
+use super::{Repository, RepositoryResult};
+
+/// How many update ids back handled updates are remembered.
+const HANDLED_UPDATE_WINDOW: i64 = 1000;
+
+pub trait IBotUpdateRepository {
+    /// The offset to pass to the next `getUpdates`, if any update was ever handled.
+    fn get_update_offset(&self) -> impl Future<Output = RepositoryResult<Option<i64>>>;
+    fn is_update_handled(&self, update_id: i64) -> impl Future<Output = RepositoryResult<bool>>;
+    /// Records the update as handled and moves the offset past it.
+    fn mark_update_handled(&self, update_id: i64) -> impl Future<Output = RepositoryResult<()>>;
+}
+
+impl IBotUpdateRepository for Repository {
+    async fn get_update_offset(&self) -> RepositoryResult<Option<i64>> {
+        const SELECT_UPDATE_OFFSET_SQL: &str =
+            "SELECT `update_offset` FROM `bot_update_offset` WHERE `id` = 0";
+        let res = self
+            .with_db(|conn| {
+                let mut select_update_offset_stmt =
+                    conn.prepare_cached(SELECT_UPDATE_OFFSET_SQL)?;
+                let res = select_update_offset_stmt
+                    .query_row([], |row| row.get(0))
+                    .optional()?;
+                Ok(res)
+            })
+            .await?;
+        Ok(res)
+    }
+
+    async fn is_update_handled(&self, update_id: i64) -> RepositoryResult<bool> {
+        const SELECT_HANDLED_UPDATE_SQL: &str =
+            "SELECT 1 FROM `bot_handled_update` WHERE `update_id` = ?";
+        let res = self
+            .with_db(move |conn| {
+                let mut select_handled_update_stmt =
+                    conn.prepare_cached(SELECT_HANDLED_UPDATE_SQL)?;
+                Ok(select_handled_update_stmt.exists(params![update_id])?)
+            })
+            .await?;
+        Ok(res)
+    }
+
+    async fn mark_update_handled(&self, update_id: i64) -> RepositoryResult<()> {
+        const INSERT_HANDLED_UPDATE_SQL: &str =
+            "INSERT OR IGNORE INTO `bot_handled_update` (`update_id`) VALUES (?)";
+        const UPSERT_UPDATE_OFFSET_SQL: &str =
+            "INSERT INTO `bot_update_offset` (`id`, `update_offset`)
+            VALUES (0, ?1)
+            ON CONFLICT (`id`)
+                DO UPDATE SET `update_offset` = MAX(`update_offset`, excluded.update_offset)";
+        const DELETE_OLD_HANDLED_UPDATES_SQL: &str =
+            "DELETE FROM `bot_handled_update` WHERE `update_id` < ?";
+        self.with_db(move |conn| {
+            let tx = conn.transaction()?;
+            {
+                let mut insert_handled_update_stmt =
+                    tx.prepare_cached(INSERT_HANDLED_UPDATE_SQL)?;
+                let mut upsert_update_offset_stmt = tx.prepare_cached(UPSERT_UPDATE_OFFSET_SQL)?;
+                let mut delete_old_handled_updates_stmt =
+                    tx.prepare_cached(DELETE_OLD_HANDLED_UPDATES_SQL)?;
+                insert_handled_update_stmt.execute(params![update_id])?;
+                upsert_update_offset_stmt.execute(params![update_id + 1])?;
+                delete_old_handled_updates_stmt
+                    .execute(params![update_id - HANDLED_UPDATE_WINDOW])?;
+            }
+            tx.commit()?;
+            Ok(())
+        })
+        .await?;
+        Ok(())
+    }
+}
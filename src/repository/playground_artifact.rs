@@ -0,0 +1,40 @@
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PlaygroundArtifact {
+    pub kind: PlaygroundArtifactKind,
+    pub success: bool,
+    pub code: String,
+    pub stderr: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PlaygroundArtifactKind {
+    Asm,
+    LlvmIr,
+    Mir,
+    Hir,
+    Wasm,
+    MacroExpansion,
+}
+
+impl PlaygroundArtifactKind {
+    /// Target of the playground's compile endpoint, macro expansion has an endpoint of its own.
+    pub fn target(self) -> Option<&'static str> {
+        match self {
+            PlaygroundArtifactKind::Asm => Some("asm"),
+            PlaygroundArtifactKind::LlvmIr => Some("llvm-ir"),
+            PlaygroundArtifactKind::Mir => Some("mir"),
+            PlaygroundArtifactKind::Hir => Some("hir"),
+            PlaygroundArtifactKind::Wasm => Some("wasm"),
+            PlaygroundArtifactKind::MacroExpansion => None,
+        }
+    }
+
+    pub fn requires_nightly(self) -> bool {
+        matches!(
+            self,
+            PlaygroundArtifactKind::Hir
+                | PlaygroundArtifactKind::Wasm
+                | PlaygroundArtifactKind::MacroExpansion
+        )
+    }
+}
@@ -0,0 +1,26 @@
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PlaygroundDiagnostic {
+    pub level: PlaygroundDiagnosticLevel,
+    pub code: Option<String>,
+    pub message: String,
+    pub spans: Vec<PlaygroundDiagnosticSpan>,
+    pub notes: Vec<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PlaygroundDiagnosticLevel {
+    Error,
+    Warning,
+    Note,
+    Help,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PlaygroundDiagnosticSpan {
+    pub file: String,
+    pub line: u32,
+    pub column: u32,
+    pub label: Option<String>,
+}
@@ -1,9 +1,14 @@
 use std::future::Future;
 
 use chrono::{DateTime, Utc};
-use rusqlite::{params, OptionalExtension};
+use rusqlite::{params, Connection, OptionalExtension};
 
-use super::{id::Id, Repository, RepositoryResult};
+use super::{
+    id::Id,
+    playground_artifact::{PlaygroundArtifact, PlaygroundArtifactKind},
+    playground_diagnostic::{PlaygroundDiagnostic, PlaygroundDiagnosticLevel},
+    Repository, RepositoryResult,
+};
 
 pub type PlaygroundRecordId = Id<PlaygroundRecord>;
 
@@ -16,6 +21,7 @@ pub struct PlaygroundRecord {
     pub created_by_user_id: i64,
     pub revision_id: PlaygroundRecordRevisionId,
     pub page_state: PlaygroundRecordPageState,
+    pub page: u32,
 }
 
 #[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
@@ -24,6 +30,9 @@ pub enum PlaygroundRecordPageState {
     Output,
     Stderr,
     Miri,
+    Clippy,
+    Format,
+    Artifact(PlaygroundArtifactKind),
 }
 
 pub type PlaygroundRecordRevisionId = Id<PlaygroundRecordRevision>;
@@ -33,8 +42,10 @@ pub struct PlaygroundRecordRevision {
     pub revision_id: PlaygroundRecordRevisionId,
     pub record_revision_count: u32,
     pub perma_link: Option<String>,
+    pub user_code: String,
     pub rendered_code: String,
-    // pub code_formatted: String,
+    pub format_success: Option<bool>,
+    pub code_formatted: String,
     pub warning_count: u32,
     pub error_count: u32,
     pub result_success: bool,
@@ -42,36 +53,110 @@ pub struct PlaygroundRecordRevision {
     pub result_exit_detail: String,
     pub result_stdout: String,
     pub result_stderr: String,
+    pub result_backend: String,
     pub playground_error: String,
-    // pub rust_edition: PlaygroundRustEdition,
-    // pub rust_channel: PlaygroundRustChannel,
-    // pub rust_profile: PlaygroundRustProfile,
+    pub miri_success: Option<bool>,
+    pub miri_exit_detail: String,
+    pub miri_stdout: String,
+    pub miri_stderr: String,
+    pub clippy_success: Option<bool>,
+    pub clippy_stderr: String,
+    pub clippy_warning_count: u32,
+    pub clippy_error_count: u32,
+    pub toolchain: PlaygroundRustToolchain,
+    pub tests: bool,
+    pub artifacts: Vec<PlaygroundArtifact>,
+    /// Parsed from `result_stderr`.
+    pub diagnostics: Vec<PlaygroundDiagnostic>,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct PlaygroundRustToolchain {
+    pub edition: PlaygroundRustEdition,
+    pub channel: PlaygroundRustChannel,
+    pub profile: PlaygroundRustProfile,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum PlaygroundRustEdition {
+    Rust2015,
+    Rust2018,
+    #[default]
+    Rust2021,
+    Rust2024,
+}
+
+impl PlaygroundRustEdition {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            PlaygroundRustEdition::Rust2015 => "2015",
+            PlaygroundRustEdition::Rust2018 => "2018",
+            PlaygroundRustEdition::Rust2021 => "2021",
+            PlaygroundRustEdition::Rust2024 => "2024",
+        }
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum PlaygroundRustChannel {
+    #[default]
+    Stable,
+    Beta,
+    Nightly,
+}
+
+impl PlaygroundRustChannel {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            PlaygroundRustChannel::Stable => "stable",
+            PlaygroundRustChannel::Beta => "beta",
+            PlaygroundRustChannel::Nightly => "nightly",
+        }
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum PlaygroundRustProfile {
+    #[default]
+    Debug,
+    Release,
+}
+
+impl PlaygroundRustProfile {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            PlaygroundRustProfile::Debug => "debug",
+            PlaygroundRustProfile::Release => "release",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PlaygroundRevisionStatus {
+    Pending,
+    Finished,
+    Abandoned,
+}
+
+/// A revision that was created but never got its result written.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PendingPlaygroundRevision {
+    pub revision: PlaygroundRecordRevision,
+    pub created_at: DateTime<Utc>,
+    pub chat_id: i64,
+    pub eval_msg_id: Option<i64>,
+    pub page_state: PlaygroundRecordPageState,
+    pub is_latest: bool,
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct NewPlaygroundRecordRevision {
+    pub user_code: String,
+    pub rendered_code: String,
+    pub toolchain: PlaygroundRustToolchain,
+    pub tests: bool,
 }
 
-// #[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
-// pub enum PlaygroundRustEdition {
-//     Rust2015,
-//     Rust2018,
-//     #[default]
-//     Rust2021,
-//     Rust2024,
-// }
-//
-// #[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
-// pub enum PlaygroundRustChannel {
-//     #[default]
-//     Stable,
-//     Beta,
-//     Nightly,
-// }
-//
-// #[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
-// pub enum PlaygroundRustProfile {
-//     #[default]
-//     Debug,
-//     Release,
-// }
-//
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct CreateRevisionUpsertRecordResult {
     pub revision_id: PlaygroundRecordRevisionId,
@@ -84,7 +169,7 @@ pub trait IPlaygroundRecordRepository {
         chat_id: i64,
         user_msg_id: i64,
         created_by_user_id: i64,
-        rendered_code: String,
+        revision: NewPlaygroundRecordRevision,
         page_state: PlaygroundRecordPageState,
     ) -> impl Future<Output = RepositoryResult<CreateRevisionUpsertRecordResult>>;
     fn update_eval_msg_id_for_revision_id(
@@ -110,19 +195,34 @@ pub trait IPlaygroundRecordRepository {
         created_by_user_id: i64,
         revision_id: PlaygroundRecordRevisionId,
         page_state: PlaygroundRecordPageState,
+        page: u32,
     ) -> impl Future<Output = RepositoryResult<Option<PlaygroundRecordRevision>>>;
 
     fn get_revision_by_id(
         &self,
         revision_id: PlaygroundRecordRevisionId,
     ) -> impl Future<
-        Output = RepositoryResult<Option<(PlaygroundRecordRevision, PlaygroundRecordPageState)>>,
+        Output = RepositoryResult<
+            Option<(PlaygroundRecordRevision, PlaygroundRecordPageState, u32)>,
+        >,
     >;
     fn update_perma_link_for_revision_id(
         &self,
         revision_id: PlaygroundRecordRevisionId,
         perma_link: String,
     ) -> impl Future<Output = RepositoryResult<()>>;
+    fn update_tool_results_for_revision_id(
+        &self,
+        revision: &PlaygroundRecordRevision,
+    ) -> impl Future<Output = RepositoryResult<()>>;
+    fn get_pending_revisions(
+        &self,
+    ) -> impl Future<Output = RepositoryResult<Vec<PendingPlaygroundRevision>>>;
+    fn update_status_for_revision_id(
+        &self,
+        revision_id: PlaygroundRecordRevisionId,
+        status: PlaygroundRevisionStatus,
+    ) -> impl Future<Output = RepositoryResult<()>>;
 }
 
 impl IPlaygroundRecordRepository for Repository {
@@ -131,15 +231,15 @@ impl IPlaygroundRecordRepository for Repository {
         chat_id: i64,
         user_msg_id: i64,
         created_by_user_id: i64,
-        rendered_code: String,
+        revision: NewPlaygroundRecordRevision,
         page_state: PlaygroundRecordPageState,
     ) -> RepositoryResult<CreateRevisionUpsertRecordResult> {
-        const INSERT_REVISION_SQL: &str =
-            "INSERT INTO `playground_revision` (`record_id`, `rendered_code`) VALUES (0, ?)";
+        const INSERT_REVISION_SQL: &str = "INSERT INTO `playground_revision` (`record_id`, `user_code`, `rendered_code`, `rust_edition`, `rust_channel`, `rust_profile`, `tests`, `status`)
+            VALUES (0, ?, ?, ?, ?, ?, ?, ?)";
         const UPSERT_RECORD_SQL: &str = "INSERT INTO `playground_record` (`chat_id`, `user_msg_id`, `created_by_user_id`, `revision_id`, `page_state`)
             VALUES (?1, ?2, ?3, ?4, ?5)
             ON CONFLICT (`user_msg_id`, `chat_id`)
-                DO UPDATE SET `revision_id` = excluded.revision_id, `page_state` = excluded.page_state
+                DO UPDATE SET `revision_id` = excluded.revision_id, `page_state` = excluded.page_state, `page` = 0
             RETURNING `id`, `eval_msg_id`, `page_state`";
         const UPDATE_REVISION_RECORD_ID_SQL: &str =
             "UPDATE `playground_revision` SET `record_id` = ? WHERE `id` = ?";
@@ -151,7 +251,15 @@ impl IPlaygroundRecordRepository for Repository {
                     let mut upsert_record_stmt = tx.prepare_cached(UPSERT_RECORD_SQL)?;
                     let mut update_revision_record_id_stmt =
                         tx.prepare_cached(UPDATE_REVISION_RECORD_ID_SQL)?;
-                    insert_revision_stmt.execute(params![rendered_code])?;
+                    insert_revision_stmt.execute(params![
+                        revision.user_code,
+                        revision.rendered_code,
+                        encode_edition(revision.toolchain.edition),
+                        revision.toolchain.channel as i64,
+                        revision.toolchain.profile as i64,
+                        revision.tests,
+                        encode_status(PlaygroundRevisionStatus::Pending)
+                    ])?;
                     let revision_id =
                         PlaygroundRecordRevisionId::try_from(tx.last_insert_rowid()).unwrap();
                     let (record_id, eval_msg_id, page_state) = upsert_record_stmt.query_row(
@@ -160,7 +268,7 @@ impl IPlaygroundRecordRepository for Repository {
                             user_msg_id,
                             created_by_user_id,
                             revision_id,
-                            page_state as i64
+                            encode_page_state(page_state)
                         ],
                         |row| {
                             Ok((
@@ -206,7 +314,7 @@ impl IPlaygroundRecordRepository for Repository {
         revision: &mut PlaygroundRecordRevision,
     ) -> RepositoryResult<bool> {
         const UPDATE_REVISION_SQL: &str = "UPDATE `playground_revision`
-            SET `perma_link` = ?, `warning_count` = ?, `error_count` = ?, `result_success` = ?, `result_code` = ?, `result_exit_detail` = ?, `result_stdout` = ?, `result_stderr` = ?, `playground_error` = ?
+            SET `perma_link` = ?, `warning_count` = ?, `error_count` = ?, `result_success` = ?, `result_code` = ?, `result_exit_detail` = ?, `result_stdout` = ?, `result_stderr` = ?, `playground_error` = ?, `miri_success` = ?, `miri_exit_detail` = ?, `miri_stdout` = ?, `miri_stderr` = ?, `clippy_success` = ?, `clippy_stderr` = ?, `clippy_warning_count` = ?, `clippy_error_count` = ?, `format_success` = ?, `code_formatted` = ?, `result_backend` = ?, `status` = ?
             WHERE `id` = ?";
         const SELECT_RECORD_REVISION_COUNT_SQL: &str = "SELECT
             COUNT(REV.`id`)
@@ -217,12 +325,28 @@ impl IPlaygroundRecordRepository for Repository {
                 AND `playground_record`.`revision_id` = ?1
             GROUP BY `playground_record`.`id`
             LIMIT 1";
+        const DELETE_DIAGNOSTICS_SQL: &str =
+            "DELETE FROM `playground_diagnostic` WHERE `revision_id` = ?";
+        const INSERT_DIAGNOSTIC_SQL: &str = "INSERT INTO `playground_diagnostic` (`revision_id`, `level`, `code`, `message`, `spans`, `notes`)
+            VALUES (?, ?, ?, ?, ?, ?)";
+        const DELETE_ARTIFACTS_SQL: &str =
+            "DELETE FROM `playground_artifact` WHERE `revision_id` = ?";
+        const INSERT_ARTIFACT_SQL: &str =
+            "INSERT INTO `playground_artifact` (`revision_id`, `kind`, `success`, `code`, `stderr`)
+            VALUES (?, ?, ?, ?, ?)";
         let record_revision_count = self
             .with_db({
                 let revision = revision.clone();
                 move |conn| {
-                    {
-                        let mut update_revision_stmt = conn.prepare_cached(UPDATE_REVISION_SQL)?;
+                    let tx = conn.transaction()?;
+                    let record_revision_count = {
+                        let mut update_revision_stmt = tx.prepare_cached(UPDATE_REVISION_SQL)?;
+                        let mut delete_diagnostics_stmt =
+                            tx.prepare_cached(DELETE_DIAGNOSTICS_SQL)?;
+                        let mut insert_diagnostic_stmt =
+                            tx.prepare_cached(INSERT_DIAGNOSTIC_SQL)?;
+                        let mut delete_artifacts_stmt = tx.prepare_cached(DELETE_ARTIFACTS_SQL)?;
+                        let mut insert_artifact_stmt = tx.prepare_cached(INSERT_ARTIFACT_SQL)?;
                         update_revision_stmt.execute(params![
                             revision.perma_link,
                             revision.warning_count,
@@ -233,16 +357,49 @@ impl IPlaygroundRecordRepository for Repository {
                             revision.result_stdout,
                             revision.result_stderr,
                             revision.playground_error,
+                            revision.miri_success,
+                            revision.miri_exit_detail,
+                            revision.miri_stdout,
+                            revision.miri_stderr,
+                            revision.clippy_success,
+                            revision.clippy_stderr,
+                            revision.clippy_warning_count,
+                            revision.clippy_error_count,
+                            revision.format_success,
+                            revision.code_formatted,
+                            revision.result_backend,
+                            encode_status(PlaygroundRevisionStatus::Finished),
                             revision.revision_id
                         ])?;
-                    }
-                    let record_revision_count = conn
-                        .query_row(
+                        delete_diagnostics_stmt.execute(params![revision.revision_id])?;
+                        for diagnostic in &revision.diagnostics {
+                            insert_diagnostic_stmt.execute(params![
+                                revision.revision_id,
+                                encode_diagnostic_level(diagnostic.level),
+                                diagnostic.code,
+                                diagnostic.message,
+                                serde_json::to_string(&diagnostic.spans)?,
+                                serde_json::to_string(&diagnostic.notes)?
+                            ])?;
+                        }
+                        delete_artifacts_stmt.execute(params![revision.revision_id])?;
+                        for artifact in &revision.artifacts {
+                            insert_artifact_stmt.execute(params![
+                                revision.revision_id,
+                                encode_artifact_kind(artifact.kind),
+                                artifact.success,
+                                artifact.code,
+                                artifact.stderr
+                            ])?;
+                        }
+                        tx.query_row(
                             SELECT_RECORD_REVISION_COUNT_SQL,
                             params![revision.revision_id],
                             |row| row.get(0),
                         )
-                        .optional()?;
+                        .optional()?
+                    };
+                    tx.commit()?;
 
                     Ok(record_revision_count)
                 }
@@ -289,9 +446,10 @@ impl IPlaygroundRecordRepository for Repository {
         created_by_user_id: i64,
         revision_id: PlaygroundRecordRevisionId,
         page_state: PlaygroundRecordPageState,
+        page: u32,
     ) -> RepositoryResult<Option<PlaygroundRecordRevision>> {
         const UPDATE_PAGE_STATE_IF_MATCH_SQL: &str = "UPDATE `playground_record`
-            SET `page_state` = ?1
+            SET `page_state` = ?1, `page` = ?5
             WHERE `id` = (SELECT `record_id` FROM `playground_revision` WHERE `id` = ?2)
             AND `revision_id` = ?2
             AND `eval_msg_id` = ?3
@@ -310,7 +468,23 @@ impl IPlaygroundRecordRepository for Repository {
             REV.`result_exit_detail`,
             REV.`result_stdout`,
             REV.`result_stderr`,
-            REV.`playground_error`
+            REV.`playground_error`,
+            REV.`miri_success`,
+            REV.`miri_exit_detail`,
+            REV.`miri_stdout`,
+            REV.`miri_stderr`,
+            REV.`rust_edition`,
+            REV.`rust_channel`,
+            REV.`rust_profile`,
+            REV.`user_code`,
+            REV.`clippy_success`,
+            REV.`clippy_stderr`,
+            REV.`clippy_warning_count`,
+            REV.`clippy_error_count`,
+            REV.`format_success`,
+            REV.`code_formatted`,
+            REV.`tests`,
+            REV.`result_backend`
             FROM `playground_revision` REV
             WHERE `id` = ?
             LIMIT 1";
@@ -322,17 +496,22 @@ impl IPlaygroundRecordRepository for Repository {
                         tx.prepare_cached(UPDATE_PAGE_STATE_IF_MATCH_SQL)?;
                     let mut select_revision_stmt = tx.prepare_cached(SELECT_REVISION_SQL)?;
                     let affected = update_page_state_if_match_stmt.execute(params![
-                        page_state as i64,
+                        encode_page_state(page_state),
                         revision_id,
                         eval_msg_id,
-                        created_by_user_id
+                        created_by_user_id,
+                        page
                     ])?;
                     if affected == 0 {
                         return Ok(None);
                     }
-                    let revision = select_revision_stmt
+                    let mut revision = select_revision_stmt
                         .query_row(params![revision_id], map_record_revision_rows)
                         .optional()?;
+                    if let Some(revision) = &mut revision {
+                        revision.artifacts = select_artifacts(&tx, revision.revision_id)?;
+                        revision.diagnostics = select_diagnostics(&tx, revision.revision_id)?;
+                    }
                     Ok(revision)
                 };
                 tx.commit()?;
@@ -345,7 +524,7 @@ impl IPlaygroundRecordRepository for Repository {
     async fn get_revision_by_id(
         &self,
         revision_id: PlaygroundRecordRevisionId,
-    ) -> RepositoryResult<Option<(PlaygroundRecordRevision, PlaygroundRecordPageState)>> {
+    ) -> RepositoryResult<Option<(PlaygroundRecordRevision, PlaygroundRecordPageState, u32)>> {
         const SELECT_REVISION_SQL: &str = "SELECT
                 REV.`id`,
                 (
@@ -361,7 +540,24 @@ impl IPlaygroundRecordRepository for Repository {
                 REV.`result_stdout`,
                 REV.`result_stderr`,
                 REV.`playground_error`,
-                `playground_record`.`page_state`
+                REV.`miri_success`,
+                REV.`miri_exit_detail`,
+                REV.`miri_stdout`,
+                REV.`miri_stderr`,
+                REV.`rust_edition`,
+                REV.`rust_channel`,
+                REV.`rust_profile`,
+                REV.`user_code`,
+                REV.`clippy_success`,
+                REV.`clippy_stderr`,
+                REV.`clippy_warning_count`,
+                REV.`clippy_error_count`,
+                REV.`format_success`,
+                REV.`code_formatted`,
+                REV.`tests`,
+                REV.`result_backend`,
+                `playground_record`.`page_state`,
+                `playground_record`.`page`
                 FROM `playground_revision` REV
                 INNER JOIN `playground_record` ON REV.`record_id` = `playground_record`.`id`
                 WHERE REV.`id` = ?
@@ -370,13 +566,17 @@ impl IPlaygroundRecordRepository for Repository {
             .with_db(move |conn| {
                 let mut select_revision_stmt = conn.prepare_cached(SELECT_REVISION_SQL)?;
 
-                let res = select_revision_stmt
+                let mut res = select_revision_stmt
                     .query_row(params![revision_id], |rows| {
                         let revision = map_record_revision_rows(rows)?;
-                        let page_state = decode_page_state(rows.get(12)?);
-                        Ok((revision, page_state))
+                        let page_state = decode_page_state(rows.get(28)?);
+                        Ok((revision, page_state, rows.get(29)?))
                     })
                     .optional()?;
+                if let Some((revision, ..)) = &mut res {
+                    revision.artifacts = select_artifacts(conn, revision_id)?;
+                    revision.diagnostics = select_diagnostics(conn, revision_id)?;
+                }
                 Ok(res)
             })
             .await?;
@@ -398,16 +598,261 @@ impl IPlaygroundRecordRepository for Repository {
         .await?;
         Ok(())
     }
+    async fn update_tool_results_for_revision_id(
+        &self,
+        revision: &PlaygroundRecordRevision,
+    ) -> RepositoryResult<()> {
+        const UPDATE_TOOL_RESULTS_SQL: &str = "UPDATE `playground_revision`
+                SET `miri_success` = ?, `miri_exit_detail` = ?, `miri_stdout` = ?, `miri_stderr` = ?, `format_success` = ?, `code_formatted` = ?
+                WHERE `id` = ?";
+        let revision = revision.clone();
+        self.with_db(move |conn| {
+            let mut update_tool_results_stmt = conn.prepare_cached(UPDATE_TOOL_RESULTS_SQL)?;
+            update_tool_results_stmt.execute(params![
+                revision.miri_success,
+                revision.miri_exit_detail,
+                revision.miri_stdout,
+                revision.miri_stderr,
+                revision.format_success,
+                revision.code_formatted,
+                revision.revision_id
+            ])?;
+            Ok(())
+        })
+        .await?;
+        Ok(())
+    }
+    async fn get_pending_revisions(&self) -> RepositoryResult<Vec<PendingPlaygroundRevision>> {
+        const SELECT_PENDING_REVISIONS_SQL: &str = "SELECT
+                REV.`id`,
+                REV.`user_code`,
+                REV.`rendered_code`,
+                REV.`rust_edition`,
+                REV.`rust_channel`,
+                REV.`rust_profile`,
+                REV.`tests`,
+                REV.`created_at`,
+                `playground_record`.`chat_id`,
+                `playground_record`.`eval_msg_id`,
+                `playground_record`.`page_state`,
+                `playground_record`.`revision_id` = REV.`id`
+                FROM `playground_revision` REV
+                INNER JOIN `playground_record` ON REV.`record_id` = `playground_record`.`id`
+                WHERE REV.`status` = ?
+                ORDER BY REV.`id`";
+        let res = self
+            .with_db(move |conn| {
+                let mut select_pending_revisions_stmt =
+                    conn.prepare_cached(SELECT_PENDING_REVISIONS_SQL)?;
+                let res = select_pending_revisions_stmt
+                    .query_map(
+                        params![encode_status(PlaygroundRevisionStatus::Pending)],
+                        |row| {
+                            let revision_id: i64 = row.get(0)?;
+                            Ok(PendingPlaygroundRevision {
+                                revision: PlaygroundRecordRevision {
+                                    revision_id: PlaygroundRecordRevisionId::try_from(revision_id)
+                                        .unwrap(),
+                                    user_code: row.get(1)?,
+                                    rendered_code: row.get(2)?,
+                                    toolchain: PlaygroundRustToolchain {
+                                        edition: decode_edition(row.get(3)?),
+                                        channel: decode_channel(row.get(4)?),
+                                        profile: decode_profile(row.get(5)?),
+                                    },
+                                    tests: row.get(6)?,
+                                    ..Default::default()
+                                },
+                                created_at: row.get(7)?,
+                                chat_id: row.get(8)?,
+                                eval_msg_id: row.get(9)?,
+                                page_state: decode_page_state(row.get(10)?),
+                                is_latest: row.get(11)?,
+                            })
+                        },
+                    )?
+                    .collect::<Result<_, _>>()?;
+                Ok(res)
+            })
+            .await?;
+        Ok(res)
+    }
+    async fn update_status_for_revision_id(
+        &self,
+        revision_id: PlaygroundRecordRevisionId,
+        status: PlaygroundRevisionStatus,
+    ) -> RepositoryResult<()> {
+        const UPDATE_STATUS_SQL: &str = "UPDATE `playground_revision`
+                SET `status` = ?
+                WHERE `id` = ?";
+        self.with_db(move |conn| {
+            let mut update_status_stmt = conn.prepare_cached(UPDATE_STATUS_SQL)?;
+            update_status_stmt.execute(params![encode_status(status), revision_id])?;
+            Ok(())
+        })
+        .await?;
+        Ok(())
+    }
+}
+
+fn select_artifacts(
+    conn: &Connection,
+    revision_id: PlaygroundRecordRevisionId,
+) -> RepositoryResult<Vec<PlaygroundArtifact>> {
+    const SELECT_ARTIFACTS_SQL: &str = "SELECT `kind`, `success`, `code`, `stderr`
+        FROM `playground_artifact`
+        WHERE `revision_id` = ?
+        ORDER BY `kind`";
+    let mut select_artifacts_stmt = conn.prepare_cached(SELECT_ARTIFACTS_SQL)?;
+    let artifacts = select_artifacts_stmt
+        .query_map(params![revision_id], |row| {
+            Ok(PlaygroundArtifact {
+                kind: decode_artifact_kind(row.get(0)?),
+                success: row.get(1)?,
+                code: row.get(2)?,
+                stderr: row.get(3)?,
+            })
+        })?
+        .collect::<Result<_, _>>()?;
+    Ok(artifacts)
+}
+
+fn select_diagnostics(
+    conn: &Connection,
+    revision_id: PlaygroundRecordRevisionId,
+) -> RepositoryResult<Vec<PlaygroundDiagnostic>> {
+    const SELECT_DIAGNOSTICS_SQL: &str = "SELECT `level`, `code`, `message`, `spans`, `notes`
+        FROM `playground_diagnostic`
+        WHERE `revision_id` = ?
+        ORDER BY `id`";
+    let mut select_diagnostics_stmt = conn.prepare_cached(SELECT_DIAGNOSTICS_SQL)?;
+    let rows = select_diagnostics_stmt
+        .query_map(params![revision_id], |row| {
+            Ok((
+                row.get::<_, u8>(0)?,
+                row.get::<_, Option<String>>(1)?,
+                row.get::<_, String>(2)?,
+                row.get::<_, String>(3)?,
+                row.get::<_, String>(4)?,
+            ))
+        })?
+        .collect::<Result<Vec<_>, _>>()?;
+    rows.into_iter()
+        .map(|(level, code, message, spans, notes)| {
+            Ok(PlaygroundDiagnostic {
+                level: decode_diagnostic_level(level),
+                code,
+                message,
+                spans: serde_json::from_str(&spans)?,
+                notes: serde_json::from_str(&notes)?,
+            })
+        })
+        .collect()
+}
+
+fn encode_status(status: PlaygroundRevisionStatus) -> u8 {
+    match status {
+        PlaygroundRevisionStatus::Pending => 0,
+        PlaygroundRevisionStatus::Finished => 1,
+        PlaygroundRevisionStatus::Abandoned => 2,
+    }
+}
+
+fn encode_page_state(page_state: PlaygroundRecordPageState) -> u8 {
+    match page_state {
+        PlaygroundRecordPageState::Output => 0,
+        PlaygroundRecordPageState::Stderr => 1,
+        PlaygroundRecordPageState::Miri => 2,
+        PlaygroundRecordPageState::Clippy => 3,
+        PlaygroundRecordPageState::Format => 4,
+        PlaygroundRecordPageState::Artifact(kind) => 5 + encode_artifact_kind(kind),
+    }
 }
 
 fn decode_page_state(page_state: u8) -> PlaygroundRecordPageState {
     match page_state {
         1 => PlaygroundRecordPageState::Stderr,
         2 => PlaygroundRecordPageState::Miri,
+        3 => PlaygroundRecordPageState::Clippy,
+        4 => PlaygroundRecordPageState::Format,
+        5..=10 => PlaygroundRecordPageState::Artifact(decode_artifact_kind(page_state - 5)),
         _ => PlaygroundRecordPageState::Output,
     }
 }
 
+fn encode_artifact_kind(kind: PlaygroundArtifactKind) -> u8 {
+    match kind {
+        PlaygroundArtifactKind::Asm => 0,
+        PlaygroundArtifactKind::LlvmIr => 1,
+        PlaygroundArtifactKind::Mir => 2,
+        PlaygroundArtifactKind::Hir => 3,
+        PlaygroundArtifactKind::Wasm => 4,
+        PlaygroundArtifactKind::MacroExpansion => 5,
+    }
+}
+
+fn decode_artifact_kind(kind: u8) -> PlaygroundArtifactKind {
+    match kind {
+        1 => PlaygroundArtifactKind::LlvmIr,
+        2 => PlaygroundArtifactKind::Mir,
+        3 => PlaygroundArtifactKind::Hir,
+        4 => PlaygroundArtifactKind::Wasm,
+        5 => PlaygroundArtifactKind::MacroExpansion,
+        _ => PlaygroundArtifactKind::Asm,
+    }
+}
+
+fn encode_diagnostic_level(level: PlaygroundDiagnosticLevel) -> u8 {
+    match level {
+        PlaygroundDiagnosticLevel::Error => 0,
+        PlaygroundDiagnosticLevel::Warning => 1,
+        PlaygroundDiagnosticLevel::Note => 2,
+        PlaygroundDiagnosticLevel::Help => 3,
+    }
+}
+
+fn decode_diagnostic_level(level: u8) -> PlaygroundDiagnosticLevel {
+    match level {
+        1 => PlaygroundDiagnosticLevel::Warning,
+        2 => PlaygroundDiagnosticLevel::Note,
+        3 => PlaygroundDiagnosticLevel::Help,
+        _ => PlaygroundDiagnosticLevel::Error,
+    }
+}
+
+fn encode_edition(edition: PlaygroundRustEdition) -> u16 {
+    match edition {
+        PlaygroundRustEdition::Rust2015 => 2015,
+        PlaygroundRustEdition::Rust2018 => 2018,
+        PlaygroundRustEdition::Rust2021 => 2021,
+        PlaygroundRustEdition::Rust2024 => 2024,
+    }
+}
+
+fn decode_edition(edition: u16) -> PlaygroundRustEdition {
+    match edition {
+        2015 => PlaygroundRustEdition::Rust2015,
+        2018 => PlaygroundRustEdition::Rust2018,
+        2024 => PlaygroundRustEdition::Rust2024,
+        _ => PlaygroundRustEdition::Rust2021,
+    }
+}
+
+fn decode_channel(channel: u8) -> PlaygroundRustChannel {
+    match channel {
+        1 => PlaygroundRustChannel::Beta,
+        2 => PlaygroundRustChannel::Nightly,
+        _ => PlaygroundRustChannel::Stable,
+    }
+}
+
+fn decode_profile(profile: u8) -> PlaygroundRustProfile {
+    match profile {
+        1 => PlaygroundRustProfile::Release,
+        _ => PlaygroundRustProfile::Debug,
+    }
+}
+
 fn map_record_revision_rows(
     row: &rusqlite::Row<'_>,
 ) -> Result<PlaygroundRecordRevision, rusqlite::Error> {
@@ -425,5 +870,25 @@ fn map_record_revision_rows(
         result_stdout: row.get(9)?,
         result_stderr: row.get(10)?,
         playground_error: row.get(11)?,
+        miri_success: row.get(12)?,
+        miri_exit_detail: row.get(13)?,
+        miri_stdout: row.get(14)?,
+        miri_stderr: row.get(15)?,
+        toolchain: PlaygroundRustToolchain {
+            edition: decode_edition(row.get(16)?),
+            channel: decode_channel(row.get(17)?),
+            profile: decode_profile(row.get(18)?),
+        },
+        user_code: row.get(19)?,
+        clippy_success: row.get(20)?,
+        clippy_stderr: row.get(21)?,
+        clippy_warning_count: row.get(22)?,
+        clippy_error_count: row.get(23)?,
+        format_success: row.get(24)?,
+        code_formatted: row.get(25)?,
+        tests: row.get(26)?,
+        result_backend: row.get(27)?,
+        artifacts: vec![],
+        diagnostics: vec![],
     })
 }
@@ -1 +1,4 @@
+pub mod backend;
+pub mod failover;
+pub mod local;
 pub mod playground;
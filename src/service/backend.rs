@@ -0,0 +1,104 @@
+use super::{
+    local::LocalPlaygroundService,
+    playground::{
+        IPlaygrounService, PlaygroundCompileResult, PlaygroundExecuteResult,
+        PlaygroundFormatResult, PlaygroundResult, PlaygroundService,
+    },
+};
+
+/// The playground picked by configuration.
+#[derive(Clone)]
+pub enum PlaygroundBackend {
+    Remote(PlaygroundService),
+    Local(LocalPlaygroundService),
+}
+
+impl IPlaygrounService for PlaygroundBackend {
+    async fn run_code(
+        &self,
+        code: &str,
+        channel: &'static str,
+        mode: &'static str,
+        edition: &'static str,
+        tests: bool,
+    ) -> PlaygroundResult<PlaygroundExecuteResult> {
+        match self {
+            PlaygroundBackend::Remote(p) => p.run_code(code, channel, mode, edition, tests).await,
+            PlaygroundBackend::Local(p) => p.run_code(code, channel, mode, edition, tests).await,
+        }
+    }
+
+    async fn run_miri(
+        &self,
+        code: &str,
+        edition: &'static str,
+        tests: bool,
+    ) -> PlaygroundResult<PlaygroundExecuteResult> {
+        match self {
+            PlaygroundBackend::Remote(p) => p.run_miri(code, edition, tests).await,
+            PlaygroundBackend::Local(p) => p.run_miri(code, edition, tests).await,
+        }
+    }
+
+    async fn clippy(
+        &self,
+        code: &str,
+        channel: &'static str,
+        edition: &'static str,
+    ) -> PlaygroundResult<PlaygroundExecuteResult> {
+        match self {
+            PlaygroundBackend::Remote(p) => p.clippy(code, channel, edition).await,
+            PlaygroundBackend::Local(p) => p.clippy(code, channel, edition).await,
+        }
+    }
+
+    async fn format(
+        &self,
+        code: &str,
+        channel: &'static str,
+        edition: &'static str,
+    ) -> PlaygroundResult<PlaygroundFormatResult> {
+        match self {
+            PlaygroundBackend::Remote(p) => p.format(code, channel, edition).await,
+            PlaygroundBackend::Local(p) => p.format(code, channel, edition).await,
+        }
+    }
+
+    async fn compile(
+        &self,
+        code: &str,
+        target: &'static str,
+        channel: &'static str,
+        mode: &'static str,
+        edition: &'static str,
+    ) -> PlaygroundResult<PlaygroundCompileResult> {
+        match self {
+            PlaygroundBackend::Remote(p) => p.compile(code, target, channel, mode, edition).await,
+            PlaygroundBackend::Local(p) => p.compile(code, target, channel, mode, edition).await,
+        }
+    }
+
+    async fn macro_expansion(
+        &self,
+        code: &str,
+        edition: &'static str,
+    ) -> PlaygroundResult<PlaygroundCompileResult> {
+        match self {
+            PlaygroundBackend::Remote(p) => p.macro_expansion(code, edition).await,
+            PlaygroundBackend::Local(p) => p.macro_expansion(code, edition).await,
+        }
+    }
+
+    async fn generate_link(
+        &self,
+        code: &str,
+        channel: &'static str,
+        mode: &'static str,
+        edition: &'static str,
+    ) -> PlaygroundResult<String> {
+        match self {
+            PlaygroundBackend::Remote(p) => p.generate_link(code, channel, mode, edition).await,
+            PlaygroundBackend::Local(p) => p.generate_link(code, channel, mode, edition).await,
+        }
+    }
+}
@@ -0,0 +1,409 @@
+use std::{
+    future::Future,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use tracing::{debug, warn};
+
+use super::playground::{
+    IPlaygrounService, PlaygroundCompileResult, PlaygroundError, PlaygroundExecuteResult,
+    PlaygroundFormatResult, PlaygroundResult,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CircuitBreakerConfig {
+    /// Failures in a row after which a backend is skipped.
+    pub failures: u32,
+    /// How long a backend is skipped for before it gets another chance.
+    pub cooldown: Duration,
+}
+
+/// Runs each call on the first backend that is not known to be failing, falling back to the
+/// next ones when it fails.
+#[derive(Clone)]
+pub struct FailoverPlaygroundService<P> {
+    backends: Arc<[Backend<P>]>,
+    config: CircuitBreakerConfig,
+}
+
+struct Backend<P> {
+    name: String,
+    service: P,
+    health: Mutex<BackendHealth>,
+}
+
+#[derive(Debug, Default)]
+struct BackendHealth {
+    failures: u32,
+    /// Set once `failures` reaches the limit, the backend is skipped until then.
+    open_until: Option<Instant>,
+    /// Moving average of how long successful calls take.
+    latency: Option<Duration>,
+}
+
+impl<P> FailoverPlaygroundService<P> {
+    /// Tries `backends` in the given order, which must not be empty.
+    pub fn new(backends: Vec<(String, P)>, config: CircuitBreakerConfig) -> Self {
+        assert!(!backends.is_empty(), "no playground backend");
+        let backends = backends
+            .into_iter()
+            .map(|(name, service)| Backend {
+                name,
+                service,
+                health: Mutex::default(),
+            })
+            .collect();
+        Self { backends, config }
+    }
+
+    /// Backends in the order to try them. When all of them are failing, the one to recover
+    /// first is tried first rather than giving up.
+    fn candidates(&self, now: Instant) -> Vec<&Backend<P>> {
+        let mut candidates = self
+            .backends
+            .iter()
+            .map(|backend| (backend.health.lock().unwrap().open_until, backend))
+            .collect::<Vec<_>>();
+        if candidates
+            .iter()
+            .any(|(open_until, _)| !is_open(*open_until, now))
+        {
+            candidates.retain(|(open_until, _)| !is_open(*open_until, now));
+        } else {
+            candidates.sort_by_key(|(open_until, _)| *open_until);
+        }
+        candidates.into_iter().map(|(_, backend)| backend).collect()
+    }
+
+    /// Calls `f` on the candidates until one of them succeeds, returning the name and average
+    /// latency of that one if there is more than one backend to tell apart.
+    async fn call<'a, T, F>(
+        &'a self,
+        method: &'static str,
+        f: impl Fn(&'a P) -> F,
+    ) -> PlaygroundResult<(T, Option<(&'a str, Duration)>)>
+    where
+        F: Future<Output = PlaygroundResult<T>>,
+    {
+        let mut last_err = None;
+        for backend in self.candidates(Instant::now()) {
+            let start = Instant::now();
+            let err = match f(&backend.service).await {
+                Ok(res) => {
+                    let elapsed = start.elapsed();
+                    let latency = backend.succeeded(elapsed);
+                    debug!(backend = %backend.name, method, ?elapsed, ?latency, "playground call done");
+                    let name = (self.backends.len() > 1).then_some((&*backend.name, latency));
+                    return Ok((res, name));
+                }
+                // Not the backend's fault, another one may still take the call
+                Err(e) if !is_backend_failure(&e) => e,
+                Err(e) => {
+                    let failures = backend.failed(&self.config);
+                    warn!(backend = %backend.name, method, failures, "playground call failed: {e}");
+                    e
+                }
+            };
+            last_err = Some(err);
+        }
+        Err(last_err.expect("no playground backend"))
+    }
+
+    async fn call_execute<'a, F>(
+        &'a self,
+        method: &'static str,
+        f: impl Fn(&'a P) -> F,
+    ) -> PlaygroundResult<PlaygroundExecuteResult>
+    where
+        F: Future<Output = PlaygroundResult<PlaygroundExecuteResult>>,
+    {
+        let (mut res, name) = self.call(method, f).await?;
+        if let Some((name, latency)) = name {
+            res.result_backend = format!("{name} ~{:.1}s", latency.as_secs_f64());
+        }
+        Ok(res)
+    }
+}
+
+fn is_open(open_until: Option<Instant>, now: Instant) -> bool {
+    open_until.is_some_and(|until| until > now)
+}
+
+/// Transport and infrastructure errors. Snippets that fail or run out of time come back as
+/// results, and requests a backend refuses say nothing about its health.
+fn is_backend_failure(e: &PlaygroundError) -> bool {
+    match e {
+        PlaygroundError::Unavailable(_) => false,
+        PlaygroundError::Status(status) => *status >= 500 || *status == 429,
+        PlaygroundError::Request(_)
+        | PlaygroundError::Timeout
+        | PlaygroundError::InvalidResponse(_)
+        | PlaygroundError::Sandbox(_) => true,
+    }
+}
+
+impl<P> Backend<P> {
+    /// Resets the failure count and folds `elapsed` into the average latency, returning it.
+    fn succeeded(&self, elapsed: Duration) -> Duration {
+        let mut health = self.health.lock().unwrap();
+        health.failures = 0;
+        health.open_until = None;
+        let latency = match health.latency {
+            Some(latency) => latency * 7 / 8 + elapsed / 8,
+            None => elapsed,
+        };
+        health.latency = Some(latency);
+        latency
+    }
+
+    fn failed(&self, config: &CircuitBreakerConfig) -> u32 {
+        let mut health = self.health.lock().unwrap();
+        health.failures += 1;
+        if health.failures >= config.failures {
+            // A backend given another chance is skipped again after a single failure
+            health.open_until = Some(Instant::now() + config.cooldown);
+        }
+        health.failures
+    }
+}
+
+impl<P: IPlaygrounService> IPlaygrounService for FailoverPlaygroundService<P> {
+    async fn run_code(
+        &self,
+        code: &str,
+        channel: &'static str,
+        mode: &'static str,
+        edition: &'static str,
+        tests: bool,
+    ) -> PlaygroundResult<PlaygroundExecuteResult> {
+        self.call_execute("run_code", |p| {
+            p.run_code(code, channel, mode, edition, tests)
+        })
+        .await
+    }
+
+    async fn run_miri(
+        &self,
+        code: &str,
+        edition: &'static str,
+        tests: bool,
+    ) -> PlaygroundResult<PlaygroundExecuteResult> {
+        self.call_execute("run_miri", |p| p.run_miri(code, edition, tests))
+            .await
+    }
+
+    async fn clippy(
+        &self,
+        code: &str,
+        channel: &'static str,
+        edition: &'static str,
+    ) -> PlaygroundResult<PlaygroundExecuteResult> {
+        self.call_execute("clippy", |p| p.clippy(code, channel, edition))
+            .await
+    }
+
+    async fn format(
+        &self,
+        code: &str,
+        channel: &'static str,
+        edition: &'static str,
+    ) -> PlaygroundResult<PlaygroundFormatResult> {
+        let (res, _) = self
+            .call("format", |p| p.format(code, channel, edition))
+            .await?;
+        Ok(res)
+    }
+
+    async fn compile(
+        &self,
+        code: &str,
+        target: &'static str,
+        channel: &'static str,
+        mode: &'static str,
+        edition: &'static str,
+    ) -> PlaygroundResult<PlaygroundCompileResult> {
+        let (res, _) = self
+            .call("compile", |p| {
+                p.compile(code, target, channel, mode, edition)
+            })
+            .await?;
+        Ok(res)
+    }
+
+    async fn macro_expansion(
+        &self,
+        code: &str,
+        edition: &'static str,
+    ) -> PlaygroundResult<PlaygroundCompileResult> {
+        let (res, _) = self
+            .call("macro_expansion", |p| p.macro_expansion(code, edition))
+            .await?;
+        Ok(res)
+    }
+
+    async fn generate_link(
+        &self,
+        code: &str,
+        channel: &'static str,
+        mode: &'static str,
+        edition: &'static str,
+    ) -> PlaygroundResult<String> {
+        let (res, _) = self
+            .call("generate_link", |p| {
+                p.generate_link(code, channel, mode, edition)
+            })
+            .await?;
+        Ok(res)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{MockPlayground, MockResponse};
+
+    const CONFIG: CircuitBreakerConfig = CircuitBreakerConfig {
+        failures: 2,
+        cooldown: Duration::from_secs(60),
+    };
+
+    fn service() -> (
+        FailoverPlaygroundService<MockPlayground>,
+        MockPlayground,
+        MockPlayground,
+    ) {
+        let (primary, mirror) = (MockPlayground::default(), MockPlayground::default());
+        let service = FailoverPlaygroundService::new(
+            vec![
+                ("primary".into(), primary.clone()),
+                ("mirror".into(), mirror.clone()),
+            ],
+            CONFIG,
+        );
+        (service, primary, mirror)
+    }
+
+    async fn run(service: &FailoverPlaygroundService<MockPlayground>) -> PlaygroundExecuteResult {
+        service
+            .run_code("1", "stable", "debug", "2021", false)
+            .await
+            .unwrap()
+    }
+
+    /// The backend name without the latency that follows it.
+    fn backend(res: &PlaygroundExecuteResult) -> &str {
+        res.result_backend.split(" ~").next().unwrap()
+    }
+
+    #[compio::test]
+    async fn test_failover() {
+        let (service, primary, mirror) = service();
+        primary.respond("run_code", MockResponse::stdout("1"));
+        assert_eq!(backend(&run(&service).await), "primary");
+
+        primary.respond("run_code", MockResponse::Timeout);
+        mirror.respond("run_code", MockResponse::stdout("1"));
+        let res = run(&service).await;
+        assert_eq!(backend(&res), "mirror");
+        assert_eq!(res.result_stdout, "1");
+        assert_eq!(primary.calls("run_code").len(), 2);
+        assert_eq!(mirror.calls("run_code").len(), 1);
+
+        // Unsupported calls fall through without counting against the backend
+        primary.respond("generate_link", MockResponse::Unavailable);
+        mirror.respond("generate_link", MockResponse::Link("link".into()));
+        let link = service
+            .generate_link("1", "stable", "debug", "2021")
+            .await
+            .unwrap();
+        assert_eq!(link, "link");
+        assert_eq!(service.backends[0].health.lock().unwrap().failures, 1);
+    }
+
+    #[compio::test]
+    async fn test_circuit_breaker() {
+        let (service, primary, mirror) = service();
+        for _ in 0..CONFIG.failures {
+            primary.respond("run_code", MockResponse::Malformed);
+            mirror.respond("run_code", MockResponse::stdout("1"));
+            assert_eq!(backend(&run(&service).await), "mirror");
+        }
+        // The primary is skipped until the cooldown is over
+        mirror.respond("run_code", MockResponse::stdout("1"));
+        assert_eq!(backend(&run(&service).await), "mirror");
+        assert_eq!(primary.calls("run_code").len(), 2);
+        let now = Instant::now();
+        let candidates = service.candidates(now + CONFIG.cooldown);
+        assert_eq!(candidates.len(), 2);
+        assert_eq!(candidates[0].name, "primary");
+
+        // With every backend failing, the one to recover first is tried
+        for _ in 0..CONFIG.failures {
+            mirror.respond("run_code", MockResponse::Timeout);
+            let res = service
+                .run_code("1", "stable", "debug", "2021", false)
+                .await;
+            assert!(matches!(res, Err(PlaygroundError::Timeout)));
+        }
+        primary.respond("run_code", MockResponse::stdout("1"));
+        assert_eq!(backend(&run(&service).await), "primary");
+        assert!(service.backends[0]
+            .health
+            .lock()
+            .unwrap()
+            .open_until
+            .is_none());
+        assert_eq!(service.candidates(Instant::now())[0].name, "primary");
+    }
+
+    #[compio::test]
+    async fn test_refused_request() {
+        let (service, primary, mirror) = service();
+        for _ in 0..CONFIG.failures {
+            primary.respond("run_code", MockResponse::Status(413));
+            mirror.respond("run_code", MockResponse::stdout("1"));
+            assert_eq!(backend(&run(&service).await), "mirror");
+        }
+        assert_eq!(service.backends[0].health.lock().unwrap().failures, 0);
+
+        primary.respond("run_code", MockResponse::Status(503));
+        mirror.respond("run_code", MockResponse::stdout("1"));
+        assert_eq!(backend(&run(&service).await), "mirror");
+        assert_eq!(service.backends[0].health.lock().unwrap().failures, 1);
+    }
+
+    #[compio::test]
+    async fn test_latency() {
+        let (service, primary, _) = service();
+        let delay = Duration::from_millis(200);
+        primary.respond_after("run_code", delay, MockResponse::stdout("1"));
+        assert_eq!(run(&service).await.result_backend, "primary ~0.2s");
+        let first = service.backends[0].health.lock().unwrap().latency.unwrap();
+        assert!(first >= delay);
+
+        // A fast call only moves the average an eighth of the way
+        primary.respond("run_code", MockResponse::stdout("1"));
+        run(&service).await;
+        let second = service.backends[0].health.lock().unwrap().latency.unwrap();
+        assert!(second < first);
+        assert!(second >= delay * 7 / 8);
+    }
+
+    #[compio::test]
+    async fn test_single_backend() {
+        let playground = MockPlayground::default();
+        let service =
+            FailoverPlaygroundService::new(vec![("only".into(), playground.clone())], CONFIG);
+        for _ in 0..CONFIG.failures {
+            playground.respond("run_code", MockResponse::Timeout);
+            assert!(service
+                .run_code("1", "stable", "debug", "2021", false)
+                .await
+                .is_err());
+        }
+        // Still tried with its circuit open, and not named in results
+        playground.respond("run_code", MockResponse::stdout("1"));
+        assert_eq!(run(&service).await.result_backend, "");
+    }
+}
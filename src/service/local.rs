@@ -0,0 +1,649 @@
+use std::{
+    collections::HashMap,
+    fs::{self, File},
+    io::{self, Read},
+    path::{Path, PathBuf},
+    process::{Child, Command, ExitStatus, Stdio},
+    sync::{
+        atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering},
+        Arc,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+use event_listener::Event;
+use futures::channel::oneshot;
+
+use super::playground::{
+    IPlaygrounService, PlaygroundCompileResult, PlaygroundError, PlaygroundExecuteResult,
+    PlaygroundFormatResult, PlaygroundResult,
+};
+
+/// Where the snippet's crate is mounted inside the sandbox.
+const SANDBOX_DIR: &str = "/playground";
+const POLL_INTERVAL: Duration = Duration::from_millis(10);
+/// Put between the build's and the program's stderr, as cargo does on the playground, so that
+/// diagnostics parsing stops before the program's output.
+const RUNNING_LINE: &str = "     Running `target/playground`\n";
+
+/// Settings for [`LocalPlaygroundService`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SandboxConfig {
+    /// The bubblewrap binary.
+    pub bwrap: PathBuf,
+    /// Each call gets a directory of its own in here, removed once it is done.
+    pub work_dir: PathBuf,
+    /// Toolchain roots containing `bin/rustc`, by channel.
+    pub toolchains: HashMap<&'static str, PathBuf>,
+    /// Host paths mounted read-only, for the linker and the libraries it needs.
+    pub ro_binds: Vec<PathBuf>,
+    pub limits: SandboxLimits,
+    /// Wall-clock limit for everything a call runs.
+    pub timeout: Duration,
+    /// Calls running at once, the others wait for a slot.
+    pub max_jobs: usize,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SandboxLimits {
+    /// Address space of each process, in bytes.
+    pub memory: u64,
+    /// CPU seconds of each process.
+    pub cpu_time: u64,
+    /// Bytes kept of stdout and stderr each, also the largest file a snippet may write.
+    pub output: u64,
+    /// Processes of the bot's user. The kernel counts these per user rather than per sandbox, so
+    /// the bot itself and all of its jobs share them: run the bot under a user of its own and
+    /// leave room for `max_jobs` jobs.
+    pub processes: u64,
+}
+
+/// Compiles and runs snippets with toolchains installed on this host. Every command is
+/// confined by bubblewrap to a fresh directory, without network access and under rlimits.
+#[derive(Clone)]
+pub struct LocalPlaygroundService {
+    config: Arc<SandboxConfig>,
+    next_job: Arc<AtomicU64>,
+    slots: Arc<JobSlots>,
+}
+
+impl LocalPlaygroundService {
+    pub fn new(config: SandboxConfig) -> Self {
+        Self {
+            slots: Arc::new(JobSlots::new(config.max_jobs)),
+            config: Arc::new(config),
+            next_job: Arc::new(AtomicU64::new(0)),
+        }
+    }
+
+    /// Runs `work` on a thread of its own once a slot is free, killing what it started if the
+    /// call is dropped.
+    async fn run_job<T: Send + 'static>(
+        &self,
+        code: &str,
+        work: impl FnOnce(&Job) -> PlaygroundResult<T> + Send + 'static,
+    ) -> PlaygroundResult<T> {
+        let slot = self.slots.acquire().await;
+        let id = self.next_job.fetch_add(1, Ordering::Relaxed);
+        let job = Job {
+            dir: self
+                .config
+                .work_dir
+                .join(format!("{}-{id}", std::process::id())),
+            config: self.config.clone(),
+            deadline: Instant::now() + self.config.timeout,
+            cancelled: Arc::new(AtomicBool::new(false)),
+        };
+        let _cancel = CancelOnDrop(job.cancelled.clone());
+        let code = code.to_owned();
+        let (result_tx, result_rx) = oneshot::channel();
+        thread::spawn(move || {
+            let result = job
+                .create(&code)
+                .map_err(Into::into)
+                .and_then(|()| work(&job));
+            drop(job);
+            // Only freed once the sandbox is gone, not as soon as the call is dropped
+            drop(slot);
+            let _ = result_tx.send(result);
+        });
+        result_rx
+            .await
+            .map_err(|_| io::Error::other("sandbox thread panicked"))?
+    }
+
+    async fn artifact(
+        &self,
+        code: &str,
+        channel: &'static str,
+        mode: &'static str,
+        edition: &'static str,
+        flags: &'static [&'static str],
+    ) -> PlaygroundResult<PlaygroundCompileResult> {
+        self.run_job(code, move |job| {
+            let toolchain = job.toolchain(channel)?;
+            let mut args = rustc_args(edition, mode);
+            args.extend(flags);
+            args.extend(["-o", "target/playground.out", "src/main.rs"]);
+            let output = job.run(toolchain, &toolchain.join("bin/rustc"), &args, false)?;
+            let code = if output.success() {
+                read_output(
+                    &job.dir.join("playground/target/playground.out"),
+                    job.config.limits.output,
+                )?
+            } else {
+                "".into()
+            };
+            Ok(PlaygroundCompileResult {
+                success: output.success(),
+                exit_detail: output.exit_detail(),
+                code,
+                stderr: output.stderr,
+            })
+        })
+        .await
+    }
+}
+
+impl IPlaygrounService for LocalPlaygroundService {
+    async fn run_code(
+        &self,
+        code: &str,
+        channel: &'static str,
+        mode: &'static str,
+        edition: &'static str,
+        tests: bool,
+    ) -> PlaygroundResult<PlaygroundExecuteResult> {
+        self.run_job(code, move |job| {
+            let toolchain = job.toolchain(channel)?;
+            let mut args = rustc_args(edition, mode);
+            if tests {
+                args.push("--test");
+            }
+            args.extend(["-o", "target/playground", "src/main.rs"]);
+            let build = job.run(toolchain, &toolchain.join("bin/rustc"), &args, false)?;
+            if !build.success() {
+                return Ok(build.into());
+            }
+            let program = Path::new(SANDBOX_DIR).join("target/playground");
+            let mut res = PlaygroundExecuteResult::from(job.run(toolchain, &program, &[], true)?);
+            res.result_stderr = join_stderr(build.stderr, &res.result_stderr);
+            Ok(res)
+        })
+        .await
+    }
+
+    async fn run_miri(
+        &self,
+        _code: &str,
+        _edition: &'static str,
+        _tests: bool,
+    ) -> PlaygroundResult<PlaygroundExecuteResult> {
+        Err(PlaygroundError::Unavailable("Miri".into()))
+    }
+
+    async fn clippy(
+        &self,
+        code: &str,
+        channel: &'static str,
+        edition: &'static str,
+    ) -> PlaygroundResult<PlaygroundExecuteResult> {
+        self.run_job(code, move |job| {
+            let toolchain = job.toolchain(channel)?;
+            // Unlike rustc, clippy-driver cannot tell its sysroot from where it lives
+            let sysroot = toolchain.to_string_lossy();
+            let mut args = rustc_args(edition, "debug");
+            args.extend(["--sysroot", &*sysroot]);
+            args.extend(["--emit", "metadata=target/playground.rmeta", "src/main.rs"]);
+            let output = job.run(
+                toolchain,
+                &toolchain.join("bin/clippy-driver"),
+                &args,
+                false,
+            )?;
+            Ok(output.into())
+        })
+        .await
+    }
+
+    async fn format(
+        &self,
+        code: &str,
+        channel: &'static str,
+        edition: &'static str,
+    ) -> PlaygroundResult<PlaygroundFormatResult> {
+        self.run_job(code, move |job| {
+            let toolchain = job.toolchain(channel)?;
+            let args = ["--edition", edition, "src/main.rs"];
+            let output = job.run(toolchain, &toolchain.join("bin/rustfmt"), &args, false)?;
+            Ok(PlaygroundFormatResult {
+                success: output.success(),
+                code: fs::read_to_string(job.dir.join("playground/src/main.rs"))?,
+                stderr: output.stderr,
+            })
+        })
+        .await
+    }
+
+    async fn compile(
+        &self,
+        code: &str,
+        target: &'static str,
+        channel: &'static str,
+        mode: &'static str,
+        edition: &'static str,
+    ) -> PlaygroundResult<PlaygroundCompileResult> {
+        let flags: &[&str] = match target {
+            "asm" => &["--emit=asm", "-C", "llvm-args=-x86-asm-syntax=intel"],
+            "llvm-ir" => &["--emit=llvm-ir"],
+            "mir" => &["--emit=mir"],
+            "hir" => &["-Zunpretty=hir"],
+            _ => return Err(PlaygroundError::Unavailable(format!("{target} output"))),
+        };
+        self.artifact(code, channel, mode, edition, flags).await
+    }
+
+    async fn macro_expansion(
+        &self,
+        code: &str,
+        edition: &'static str,
+    ) -> PlaygroundResult<PlaygroundCompileResult> {
+        self.artifact(code, "nightly", "debug", edition, &["-Zunpretty=expanded"])
+            .await
+    }
+
+    async fn generate_link(
+        &self,
+        _code: &str,
+        _channel: &'static str,
+        _mode: &'static str,
+        _edition: &'static str,
+    ) -> PlaygroundResult<String> {
+        Err(PlaygroundError::Unavailable("Sharing".into()))
+    }
+}
+
+/// Counts free job slots, like a semaphore.
+struct JobSlots {
+    free: AtomicUsize,
+    released: Event,
+}
+
+/// A taken slot, given back on drop.
+struct JobSlot(Arc<JobSlots>);
+
+impl JobSlots {
+    fn new(count: usize) -> Self {
+        Self {
+            free: AtomicUsize::new(count),
+            released: Event::new(),
+        }
+    }
+
+    async fn acquire(self: &Arc<Self>) -> JobSlot {
+        loop {
+            if let Some(slot) = self.try_acquire() {
+                return slot;
+            }
+            let listener = self.released.listen();
+            if let Some(slot) = self.try_acquire() {
+                return slot;
+            }
+            listener.await;
+        }
+    }
+
+    fn try_acquire(self: &Arc<Self>) -> Option<JobSlot> {
+        self.free
+            .fetch_update(Ordering::AcqRel, Ordering::Acquire, |free| {
+                free.checked_sub(1)
+            })
+            .ok()
+            .map(|_| JobSlot(self.clone()))
+    }
+}
+
+impl Drop for JobSlot {
+    fn drop(&mut self) {
+        self.0.free.fetch_add(1, Ordering::AcqRel);
+        self.0.released.notify(usize::MAX);
+    }
+}
+
+struct CancelOnDrop(Arc<AtomicBool>);
+
+impl Drop for CancelOnDrop {
+    fn drop(&mut self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+}
+
+/// The scratch directory of a single call, with `playground/` mounted as [`SANDBOX_DIR`] and
+/// the output of the last command next to it.
+struct Job {
+    dir: PathBuf,
+    config: Arc<SandboxConfig>,
+    deadline: Instant,
+    cancelled: Arc<AtomicBool>,
+}
+
+struct Output {
+    status: ExitStatus,
+    /// Killed for running past the job's deadline.
+    timed_out: bool,
+    stdout: String,
+    stderr: String,
+}
+
+impl Output {
+    fn success(&self) -> bool {
+        !self.timed_out && self.status.success()
+    }
+
+    fn exit_detail(&self) -> String {
+        if self.timed_out {
+            "Timed out".into()
+        } else {
+            exit_detail(self.status)
+        }
+    }
+}
+
+impl From<Output> for PlaygroundExecuteResult {
+    fn from(output: Output) -> Self {
+        PlaygroundExecuteResult {
+            result_success: output.success(),
+            result_code: "".into(),
+            result_exit_detail: output.exit_detail(),
+            result_stdout: output.stdout,
+            result_stderr: output.stderr,
+            result_backend: "".into(),
+        }
+    }
+}
+
+impl Job {
+    fn create(&self, code: &str) -> io::Result<()> {
+        fs::create_dir_all(self.dir.join("playground/src"))?;
+        fs::create_dir_all(self.dir.join("playground/target"))?;
+        fs::write(self.dir.join("playground/src/main.rs"), code)
+    }
+
+    fn toolchain(&self, channel: &str) -> PlaygroundResult<&Path> {
+        self.config
+            .toolchains
+            .get(channel)
+            .map(PathBuf::as_path)
+            .ok_or_else(|| PlaygroundError::Unavailable(format!("The {channel} toolchain")))
+    }
+
+    /// Runs `program` in the sandbox until it exits, the job's deadline passes or the call is
+    /// dropped. Running out of time is the snippet's doing and ends in an unsuccessful output,
+    /// not an error. `limit_output` also caps the size of files written, which would stop rustc.
+    fn run(
+        &self,
+        toolchain: &Path,
+        program: &Path,
+        args: &[&str],
+        limit_output: bool,
+    ) -> PlaygroundResult<Output> {
+        let stdout_path = self.dir.join("stdout");
+        let stderr_path = self.dir.join("stderr");
+        let mut cmd = self.sandbox_command(toolchain, program, args);
+        cmd.stdout(File::create(&stdout_path)?)
+            .stderr(File::create(&stderr_path)?);
+        set_limits(&mut cmd, self.config.limits, limit_output);
+        let mut child = cmd.spawn()?;
+        let (status, timed_out) = self.wait(&mut child)?;
+        Ok(Output {
+            status,
+            timed_out,
+            stdout: read_output(&stdout_path, self.config.limits.output)?,
+            stderr: read_output(&stderr_path, self.config.limits.output)?,
+        })
+    }
+
+    fn sandbox_command(&self, toolchain: &Path, program: &Path, args: &[&str]) -> Command {
+        let mut cmd = Command::new(&self.config.bwrap);
+        cmd.args(["--unshare-all", "--die-with-parent", "--new-session"]);
+        for path in &self.config.ro_binds {
+            cmd.arg("--ro-bind-try").arg(path).arg(path);
+        }
+        cmd.arg("--ro-bind").arg(toolchain).arg(toolchain);
+        cmd.arg("--bind")
+            .arg(self.dir.join("playground"))
+            .arg(SANDBOX_DIR);
+        cmd.args(["--proc", "/proc", "--dev", "/dev", "--tmpfs", "/tmp"]);
+        cmd.args(["--chdir", SANDBOX_DIR, "--"])
+            .arg(program)
+            .args(args);
+        // Keep the bot's own settings, such as its API key, out of reach
+        cmd.env_clear()
+            .env("PATH", "/usr/bin:/bin")
+            .env("HOME", SANDBOX_DIR)
+            .stdin(Stdio::null());
+        cmd
+    }
+
+    /// Waits for `child` to exit, killing it once the deadline passes, in which case the status
+    /// comes with `true`.
+    fn wait(&self, child: &mut Child) -> PlaygroundResult<(ExitStatus, bool)> {
+        loop {
+            if let Some(status) = child.try_wait()? {
+                return Ok((status, false));
+            }
+            if self.cancelled.load(Ordering::Relaxed) {
+                let _ = child.kill();
+                child.wait()?;
+                return Err(PlaygroundError::Timeout);
+            }
+            if Instant::now() >= self.deadline {
+                let _ = child.kill();
+                return Ok((child.wait()?, true));
+            }
+            thread::sleep(POLL_INTERVAL);
+        }
+    }
+}
+
+impl Drop for Job {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.dir);
+    }
+}
+
+/// Flags of every rustc invocation, matching the profiles cargo would use.
+fn rustc_args(edition: &'static str, mode: &'static str) -> Vec<&'static str> {
+    let mut args = vec!["--edition", edition, "--crate-name", "playground"];
+    args.extend(["--crate-type", "bin"]);
+    if mode == "release" {
+        args.extend(["-C", "opt-level=3"]);
+    } else {
+        args.extend(["-C", "debuginfo=2"]);
+    }
+    args
+}
+
+#[cfg(unix)]
+fn set_limits(cmd: &mut Command, limits: SandboxLimits, limit_output: bool) {
+    use std::os::unix::process::CommandExt;
+
+    let rlimits = [
+        (libc::RLIMIT_AS, limits.memory),
+        (libc::RLIMIT_CPU, limits.cpu_time),
+        (libc::RLIMIT_NPROC, limits.processes),
+        (libc::RLIMIT_CORE, 0),
+        (
+            libc::RLIMIT_FSIZE,
+            if limit_output {
+                limits.output
+            } else {
+                libc::RLIM_INFINITY
+            },
+        ),
+    ];
+    // SAFETY: the limits are a plain array moved in, so the closure only calls getrlimit and
+    // setrlimit, which are async-signal-safe, and builds errors from errno without allocating
+    unsafe {
+        cmd.pre_exec(move || {
+            for &(resource, limit) in &rlimits {
+                let mut rlimit = libc::rlimit {
+                    rlim_cur: 0,
+                    rlim_max: 0,
+                };
+                if libc::getrlimit(resource, &mut rlimit) != 0 {
+                    return Err(io::Error::last_os_error());
+                }
+                // Raising the hard limit needs privileges the bot should not have
+                rlimit.rlim_max = rlimit.rlim_max.min(limit);
+                rlimit.rlim_cur = rlimit.rlim_max;
+                if libc::setrlimit(resource, &rlimit) != 0 {
+                    return Err(io::Error::last_os_error());
+                }
+            }
+            Ok(())
+        });
+    }
+}
+
+#[cfg(not(unix))]
+fn set_limits(_cmd: &mut Command, _limits: SandboxLimits, _limit_output: bool) {}
+
+fn join_stderr(mut build: String, program: &str) -> String {
+    if !build.is_empty() && !build.ends_with('\n') {
+        build.push('\n');
+    }
+    build + RUNNING_LINE + program
+}
+
+/// Reads what a command printed, cut at `limit` bytes.
+fn read_output(path: &Path, limit: u64) -> io::Result<String> {
+    let mut buf = Vec::new();
+    File::open(path)?.take(limit).read_to_end(&mut buf)?;
+    Ok(String::from_utf8_lossy(&buf).into_owned())
+}
+
+fn exit_detail(status: ExitStatus) -> String {
+    #[cfg(unix)]
+    {
+        use std::os::unix::process::ExitStatusExt;
+
+        if let Some(signal) = status.signal() {
+            return format!("Exited with signal {signal}");
+        }
+    }
+    match status.code() {
+        Some(0) | None => "".into(),
+        Some(code) => format!("Exited with status {code}"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A job in a directory of its own, as `Job` removes its directory on drop.
+    fn test_job(name: &str) -> Job {
+        let work_dir = std::env::temp_dir().join(format!("ebrz-test-{}", std::process::id()));
+        Job {
+            dir: work_dir.join(name),
+            config: Arc::new(SandboxConfig {
+                bwrap: "/usr/bin/bwrap".into(),
+                work_dir,
+                toolchains: HashMap::from([("stable", "/opt/rust/stable".into())]),
+                ro_binds: vec!["/usr".into(), "/lib64".into()],
+                limits: SandboxLimits {
+                    memory: 1 << 30,
+                    cpu_time: 10,
+                    output: 1 << 16,
+                    processes: 64,
+                },
+                timeout: Duration::from_secs(10),
+                max_jobs: 1,
+            }),
+            deadline: Instant::now() + Duration::from_secs(10),
+            cancelled: Arc::new(AtomicBool::new(false)),
+        }
+    }
+
+    #[test]
+    fn test_sandbox_command() {
+        let job = test_job("sandbox-command");
+        let toolchain = job.toolchain("stable").unwrap();
+        let cmd = job.sandbox_command(
+            toolchain,
+            &toolchain.join("bin/rustc"),
+            &rustc_args("2021", "release"),
+        );
+        assert_eq!(cmd.get_program(), "/usr/bin/bwrap");
+        let args = cmd
+            .get_args()
+            .map(|arg| arg.to_str().unwrap())
+            .collect::<Vec<_>>()
+            .join(" ");
+        assert_eq!(
+            args,
+            format!(
+                "--unshare-all --die-with-parent --new-session \
+                --ro-bind-try /usr /usr --ro-bind-try /lib64 /lib64 \
+                --ro-bind /opt/rust/stable /opt/rust/stable \
+                --bind {} /playground \
+                --proc /proc --dev /dev --tmpfs /tmp --chdir /playground -- \
+                /opt/rust/stable/bin/rustc --edition 2021 --crate-name playground \
+                --crate-type bin -C opt-level=3",
+                job.dir.join("playground").display()
+            )
+        );
+        assert!(cmd
+            .get_envs()
+            .all(|(key, _)| key == "PATH" || key == "HOME"));
+        assert!(matches!(
+            job.toolchain("nightly"),
+            Err(PlaygroundError::Unavailable(_))
+        ));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_wait_timeout() {
+        let mut job = test_job("wait-timeout");
+        let mut child = Command::new("true").spawn().unwrap();
+        let (status, timed_out) = job.wait(&mut child).unwrap();
+        assert!(status.success() && !timed_out);
+
+        job.deadline = Instant::now();
+        let mut child = Command::new("sleep").arg("10").spawn().unwrap();
+        let (_, timed_out) = job.wait(&mut child).unwrap();
+        assert!(timed_out);
+
+        job.cancelled.store(true, Ordering::Relaxed);
+        let mut child = Command::new("sleep").arg("10").spawn().unwrap();
+        assert!(matches!(
+            job.wait(&mut child),
+            Err(PlaygroundError::Timeout)
+        ));
+    }
+
+    #[test]
+    fn test_join_stderr() {
+        assert_eq!(
+            join_stderr("warning: unused variable\n".into(), "error: oops\n"),
+            "warning: unused variable\n     Running `target/playground`\nerror: oops\n"
+        );
+        assert_eq!(
+            join_stderr("".into(), ""),
+            "     Running `target/playground`\n"
+        );
+    }
+
+    #[test]
+    fn test_job_slots() {
+        let slots = Arc::new(JobSlots::new(2));
+        let first = slots.try_acquire().unwrap();
+        let _second = slots.try_acquire().unwrap();
+        assert!(slots.try_acquire().is_none());
+        drop(first);
+        assert!(slots.try_acquire().is_some());
+    }
+}
@@ -1,6 +1,6 @@
-use std::{future::Future, sync::Arc};
+use std::{future::Future, io, sync::Arc, time::Duration};
 
-use serde::{Deserialize, Serialize};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
 use thiserror::Error;
 
 #[derive(Debug, Error)]
@@ -9,6 +9,14 @@ pub enum PlaygroundError {
     Request(cyper::Error),
     #[error("timeout")]
     Timeout,
+    #[error("server returned {0}")]
+    Status(u16),
+    #[error("invalid response")]
+    InvalidResponse(#[source] serde_json::Error),
+    #[error("sandbox error: {0}")]
+    Sandbox(#[from] io::Error),
+    #[error("{0} is not available")]
+    Unavailable(String),
 }
 
 impl From<cyper::Error> for PlaygroundError {
@@ -30,6 +38,23 @@ pub struct PlaygroundExecuteResult {
     pub result_exit_detail: String,
     pub result_stdout: String,
     pub result_stderr: String,
+    /// Which backend ran the code and its average latency, when there is more than one.
+    pub result_backend: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PlaygroundFormatResult {
+    pub success: bool,
+    pub code: String,
+    pub stderr: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PlaygroundCompileResult {
+    pub success: bool,
+    pub exit_detail: String,
+    pub code: String,
+    pub stderr: String,
 }
 
 pub trait IPlaygrounService {
@@ -39,8 +64,39 @@ pub trait IPlaygrounService {
         channel: &'static str,
         mode: &'static str,
         edition: &'static str,
+        tests: bool,
+    ) -> impl Future<Output = PlaygroundResult<PlaygroundExecuteResult>>;
+    fn run_miri(
+        &self,
+        code: &str,
+        edition: &'static str,
+        tests: bool,
+    ) -> impl Future<Output = PlaygroundResult<PlaygroundExecuteResult>>;
+    fn clippy(
+        &self,
+        code: &str,
+        channel: &'static str,
+        edition: &'static str,
     ) -> impl Future<Output = PlaygroundResult<PlaygroundExecuteResult>>;
-    // TODO: run miri
+    fn format(
+        &self,
+        code: &str,
+        channel: &'static str,
+        edition: &'static str,
+    ) -> impl Future<Output = PlaygroundResult<PlaygroundFormatResult>>;
+    fn compile(
+        &self,
+        code: &str,
+        target: &'static str,
+        channel: &'static str,
+        mode: &'static str,
+        edition: &'static str,
+    ) -> impl Future<Output = PlaygroundResult<PlaygroundCompileResult>>;
+    fn macro_expansion(
+        &self,
+        code: &str,
+        edition: &'static str,
+    ) -> impl Future<Output = PlaygroundResult<PlaygroundCompileResult>>;
     fn generate_link(
         &self,
         code: &str,
@@ -50,6 +106,43 @@ pub trait IPlaygrounService {
     ) -> impl Future<Output = PlaygroundResult<String>>;
 }
 
+/// Fields of the playground's responses, each endpoint only fills in some of them.
+#[derive(Clone, Debug, Deserialize)]
+struct PlaygroundResponse {
+    success: bool,
+    #[serde(rename = "exitDetail", default)]
+    exit_detail: String,
+    #[serde(default)]
+    stdout: String,
+    stderr: String,
+    #[serde(default)]
+    code: String,
+}
+
+impl From<PlaygroundResponse> for PlaygroundExecuteResult {
+    fn from(res: PlaygroundResponse) -> Self {
+        PlaygroundExecuteResult {
+            result_success: res.success,
+            result_code: "".into(),
+            result_exit_detail: res.exit_detail,
+            result_stdout: res.stdout,
+            result_stderr: res.stderr,
+            result_backend: "".into(),
+        }
+    }
+}
+
+impl From<PlaygroundResponse> for PlaygroundCompileResult {
+    fn from(res: PlaygroundResponse) -> Self {
+        PlaygroundCompileResult {
+            success: res.success,
+            exit_detail: res.exit_detail,
+            code: res.code,
+            stderr: res.stderr,
+        }
+    }
+}
+
 #[derive(Clone)]
 pub struct PlaygroundService {
     base_url: Arc<String>,
@@ -63,6 +156,32 @@ impl PlaygroundService {
             client: cyper::Client::new(),
         }
     }
+
+    /// Gives up on requests that take longer than `timeout`.
+    pub fn with_timeout(base_url: String, timeout: Duration) -> Self {
+        Self {
+            base_url: Arc::new(base_url),
+            client: cyper::Client::builder().timeout(timeout).build(),
+        }
+    }
+
+    async fn post<Req: Serialize, Res: DeserializeOwned>(
+        &self,
+        path: &str,
+        req: &Req,
+    ) -> PlaygroundResult<Res> {
+        let res = self
+            .client
+            .post(format!("{}{path}", self.base_url))?
+            .json(req)?
+            .send()
+            .await?;
+        if !res.status().is_success() {
+            return Err(PlaygroundError::Status(res.status().as_u16()));
+        }
+        let text = res.text().await?;
+        serde_json::from_str(&text).map_err(PlaygroundError::InvalidResponse)
+    }
 }
 
 impl IPlaygrounService for PlaygroundService {
@@ -72,6 +191,7 @@ impl IPlaygrounService for PlaygroundService {
         channel: &'static str,
         mode: &'static str,
         edition: &'static str,
+        tests: bool,
     ) -> PlaygroundResult<PlaygroundExecuteResult> {
         #[derive(Clone, Debug, Serialize)]
         struct RunRequest<'a> {
@@ -85,35 +205,170 @@ impl IPlaygrounService for PlaygroundService {
             // backtrace: bool,
             code: &'a str,
         }
-        #[derive(Clone, Debug, Deserialize)]
-        struct RunResponse {
-            pub(crate) success: bool,
-            #[serde(rename = "exitDetail")]
-            pub(crate) exit_detail: String,
-            pub(crate) stdout: String,
-            pub(crate) stderr: String,
+        let result: PlaygroundResponse = self
+            .post(
+                "/execute",
+                &RunRequest {
+                    channel,
+                    mode,
+                    edition,
+                    crate_type: "bin",
+                    tests,
+                    code,
+                },
+            )
+            .await?;
+        Ok(result.into())
+    }
+
+    async fn run_miri(
+        &self,
+        code: &str,
+        edition: &'static str,
+        tests: bool,
+    ) -> PlaygroundResult<PlaygroundExecuteResult> {
+        // Miri is only available on the nightly channel, which the playground picks implicitly
+        #[derive(Clone, Debug, Serialize)]
+        struct MiriRequest<'a> {
+            code: &'a str,
+            edition: &'a str,
+            tests: bool,
         }
-        let res = self
-            .client
-            .post(format!("{}/execute", self.base_url))?
-            .json(&RunRequest {
-                channel,
-                mode,
-                edition,
-                crate_type: "bin",
-                tests: false,
-                code,
-            })?
-            .send()
+        let result: PlaygroundResponse = self
+            .post(
+                "/miri",
+                &MiriRequest {
+                    code,
+                    edition,
+                    tests,
+                },
+            )
             .await?;
-        let text = res.text().await?;
-        let result: RunResponse = serde_json::from_str(&text).map_err(cyper::Error::Json)?;
-        Ok(PlaygroundExecuteResult {
-            result_success: result.success,
-            result_code: "".into(),
-            result_exit_detail: result.exit_detail,
-            result_stdout: result.stdout,
-            result_stderr: result.stderr,
+        Ok(result.into())
+    }
+
+    async fn clippy(
+        &self,
+        code: &str,
+        channel: &'static str,
+        edition: &'static str,
+    ) -> PlaygroundResult<PlaygroundExecuteResult> {
+        #[derive(Clone, Debug, Serialize)]
+        struct ClippyRequest<'a> {
+            channel: &'a str,
+            edition: &'a str,
+            #[serde(rename = "crateType")]
+            crate_type: &'a str,
+            code: &'a str,
+        }
+        let result: PlaygroundResponse = self
+            .post(
+                "/clippy",
+                &ClippyRequest {
+                    channel,
+                    edition,
+                    crate_type: "bin",
+                    code,
+                },
+            )
+            .await?;
+        Ok(result.into())
+    }
+
+    async fn format(
+        &self,
+        code: &str,
+        channel: &'static str,
+        edition: &'static str,
+    ) -> PlaygroundResult<PlaygroundFormatResult> {
+        #[derive(Clone, Debug, Serialize)]
+        struct FormatRequest<'a> {
+            channel: &'a str,
+            edition: &'a str,
+            code: &'a str,
+        }
+        let result: PlaygroundResponse = self
+            .post(
+                "/format",
+                &FormatRequest {
+                    channel,
+                    edition,
+                    code,
+                },
+            )
+            .await?;
+        Ok(PlaygroundFormatResult {
+            success: result.success,
+            code: result.code,
+            stderr: result.stderr,
+        })
+    }
+
+    async fn compile(
+        &self,
+        code: &str,
+        target: &'static str,
+        channel: &'static str,
+        mode: &'static str,
+        edition: &'static str,
+    ) -> PlaygroundResult<PlaygroundCompileResult> {
+        #[derive(Clone, Debug, Serialize)]
+        struct CompileRequest<'a> {
+            target: &'a str,
+            #[serde(rename = "assemblyFlavor")]
+            assembly_flavor: &'a str,
+            #[serde(rename = "demangleAssembly")]
+            demangle_assembly: &'a str,
+            #[serde(rename = "processAssembly")]
+            process_assembly: &'a str,
+            channel: &'a str,
+            mode: &'a str,
+            edition: &'a str,
+            #[serde(rename = "crateType")]
+            crate_type: &'a str,
+            tests: bool,
+            code: &'a str,
+        }
+        let result: PlaygroundResponse = self
+            .post(
+                "/compile",
+                &CompileRequest {
+                    target,
+                    assembly_flavor: "intel",
+                    demangle_assembly: "demangle",
+                    process_assembly: "filter",
+                    channel,
+                    mode,
+                    edition,
+                    crate_type: "bin",
+                    tests: false,
+                    code,
+                },
+            )
+            .await?;
+        Ok(result.into())
+    }
+
+    async fn macro_expansion(
+        &self,
+        code: &str,
+        edition: &'static str,
+    ) -> PlaygroundResult<PlaygroundCompileResult> {
+        // Expansion needs `-Zunpretty`, the playground always runs it on nightly
+        #[derive(Clone, Debug, Serialize)]
+        struct MacroExpansionRequest<'a> {
+            code: &'a str,
+            edition: &'a str,
+        }
+        let result: PlaygroundResponse = self
+            .post("/macro-expansion", &MacroExpansionRequest { code, edition })
+            .await?;
+        // The expansion is what rustc printed
+        Ok(PlaygroundCompileResult {
+            success: result.success,
+            exit_detail: result.exit_detail,
+            code: result.stdout,
+            stderr: result.stderr,
         })
     }
 
@@ -129,19 +384,12 @@ impl IPlaygrounService for PlaygroundService {
             code: &'a str,
         }
         #[derive(Clone, Debug, Deserialize)]
-        struct GistResponse<'a> {
-            pub(crate) id: &'a str,
+        struct GistResponse {
+            pub(crate) id: String,
             // pub(crate) url: String,
             // pub(crate) code: String,
         }
-        let res = self
-            .client
-            .post(format!("{}/meta/gist", self.base_url))?
-            .json(&CreateGistRequest { code })?
-            .send()
-            .await?;
-        let text = res.text().await?;
-        let result: GistResponse = serde_json::from_str(&text).map_err(cyper::Error::Json)?;
+        let result: GistResponse = self.post("/meta/gist", &CreateGistRequest { code }).await?;
         Ok(format!(
             "https://play.rust-lang.org/?version={channel}&mode={mode}&edition={edition}&gist={}",
             result.id
@@ -154,63 +402,343 @@ mod tests {
     use serde_json::json;
 
     use super::*;
+    use crate::testing::FakePlayground;
 
-    #[compio::test]
-    async fn test_run_code() {
-        let service = PlaygroundService::new("https://play.rust-lang.org".into());
-        let res = service
-            .run_code(
-                r#"fn main() { println!("Hello, world!"); }"#,
-                "stable",
-                "debug",
-                "2021",
-            )
+    const HELLO_WORLD: &str = r#"fn main() { println!("Hello, world!"); }"#;
+
+    // Recorded from play.rust-lang.org
+    const STDERR_SUCCESS: &str = "   Compiling playground v0.0.1 (/playground)\n    Finished `dev` profile [unoptimized + debuginfo] target(s) in 0.67s\n     Running `target/debug/playground`\n";
+    const STDERR_WARNING: &str = "   Compiling playground v0.0.1 (/playground)\nwarning: unused variable: `a`\n --> src/main.rs:1:17\n  |\n1 | fn main() { let a = 1; println!(\"Hello, world!\"); }\n  |                 ^ help: if this is intentional, prefix it with an underscore: `_a`\n  |\n  = note: `#[warn(unused_variables)]` on by default\n\nwarning: `playground` (bin \"playground\") generated 1 warning\n    Finished `dev` profile [unoptimized + debuginfo] target(s) in 0.76s\n     Running `target/debug/playground`\n";
+    const STDERR_ERROR: &str = "   Compiling playground v0.0.1 (/playground)\nerror[E0277]: cannot add `&str` to `{integer}`\n --> src/main.rs:1:24\n  |\n1 | fn main() { let a = 1;a+\"\"; println!(\"Hello, world!\"); }\n  |                        ^ no implementation for `{integer} + &str`\n  |\n  = help: the trait `Add<&str>` is not implemented for `{integer}`\n  = help: the following other types implement trait `Add<Rhs>`:\n            `&f128` implements `Add<f128>`\n            `&f128` implements `Add`\n            `&f16` implements `Add<f16>`\n            `&f16` implements `Add`\n            `&f32` implements `Add<f32>`\n            `&f32` implements `Add`\n            `&f64` implements `Add<f64>`\n            `&f64` implements `Add`\n          and 56 others\n\nFor more information about this error, try `rustc --explain E0277`.\nerror: could not compile `playground` (bin \"playground\") due to 1 previous error\n";
+
+    async fn start() -> (PlaygroundService, FakePlayground) {
+        let playground = FakePlayground::start().await;
+        let service =
+            PlaygroundService::with_timeout(playground.url.clone(), Duration::from_secs(1));
+        (service, playground)
+    }
+
+    async fn run_hello_world(
+        service: &PlaygroundService,
+    ) -> PlaygroundResult<PlaygroundExecuteResult> {
+        service
+            .run_code(HELLO_WORLD, "stable", "debug", "2021", false)
             .await
-            .unwrap();
-        assert!(res.result_success);
-        assert_eq!(res.result_stdout, "Hello, world!\n");
     }
 
     #[compio::test]
-    async fn test_playground() {
-        // "stderr":"   Compiling playground v0.0.1 (/playground)\n    Finished `dev` profile [unoptimized + debuginfo] target(s) in 0.67s\n     Running `target/debug/playground`\n"
-        // "stderr":"   Compiling playground v0.0.1 (/playground)\nwarning: unused variable: `a`\n --> src/main.rs:1:17\n  |\n1 | fn main() { let a = 1; println!(\"Hello, world!\"); }\n  |                 ^ help: if this is intentional, prefix it with an underscore: `_a`\n  |\n  = note: `#[warn(unused_variables)]` on by default\n\nwarning: `playground` (bin \"playground\") generated 1 warning\n    Finished `dev` profile [unoptimized + debuginfo] target(s) in 0.76s\n     Running `target/debug/playground`\n"
-        // "stderr":"   Compiling playground v0.0.1 (/playground)\nerror[E0277]: cannot add `&str` to `{integer}`\n --> src/main.rs:1:24\n  |\n1 | fn main() { let a = 1;a+\"\"; println!(\"Hello, world!\"); }\n  |
-        //      ^ no implementation for `{integer} + &str`\n  |\n  = help: the trait `Add<&str>` is not implemented for `{integer}`\n  = help: the following other types implement trait `Add<Rhs>`:\n            `&f128` implements `Add<f128>`\n            `&f128` implements `Add`\n            `&f16` implements `Add<f16>`\n            `&f16` implements `Add`\n            `&f32` implements `Add<f32>`\n            `&f32` implements `Add`\n            `&f64` implements `Add<f64>`\n            `&f64` implements `Add`\n          and 56 others\n\nFor more information about this error, try `rustc --explain E0277`.\nerror: could not compile `playground` (bin \"playground\") due to 1 previous error\n"
-        let client = cyper::Client::new();
-        let res = client
-            .post("https://play.rust-lang.org/execute")
-            .unwrap()
-            .json(&json!({
+    async fn test_run_code() {
+        let (service, playground) = start().await;
+        playground.respond(
+            "/execute",
+            json!({
+                "success": true,
+                "exitDetail": "",
+                "stdout": "Hello, world!\n",
+                "stderr": STDERR_SUCCESS,
+            }),
+        );
+        let res = run_hello_world(&service).await.unwrap();
+        assert_eq!(
+            res,
+            PlaygroundExecuteResult {
+                result_success: true,
+                result_code: "".into(),
+                result_exit_detail: "".into(),
+                result_stdout: "Hello, world!\n".into(),
+                result_stderr: STDERR_SUCCESS.into(),
+                result_backend: "".into(),
+            }
+        );
+        assert_eq!(
+            playground.requests("/execute"),
+            [json!({
                 "channel": "stable",
                 "mode": "debug",
                 "edition": "2021",
                 "crateType": "bin",
                 "tests": false,
-                "code": r#"fn main() { let a = 1;a+""; println!("Hello, world!"); }"#
-            }))
-            .unwrap()
-            .send()
+                "code": HELLO_WORLD,
+            })]
+        );
+    }
+
+    #[compio::test]
+    async fn test_run_code_diagnostics() {
+        let (service, playground) = start().await;
+        playground.respond(
+            "/execute",
+            json!({
+                "success": true,
+                "exitDetail": "",
+                "stdout": "Hello, world!\n",
+                "stderr": STDERR_WARNING,
+            }),
+        );
+        let res = run_hello_world(&service).await.unwrap();
+        assert!(res.result_success);
+        assert_eq!(res.result_stderr, STDERR_WARNING);
+
+        playground.respond(
+            "/execute",
+            json!({
+                "success": false,
+                "exitDetail": "",
+                "stdout": "",
+                "stderr": STDERR_ERROR,
+            }),
+        );
+        let res = run_hello_world(&service).await.unwrap();
+        assert!(!res.result_success);
+        assert_eq!(res.result_stdout, "");
+        assert_eq!(res.result_stderr, STDERR_ERROR);
+    }
+
+    #[compio::test]
+    async fn test_tools() {
+        let (service, playground) = start().await;
+        // Tool responses may leave out `exitDetail`
+        playground.respond(
+            "/miri",
+            json!({ "success": false, "stdout": "", "stderr": "error: Undefined Behavior" }),
+        );
+        playground.respond(
+            "/format",
+            json!({ "success": true, "code": "fn main() {}\n", "stderr": "" }),
+        );
+        playground.respond(
+            "/macro-expansion",
+            json!({ "success": true, "stdout": "fn main() {}", "stderr": "" }),
+        );
+        let res = service.run_miri(HELLO_WORLD, "2021", false).await.unwrap();
+        assert!(!res.result_success);
+        assert_eq!(res.result_exit_detail, "");
+        let res = service
+            .format("fn main(){}", "stable", "2021")
             .await
             .unwrap();
-        let text = res.text().await.unwrap();
-        println!("{text}");
+        assert_eq!(res.code, "fn main() {}\n");
+        let res = service.macro_expansion(HELLO_WORLD, "2021").await.unwrap();
+        assert_eq!(res.code, "fn main() {}");
+        assert_eq!(playground.requests("/miri")[0]["tests"], false);
+        service.run_miri(HELLO_WORLD, "2021", true).await.unwrap();
+        assert_eq!(playground.requests("/miri")[1]["tests"], true);
     }
 
     #[compio::test]
     async fn test_generate_link() {
-        let service = PlaygroundService::new("https://play.rust-lang.org".into());
+        let (service, playground) = start().await;
+        playground.respond(
+            "/meta/gist",
+            json!({
+                "id": "0123456789abcdef",
+                "url": "https://gist.github.com/rust-play/0123456789abcdef",
+                "code": HELLO_WORLD,
+            }),
+        );
         let res = service
-            .generate_link(
-                r#"fn main() { println!("Hello, world!"); }"#,
-                "stable",
-                "debug",
-                "2021",
-            )
+            .generate_link(HELLO_WORLD, "stable", "debug", "2021")
             .await
             .unwrap();
-        assert!(res.starts_with(
-            "https://play.rust-lang.org/?version=stable&mode=debug&edition=2021&gist="
-        ));
+        assert_eq!(
+            res,
+            "https://play.rust-lang.org/?version=stable&mode=debug&edition=2021&gist=0123456789abcdef"
+        );
+        assert_eq!(
+            playground.requests("/meta/gist"),
+            [json!({ "code": HELLO_WORLD })]
+        );
+    }
+
+    #[compio::test]
+    async fn test_malformed_response() {
+        let (service, playground) = start().await;
+        playground.respond_raw("/execute", "200 OK", "<html>Bad Gateway</html>");
+        let res = run_hello_world(&service).await;
+        assert!(matches!(res, Err(PlaygroundError::InvalidResponse(_))));
+
+        playground.respond("/execute", json!({ "success": true }));
+        let res = run_hello_world(&service).await;
+        assert!(matches!(res, Err(PlaygroundError::InvalidResponse(_))));
+    }
+
+    #[compio::test]
+    async fn test_error_status() {
+        let (service, playground) = start().await;
+        playground.respond_raw(
+            "/execute",
+            "500 Internal Server Error",
+            r#"{"error":"The operation timed out"}"#,
+        );
+        let res = run_hello_world(&service).await;
+        assert!(matches!(res, Err(PlaygroundError::Status(500))));
+        let res = service
+            .generate_link(HELLO_WORLD, "stable", "debug", "2021")
+            .await;
+        assert!(matches!(res, Err(PlaygroundError::Status(404))));
+    }
+
+    #[compio::test]
+    async fn test_timeout() {
+        let playground = FakePlayground::start().await;
+        let service =
+            PlaygroundService::with_timeout(playground.url.clone(), Duration::from_millis(100));
+        playground.respond_after(
+            "/execute",
+            Duration::from_secs(5),
+            json!({ "success": true, "exitDetail": "", "stdout": "", "stderr": "" }),
+        );
+        let res = run_hello_world(&service).await;
+        assert!(matches!(res, Err(PlaygroundError::Timeout)));
+    }
+
+    /// Runs against play.rust-lang.org, with `--features live-playground-tests`.
+    #[cfg(feature = "live-playground-tests")]
+    mod live {
+        use serde_json::json;
+
+        use super::*;
+
+        #[compio::test]
+        async fn test_run_code() {
+            let service = PlaygroundService::new("https://play.rust-lang.org".into());
+            let res = service
+                .run_code(
+                    r#"fn main() { println!("Hello, world!"); }"#,
+                    "stable",
+                    "debug",
+                    "2021",
+                    false,
+                )
+                .await
+                .unwrap();
+            assert!(res.result_success);
+            assert_eq!(res.result_stdout, "Hello, world!\n");
+        }
+
+        #[compio::test]
+        async fn test_run_tests() {
+            let service = PlaygroundService::new("https://play.rust-lang.org".into());
+            let res = service
+                .run_code(
+                    "#[test] fn a() {}\n#[test] fn b() { assert_eq!(1, 2); }",
+                    "stable",
+                    "debug",
+                    "2021",
+                    true,
+                )
+                .await
+                .unwrap();
+            assert!(!res.result_success);
+            assert!(res.result_stdout.contains("1 passed; 1 failed"));
+        }
+
+        #[compio::test]
+        async fn test_run_miri() {
+            let service = PlaygroundService::new("https://play.rust-lang.org".into());
+            let res = service
+                .run_miri(
+                    r#"fn main() { let a = [1u8; 2]; let p = a.as_ptr(); println!("{}", unsafe { *p.add(2) }); }"#,
+                    "2021",
+                    false,
+                )
+                .await
+                .unwrap();
+            assert!(!res.result_success);
+            assert!(res.result_stderr.contains("Undefined Behavior"));
+        }
+
+        #[compio::test]
+        async fn test_clippy() {
+            let service = PlaygroundService::new("https://play.rust-lang.org".into());
+            let res = service
+                .clippy(
+                    r#"fn main() { let v = vec![1]; if v.len() == 0 { println!("empty"); } }"#,
+                    "stable",
+                    "2021",
+                )
+                .await
+                .unwrap();
+            assert!(res.result_success);
+            assert!(res.result_stderr.contains("clippy::len_zero"));
+        }
+
+        #[compio::test]
+        async fn test_format() {
+            let service = PlaygroundService::new("https://play.rust-lang.org".into());
+            let res = service
+                .format("fn main(){let a=1;}", "stable", "2021")
+                .await
+                .unwrap();
+            assert!(res.success);
+            assert_eq!(res.code, "fn main() {\n    let a = 1;\n}\n");
+        }
+
+        #[compio::test]
+        async fn test_compile() {
+            let service = PlaygroundService::new("https://play.rust-lang.org".into());
+            let res = service
+                .compile(
+                    "pub fn square(x: u32) -> u32 { x * x }\nfn main() {}",
+                    "asm",
+                    "stable",
+                    "release",
+                    "2021",
+                )
+                .await
+                .unwrap();
+            assert!(res.success);
+            assert!(res.code.contains("square"));
+        }
+
+        #[compio::test]
+        async fn test_macro_expansion() {
+            let service = PlaygroundService::new("https://play.rust-lang.org".into());
+            let res = service
+                .macro_expansion(r#"fn main() { println!("{}", 1); }"#, "2021")
+                .await
+                .unwrap();
+            assert!(res.success);
+            assert!(res.code.contains("format_args!"));
+        }
+
+        #[compio::test]
+        async fn test_playground() {
+            let client = cyper::Client::new();
+            let res = client
+                .post("https://play.rust-lang.org/execute")
+                .unwrap()
+                .json(&json!({
+                    "channel": "stable",
+                    "mode": "debug",
+                    "edition": "2021",
+                    "crateType": "bin",
+                    "tests": false,
+                    "code": r#"fn main() { let a = 1;a+""; println!("Hello, world!"); }"#
+                }))
+                .unwrap()
+                .send()
+                .await
+                .unwrap();
+            let text = res.text().await.unwrap();
+            println!("{text}");
+        }
+
+        #[compio::test]
+        async fn test_generate_link() {
+            let service = PlaygroundService::new("https://play.rust-lang.org".into());
+            let res = service
+                .generate_link(
+                    r#"fn main() { println!("Hello, world!"); }"#,
+                    "stable",
+                    "debug",
+                    "2021",
+                )
+                .await
+                .unwrap();
+            assert!(res.starts_with(
+                "https://play.rust-lang.org/?version=stable&mode=debug&edition=2021&gist="
+            ));
+        }
     }
 }
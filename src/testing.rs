@@ -0,0 +1,69 @@
+//! In-process stand-ins for the services the bot talks to.
+
+mod bot_api;
+mod memory_repository;
+mod mock_playground;
+mod playground;
+
+use std::{
+    future::Future,
+    rc::Rc,
+    time::{Duration, Instant},
+};
+
+use compio::{net::TcpListener, runtime::spawn, time::sleep};
+
+use crate::http::{read_request, write_response, Request};
+
+pub(crate) use bot_api::FakeBotApi;
+pub(crate) use memory_repository::MemoryRepository;
+pub(crate) use mock_playground::{MockPlayground, MockResponse};
+pub(crate) use playground::FakePlayground;
+
+const POLL_INTERVAL: Duration = Duration::from_millis(10);
+const WAIT_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Serves `respond` on a random local port, returning its base URL.
+///
+/// The server is never stopped, it goes away with the runtime of the test.
+async fn serve<F, Fut>(respond: F) -> String
+where
+    F: Fn(Request) -> Fut + 'static,
+    Fut: Future<Output = (&'static str, Vec<u8>)> + 'static,
+{
+    let listener = TcpListener::bind("127.0.0.1:0")
+        .await
+        .expect("Cannot bind fake server");
+    let addr = listener.local_addr().unwrap();
+    let respond = Rc::new(respond);
+    spawn(async move {
+        while let Ok((mut stream, _)) = listener.accept().await {
+            let respond = respond.clone();
+            spawn(async move {
+                let (status, body) = match read_request(&mut stream, usize::MAX).await {
+                    Ok(Ok(req)) => respond(req).await,
+                    Ok(Err(status)) => (status, vec![]),
+                    Err(_) => return,
+                };
+                let _ = write_response(&mut stream, status, body).await;
+            })
+            .detach();
+        }
+    })
+    .detach();
+    format!("http://{addr}")
+}
+
+/// Polls `f` until it returns something, giving up after [`WAIT_TIMEOUT`].
+async fn wait_for<T>(mut f: impl FnMut() -> Option<T>) -> Option<T> {
+    let deadline = Instant::now() + WAIT_TIMEOUT;
+    loop {
+        if let Some(res) = f() {
+            return Some(res);
+        }
+        if Instant::now() >= deadline {
+            return None;
+        }
+        sleep(POLL_INTERVAL).await;
+    }
+}
@@ -0,0 +1,321 @@
+use std::{
+    cell::RefCell,
+    collections::{HashMap, VecDeque},
+    rc::Rc,
+};
+
+use compio::time::sleep;
+use serde_json::{json, Value};
+
+use crate::http::Request;
+
+use super::{serve, wait_for, POLL_INTERVAL};
+
+const BOT_USER_ID: i64 = 1;
+const FIRST_MESSAGE_ID: i64 = 1000;
+
+/// A Bot API server that hands out queued updates and records what the bot calls.
+#[derive(Clone)]
+pub(crate) struct FakeBotApi {
+    pub url: String,
+    state: Rc<RefCell<BotApiState>>,
+}
+
+/// A method called by the bot.
+#[derive(Debug)]
+pub(crate) struct Call {
+    pub params: Value,
+    pub result: Value,
+}
+
+#[derive(Default)]
+struct BotApiState {
+    updates: VecDeque<Value>,
+    last_update_id: i64,
+    /// Updates below the offset of the latest `getUpdates` have been handled.
+    offset: i64,
+    last_message_id: i64,
+    calls: VecDeque<(String, Call)>,
+    failures: HashMap<String, String>,
+    /// File contents by id, with the path `getFile` gives for each.
+    files: HashMap<String, (String, Vec<u8>)>,
+}
+
+impl FakeBotApi {
+    pub async fn start() -> Self {
+        let state = Rc::new(RefCell::new(BotApiState {
+            last_message_id: FIRST_MESSAGE_ID,
+            ..Default::default()
+        }));
+        let url = serve({
+            let state = state.clone();
+            move |req| respond(state.clone(), req)
+        })
+        .await;
+        Self { url, state }
+    }
+
+    pub fn push_message(&self, chat_id: i64, user_id: i64, message_id: i64, text: &str) -> i64 {
+        self.push_update(
+            "message",
+            message(message_id, chat_id, user(user_id), text.into()),
+        )
+    }
+
+    pub fn push_edited_message(
+        &self,
+        chat_id: i64,
+        user_id: i64,
+        message_id: i64,
+        text: &str,
+    ) -> i64 {
+        let mut message = message(message_id, chat_id, user(user_id), text.into());
+        message["edit_date"] = json!(1);
+        self.push_update("edited_message", message)
+    }
+
+    /// Queues a message with the file `file_id` attached and `caption` as its text.
+    pub fn push_document(
+        &self,
+        chat_id: i64,
+        user_id: i64,
+        message_id: i64,
+        caption: &str,
+        file_id: &str,
+    ) -> i64 {
+        let mut message = message(message_id, chat_id, user(user_id), Value::Null);
+        message["caption"] = json!(caption);
+        message["document"] = json!({
+            "file_id": file_id,
+            "file_unique_id": file_id,
+            "file_name": "main.rs",
+        });
+        self.push_update("message", message)
+    }
+
+    /// Makes `file_id` known to `getFile` as `file_path`, and downloadable from there.
+    pub fn add_file(&self, file_id: &str, file_path: &str, content: &[u8]) {
+        self.state
+            .borrow_mut()
+            .files
+            .insert(file_id.into(), (file_path.into(), content.into()));
+    }
+
+    /// Queues a press of a button on the message `message_id` sent by the bot.
+    pub fn push_callback_query(
+        &self,
+        chat_id: i64,
+        user_id: i64,
+        message_id: i64,
+        data: &str,
+    ) -> i64 {
+        let update_id = self.state.borrow().last_update_id + 1;
+        self.push_update(
+            "callback_query",
+            json!({
+                "id": format!("query-{update_id}"),
+                "from": user(user_id),
+                "message": message(message_id, chat_id, bot_user(), Value::Null),
+                "chat_instance": "0",
+                "data": data,
+            }),
+        )
+    }
+
+    fn push_update(&self, kind: &str, content: Value) -> i64 {
+        let mut state = self.state.borrow_mut();
+        state.last_update_id += 1;
+        let update_id = state.last_update_id;
+        state
+            .updates
+            .push_back(json!({ "update_id": update_id, kind: content }));
+        update_id
+    }
+
+    /// Makes the next call of `method` fail with a Telegram error.
+    pub fn fail_next(&self, method: &str, description: &str) {
+        self.state
+            .borrow_mut()
+            .failures
+            .insert(method.into(), description.into());
+    }
+
+    /// Waits for the bot to call `method`, taking the earliest such call.
+    pub async fn next_call(&self, method: &str) -> Call {
+        let call = wait_for(|| {
+            let mut state = self.state.borrow_mut();
+            let pos = state.calls.iter().position(|(m, _)| m == method)?;
+            state.calls.remove(pos).map(|(_, call)| call)
+        })
+        .await;
+        call.unwrap_or_else(|| {
+            panic!(
+                "timed out waiting for {method}, got {:?}",
+                self.pending_calls()
+            )
+        })
+    }
+
+    /// Waits until the bot has polled past every queued update.
+    pub async fn wait_handled(&self) {
+        let handled = wait_for(|| {
+            let state = self.state.borrow();
+            (state.offset > state.last_update_id).then_some(())
+        })
+        .await;
+        assert!(
+            handled.is_some(),
+            "timed out waiting for updates to be handled"
+        );
+    }
+
+    /// Methods called but not taken with [`Self::next_call`] yet.
+    pub fn pending_calls(&self) -> Vec<String> {
+        let state = self.state.borrow();
+        state
+            .calls
+            .iter()
+            .map(|(method, _)| method.clone())
+            .collect()
+    }
+}
+
+async fn respond(state: Rc<RefCell<BotApiState>>, req: Request) -> (&'static str, Vec<u8>) {
+    if let Some(path) = req.path.strip_prefix("/file/") {
+        return download(&mut state.borrow_mut(), path);
+    }
+    let method = req.path.rsplit('/').next().unwrap_or_default().to_owned();
+    // Uploads are multipart, only their method is of interest
+    let params = serde_json::from_slice(&req.body).unwrap_or(Value::Null);
+    if method == "getUpdates" {
+        return ok(get_updates(&state, &params).await);
+    }
+    let mut state = state.borrow_mut();
+    if let Some(description) = state.failures.remove(&method) {
+        state.calls.push_back((
+            method,
+            Call {
+                params,
+                result: Value::Null,
+            },
+        ));
+        let body = json!({ "ok": false, "error_code": 400, "description": description });
+        return ("400 Bad Request", body.to_string().into_bytes());
+    }
+    let chat_id = params["chat_id"].as_i64().unwrap_or_default();
+    let result = match &*method {
+        "getMe" => bot_user(),
+        "sendMessage" | "sendDocument" => {
+            state.last_message_id += 1;
+            message(
+                state.last_message_id,
+                chat_id,
+                bot_user(),
+                params["text"].clone(),
+            )
+        }
+        "editMessageText" => message(
+            params["message_id"].as_i64().unwrap_or_default(),
+            chat_id,
+            bot_user(),
+            params["text"].clone(),
+        ),
+        "deleteMessage" | "answerCallbackQuery" => json!(true),
+        "getFile" => {
+            let file_id = params["file_id"].as_str().unwrap_or_default();
+            match state.files.get(file_id) {
+                Some((file_path, content)) => json!({
+                    "file_id": file_id,
+                    "file_unique_id": file_id,
+                    "file_size": content.len(),
+                    "file_path": file_path,
+                }),
+                None => {
+                    let description = "Bad Request: invalid file_id";
+                    let body =
+                        json!({ "ok": false, "error_code": 400, "description": description });
+                    return ("400 Bad Request", body.to_string().into_bytes());
+                }
+            }
+        }
+        _ => {
+            let body = json!({ "ok": false, "error_code": 404, "description": "Not Found" });
+            return ("404 Not Found", body.to_string().into_bytes());
+        }
+    };
+    state.calls.push_back((
+        method,
+        Call {
+            params,
+            result: result.clone(),
+        },
+    ));
+    ok(result)
+}
+
+/// Serves a file from `/file/bot<token>/<file_path>`, recorded as a `download` call.
+fn download(state: &mut BotApiState, path: &str) -> (&'static str, Vec<u8>) {
+    let file_path = path.split_once('/').map_or("", |(_, file_path)| file_path);
+    let content = state
+        .files
+        .values()
+        .find(|(path, _)| path == file_path)
+        .map(|(_, content)| content.clone());
+    state.calls.push_back((
+        "download".into(),
+        Call {
+            params: json!({ "file_path": file_path }),
+            result: Value::Null,
+        },
+    ));
+    match content {
+        Some(content) => ("200 OK", content),
+        None => ("404 Not Found", vec![]),
+    }
+}
+
+async fn get_updates(state: &RefCell<BotApiState>, params: &Value) -> Value {
+    {
+        let mut state = state.borrow_mut();
+        let offset = params["offset"].as_i64().unwrap_or_default();
+        state.offset = state.offset.max(offset);
+        state
+            .updates
+            .retain(|update| update["update_id"].as_i64() >= Some(offset));
+        if !state.updates.is_empty() {
+            return state.updates.iter().cloned().collect();
+        }
+    }
+    // Stands in for long polling, without keeping the bot busy
+    sleep(POLL_INTERVAL).await;
+    json!([])
+}
+
+fn ok(result: Value) -> (&'static str, Vec<u8>) {
+    let body = json!({ "ok": true, "result": result });
+    ("200 OK", body.to_string().into_bytes())
+}
+
+fn message(message_id: i64, chat_id: i64, from: Value, text: Value) -> Value {
+    // Group and channel ids are negative
+    let chat = if chat_id < 0 {
+        json!({ "id": chat_id, "type": "supergroup", "title": "Test" })
+    } else {
+        json!({ "id": chat_id, "type": "private", "first_name": "Test" })
+    };
+    json!({
+        "message_id": message_id,
+        "date": 0,
+        "chat": chat,
+        "from": from,
+        "text": text,
+    })
+}
+
+fn user(user_id: i64) -> Value {
+    json!({ "id": user_id, "is_bot": false, "first_name": "Test" })
+}
+
+fn bot_user() -> Value {
+    json!({ "id": BOT_USER_ID, "is_bot": true, "first_name": "Bot", "username": "test_bot" })
+}
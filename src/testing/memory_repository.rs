@@ -0,0 +1,318 @@
+use std::{
+    collections::BTreeSet,
+    sync::{Arc, Mutex},
+};
+
+use chrono::{DateTime, Utc};
+
+use crate::repository::{
+    bot_update::IBotUpdateRepository,
+    playground_record::{
+        CreateRevisionUpsertRecordResult, IPlaygroundRecordRepository, NewPlaygroundRecordRevision,
+        PendingPlaygroundRevision, PlaygroundRecordPageState, PlaygroundRecordRevision,
+        PlaygroundRecordRevisionId, PlaygroundRevisionStatus,
+    },
+    RepositoryResult,
+};
+
+/// Keeps records the way the SQLite repository does, without a database.
+#[derive(Clone, Default)]
+pub(crate) struct MemoryRepository {
+    state: Arc<Mutex<MemoryState>>,
+}
+
+#[derive(Default)]
+struct MemoryState {
+    records: Vec<MemoryRecord>,
+    revisions: Vec<MemoryRevision>,
+    update_offset: Option<i64>,
+    handled_updates: BTreeSet<i64>,
+}
+
+struct MemoryRecord {
+    chat_id: i64,
+    user_msg_id: i64,
+    eval_msg_id: Option<i64>,
+    created_by_user_id: i64,
+    revision_id: PlaygroundRecordRevisionId,
+    page_state: PlaygroundRecordPageState,
+    page: u32,
+}
+
+struct MemoryRevision {
+    /// Index into `records`.
+    record: usize,
+    created_at: DateTime<Utc>,
+    status: PlaygroundRevisionStatus,
+    revision: PlaygroundRecordRevision,
+}
+
+impl MemoryState {
+    fn revision_index(&self, revision_id: PlaygroundRecordRevisionId) -> Option<usize> {
+        let index = usize::try_from(revision_id.0.get()).ok()? - 1;
+        (index < self.revisions.len()).then_some(index)
+    }
+
+    fn record_of(&mut self, revision_id: PlaygroundRecordRevisionId) -> Option<&mut MemoryRecord> {
+        let index = self.revision_index(revision_id)?;
+        let record = self.revisions[index].record;
+        self.records.get_mut(record)
+    }
+
+    /// Loads a revision back, as far as the database stores it.
+    fn load_revision(&self, index: usize) -> PlaygroundRecordRevision {
+        let record = self.revisions[index].record;
+        let mut revision = self.revisions[index].revision.clone();
+        revision.record_revision_count = self
+            .revisions
+            .iter()
+            .filter(|rev| rev.record == record)
+            .count() as u32;
+        revision
+    }
+}
+
+impl IPlaygroundRecordRepository for MemoryRepository {
+    async fn create_revision_upsert_record(
+        &self,
+        chat_id: i64,
+        user_msg_id: i64,
+        created_by_user_id: i64,
+        revision: NewPlaygroundRecordRevision,
+        page_state: PlaygroundRecordPageState,
+    ) -> RepositoryResult<CreateRevisionUpsertRecordResult> {
+        let mut state = self.state.lock().unwrap();
+        let revision_id =
+            PlaygroundRecordRevisionId::try_from(state.revisions.len() as i64 + 1).unwrap();
+        let record = match state
+            .records
+            .iter()
+            .position(|r| r.chat_id == chat_id && r.user_msg_id == user_msg_id)
+        {
+            Some(index) => {
+                let record = &mut state.records[index];
+                record.revision_id = revision_id;
+                record.page_state = page_state;
+                record.page = 0;
+                index
+            }
+            None => {
+                state.records.push(MemoryRecord {
+                    chat_id,
+                    user_msg_id,
+                    eval_msg_id: None,
+                    created_by_user_id,
+                    revision_id,
+                    page_state,
+                    page: 0,
+                });
+                state.records.len() - 1
+            }
+        };
+        state.revisions.push(MemoryRevision {
+            record,
+            created_at: Utc::now(),
+            status: PlaygroundRevisionStatus::Pending,
+            revision: PlaygroundRecordRevision {
+                revision_id,
+                user_code: revision.user_code,
+                rendered_code: revision.rendered_code,
+                toolchain: revision.toolchain,
+                tests: revision.tests,
+                ..Default::default()
+            },
+        });
+        Ok(CreateRevisionUpsertRecordResult {
+            revision_id,
+            eval_msg_id: state.records[record].eval_msg_id,
+            page_state,
+        })
+    }
+
+    async fn update_eval_msg_id_for_revision_id(
+        &self,
+        revision_id: PlaygroundRecordRevisionId,
+        eval_msg_id: i64,
+    ) -> RepositoryResult<()> {
+        let mut state = self.state.lock().unwrap();
+        if let Some(record) = state.record_of(revision_id) {
+            record.eval_msg_id = Some(eval_msg_id);
+        }
+        Ok(())
+    }
+
+    async fn update_revision_for_revision_count_and_is_latest(
+        &self,
+        revision: &mut PlaygroundRecordRevision,
+    ) -> RepositoryResult<bool> {
+        let mut state = self.state.lock().unwrap();
+        let Some(index) = state.revision_index(revision.revision_id) else {
+            return Ok(false);
+        };
+        let stored = &mut state.revisions[index];
+        stored.status = PlaygroundRevisionStatus::Finished;
+        stored.revision = PlaygroundRecordRevision {
+            user_code: stored.revision.user_code.clone(),
+            rendered_code: stored.revision.rendered_code.clone(),
+            toolchain: stored.revision.toolchain,
+            tests: stored.revision.tests,
+            ..revision.clone()
+        };
+        let record = stored.record;
+        if state.records[record].revision_id != revision.revision_id {
+            return Ok(false);
+        }
+        revision.record_revision_count = state.load_revision(index).record_revision_count;
+        Ok(true)
+    }
+
+    async fn delete_record_by_revision_id_if_match(
+        &self,
+        eval_msg_id: i64,
+        created_by_user_id: i64,
+        revision_id: PlaygroundRecordRevisionId,
+    ) -> RepositoryResult<bool> {
+        let mut state = self.state.lock().unwrap();
+        match state.record_of(revision_id) {
+            Some(record)
+                if record.eval_msg_id == Some(eval_msg_id)
+                    && record.created_by_user_id == created_by_user_id =>
+            {
+                record.eval_msg_id = None;
+                Ok(true)
+            }
+            _ => Ok(false),
+        }
+    }
+
+    async fn get_revision_update_page_state_if_match(
+        &self,
+        eval_msg_id: i64,
+        created_by_user_id: i64,
+        revision_id: PlaygroundRecordRevisionId,
+        page_state: PlaygroundRecordPageState,
+        page: u32,
+    ) -> RepositoryResult<Option<PlaygroundRecordRevision>> {
+        let mut state = self.state.lock().unwrap();
+        match state.record_of(revision_id) {
+            Some(record)
+                if record.revision_id == revision_id
+                    && record.eval_msg_id == Some(eval_msg_id)
+                    && record.created_by_user_id == created_by_user_id =>
+            {
+                record.page_state = page_state;
+                record.page = page;
+            }
+            _ => return Ok(None),
+        }
+        let index = state.revision_index(revision_id).unwrap();
+        Ok(Some(state.load_revision(index)))
+    }
+
+    async fn get_revision_by_id(
+        &self,
+        revision_id: PlaygroundRecordRevisionId,
+    ) -> RepositoryResult<Option<(PlaygroundRecordRevision, PlaygroundRecordPageState, u32)>> {
+        let state = self.state.lock().unwrap();
+        let Some(index) = state.revision_index(revision_id) else {
+            return Ok(None);
+        };
+        let record = &state.records[state.revisions[index].record];
+        Ok(Some((
+            state.load_revision(index),
+            record.page_state,
+            record.page,
+        )))
+    }
+
+    async fn update_perma_link_for_revision_id(
+        &self,
+        revision_id: PlaygroundRecordRevisionId,
+        perma_link: String,
+    ) -> RepositoryResult<()> {
+        let mut state = self.state.lock().unwrap();
+        if let Some(index) = state.revision_index(revision_id) {
+            state.revisions[index].revision.perma_link = Some(perma_link);
+        }
+        Ok(())
+    }
+
+    async fn update_tool_results_for_revision_id(
+        &self,
+        revision: &PlaygroundRecordRevision,
+    ) -> RepositoryResult<()> {
+        let mut state = self.state.lock().unwrap();
+        if let Some(index) = state.revision_index(revision.revision_id) {
+            let stored = &mut state.revisions[index].revision;
+            stored.miri_success = revision.miri_success;
+            stored.miri_exit_detail = revision.miri_exit_detail.clone();
+            stored.miri_stdout = revision.miri_stdout.clone();
+            stored.miri_stderr = revision.miri_stderr.clone();
+            stored.format_success = revision.format_success;
+            stored.code_formatted = revision.code_formatted.clone();
+        }
+        Ok(())
+    }
+
+    async fn get_pending_revisions(&self) -> RepositoryResult<Vec<PendingPlaygroundRevision>> {
+        let state = self.state.lock().unwrap();
+        let res = state
+            .revisions
+            .iter()
+            .filter(|rev| rev.status == PlaygroundRevisionStatus::Pending)
+            .map(|rev| {
+                let record = &state.records[rev.record];
+                PendingPlaygroundRevision {
+                    revision: PlaygroundRecordRevision {
+                        revision_id: rev.revision.revision_id,
+                        user_code: rev.revision.user_code.clone(),
+                        rendered_code: rev.revision.rendered_code.clone(),
+                        toolchain: rev.revision.toolchain,
+                        tests: rev.revision.tests,
+                        ..Default::default()
+                    },
+                    created_at: rev.created_at,
+                    chat_id: record.chat_id,
+                    eval_msg_id: record.eval_msg_id,
+                    page_state: record.page_state,
+                    is_latest: record.revision_id == rev.revision.revision_id,
+                }
+            })
+            .collect();
+        Ok(res)
+    }
+
+    async fn update_status_for_revision_id(
+        &self,
+        revision_id: PlaygroundRecordRevisionId,
+        status: PlaygroundRevisionStatus,
+    ) -> RepositoryResult<()> {
+        let mut state = self.state.lock().unwrap();
+        if let Some(index) = state.revision_index(revision_id) {
+            state.revisions[index].status = status;
+        }
+        Ok(())
+    }
+}
+
+impl IBotUpdateRepository for MemoryRepository {
+    async fn get_update_offset(&self) -> RepositoryResult<Option<i64>> {
+        Ok(self.state.lock().unwrap().update_offset)
+    }
+
+    async fn is_update_handled(&self, update_id: i64) -> RepositoryResult<bool> {
+        Ok(self
+            .state
+            .lock()
+            .unwrap()
+            .handled_updates
+            .contains(&update_id))
+    }
+
+    async fn mark_update_handled(&self, update_id: i64) -> RepositoryResult<()> {
+        let mut state = self.state.lock().unwrap();
+        state.handled_updates.insert(update_id);
+        state.update_offset = state.update_offset.max(Some(update_id + 1));
+        Ok(())
+    }
+}
@@ -0,0 +1,204 @@
+use std::{
+    cell::RefCell,
+    collections::{HashMap, VecDeque},
+    rc::Rc,
+    time::Duration,
+};
+
+use compio::time::sleep;
+
+use crate::service::playground::{
+    IPlaygrounService, PlaygroundCompileResult, PlaygroundError, PlaygroundExecuteResult,
+    PlaygroundFormatResult, PlaygroundResult,
+};
+
+/// What a [`MockPlayground`] answers a call with.
+#[derive(Debug, Clone)]
+pub(crate) enum MockResponse {
+    /// For `run_code`, `run_miri` and `clippy`.
+    Execute(PlaygroundExecuteResult),
+    Format(PlaygroundFormatResult),
+    /// For `compile` and `macro_expansion`.
+    Compile(PlaygroundCompileResult),
+    Link(String),
+    Timeout,
+    /// The playground answered with an error status.
+    Status(u16),
+    /// The playground answered with something that is not JSON.
+    Malformed,
+    Unavailable,
+}
+
+impl MockResponse {
+    pub fn stdout(stdout: &str) -> Self {
+        MockResponse::Execute(PlaygroundExecuteResult {
+            result_success: true,
+            result_code: "".into(),
+            result_exit_detail: "".into(),
+            result_stdout: stdout.into(),
+            result_stderr: "".into(),
+            result_backend: "".into(),
+        })
+    }
+}
+
+/// A playground service answering each call with the next response scripted for its method.
+#[derive(Clone, Default)]
+pub(crate) struct MockPlayground {
+    state: Rc<RefCell<MockPlaygroundState>>,
+}
+
+#[derive(Default)]
+struct MockPlaygroundState {
+    responses: HashMap<&'static str, VecDeque<(Duration, MockResponse)>>,
+    calls: Vec<(&'static str, String)>,
+}
+
+impl MockPlayground {
+    /// Queues a response to the next call of `method`, named as in [`IPlaygrounService`].
+    pub fn respond(&self, method: &'static str, response: MockResponse) {
+        self.respond_after(method, Duration::ZERO, response);
+    }
+
+    pub fn respond_after(&self, method: &'static str, delay: Duration, response: MockResponse) {
+        self.state
+            .borrow_mut()
+            .responses
+            .entry(method)
+            .or_default()
+            .push_back((delay, response));
+    }
+
+    /// The code sent with each call of `method` so far.
+    pub fn calls(&self, method: &str) -> Vec<String> {
+        let state = self.state.borrow();
+        state
+            .calls
+            .iter()
+            .filter(|(m, _)| *m == method)
+            .map(|(_, code)| code.clone())
+            .collect()
+    }
+
+    async fn call(&self, method: &'static str, code: &str) -> PlaygroundResult<MockResponse> {
+        let (delay, response) = {
+            let mut state = self.state.borrow_mut();
+            state.calls.push((method, code.into()));
+            state
+                .responses
+                .get_mut(method)
+                .and_then(VecDeque::pop_front)
+                .unwrap_or_else(|| panic!("unexpected {method} call"))
+        };
+        if !delay.is_zero() {
+            sleep(delay).await;
+        }
+        match response {
+            MockResponse::Timeout => Err(PlaygroundError::Timeout),
+            MockResponse::Status(status) => Err(PlaygroundError::Status(status)),
+            MockResponse::Malformed => {
+                let e = serde_json::from_str::<()>("<html>").unwrap_err();
+                Err(PlaygroundError::InvalidResponse(e))
+            }
+            MockResponse::Unavailable => Err(PlaygroundError::Unavailable(method.into())),
+            response => Ok(response),
+        }
+    }
+
+    async fn execute(
+        &self,
+        method: &'static str,
+        code: &str,
+    ) -> PlaygroundResult<PlaygroundExecuteResult> {
+        match self.call(method, code).await? {
+            MockResponse::Execute(res) => Ok(res),
+            res => panic!("{res:?} is not a response to {method}"),
+        }
+    }
+
+    async fn compile_artifact(
+        &self,
+        method: &'static str,
+        code: &str,
+    ) -> PlaygroundResult<PlaygroundCompileResult> {
+        match self.call(method, code).await? {
+            MockResponse::Compile(res) => Ok(res),
+            res => panic!("{res:?} is not a response to {method}"),
+        }
+    }
+}
+
+impl IPlaygrounService for MockPlayground {
+    async fn run_code(
+        &self,
+        code: &str,
+        _channel: &'static str,
+        _mode: &'static str,
+        _edition: &'static str,
+        _tests: bool,
+    ) -> PlaygroundResult<PlaygroundExecuteResult> {
+        self.execute("run_code", code).await
+    }
+
+    async fn run_miri(
+        &self,
+        code: &str,
+        _edition: &'static str,
+        _tests: bool,
+    ) -> PlaygroundResult<PlaygroundExecuteResult> {
+        self.execute("run_miri", code).await
+    }
+
+    async fn clippy(
+        &self,
+        code: &str,
+        _channel: &'static str,
+        _edition: &'static str,
+    ) -> PlaygroundResult<PlaygroundExecuteResult> {
+        self.execute("clippy", code).await
+    }
+
+    async fn format(
+        &self,
+        code: &str,
+        _channel: &'static str,
+        _edition: &'static str,
+    ) -> PlaygroundResult<PlaygroundFormatResult> {
+        match self.call("format", code).await? {
+            MockResponse::Format(res) => Ok(res),
+            res => panic!("{res:?} is not a response to format"),
+        }
+    }
+
+    async fn compile(
+        &self,
+        code: &str,
+        _target: &'static str,
+        _channel: &'static str,
+        _mode: &'static str,
+        _edition: &'static str,
+    ) -> PlaygroundResult<PlaygroundCompileResult> {
+        self.compile_artifact("compile", code).await
+    }
+
+    async fn macro_expansion(
+        &self,
+        code: &str,
+        _edition: &'static str,
+    ) -> PlaygroundResult<PlaygroundCompileResult> {
+        self.compile_artifact("macro_expansion", code).await
+    }
+
+    async fn generate_link(
+        &self,
+        code: &str,
+        _channel: &'static str,
+        _mode: &'static str,
+        _edition: &'static str,
+    ) -> PlaygroundResult<String> {
+        match self.call("generate_link", code).await? {
+            MockResponse::Link(link) => Ok(link),
+            res => panic!("{res:?} is not a response to generate_link"),
+        }
+    }
+}
@@ -0,0 +1,88 @@
+use std::{cell::RefCell, collections::HashMap, rc::Rc, time::Duration};
+
+use compio::time::sleep;
+use serde_json::Value;
+
+use super::serve;
+
+/// A playground server answering each path with a canned response.
+#[derive(Clone)]
+pub(crate) struct FakePlayground {
+    pub url: String,
+    state: Rc<RefCell<PlaygroundState>>,
+}
+
+#[derive(Default)]
+struct PlaygroundState {
+    responses: HashMap<String, FakeResponse>,
+    requests: Vec<(String, Value)>,
+}
+
+#[derive(Clone)]
+struct FakeResponse {
+    delay: Duration,
+    status: &'static str,
+    body: Vec<u8>,
+}
+
+impl FakePlayground {
+    pub async fn start() -> Self {
+        let state = Rc::new(RefCell::new(PlaygroundState::default()));
+        let url = serve({
+            let state = state.clone();
+            move |req| {
+                let mut state = state.borrow_mut();
+                let params = serde_json::from_slice(&req.body).unwrap_or(Value::Null);
+                let res = state.responses.get(&req.path).cloned();
+                state.requests.push((req.path, params));
+                async move {
+                    let Some(res) = res else {
+                        return ("404 Not Found", vec![]);
+                    };
+                    if !res.delay.is_zero() {
+                        sleep(res.delay).await;
+                    }
+                    (res.status, res.body)
+                }
+            }
+        })
+        .await;
+        Self { url, state }
+    }
+
+    /// Answers requests to `path` with `body` from now on.
+    pub fn respond(&self, path: &str, body: Value) {
+        self.respond_after(path, Duration::ZERO, body);
+    }
+
+    pub fn respond_after(&self, path: &str, delay: Duration, body: Value) {
+        self.set_response(path, delay, "200 OK", body.to_string().into_bytes());
+    }
+
+    /// Answers requests to `path` with something other than the JSON the playground sends.
+    pub fn respond_raw(&self, path: &str, status: &'static str, body: &str) {
+        self.set_response(path, Duration::ZERO, status, body.into());
+    }
+
+    fn set_response(&self, path: &str, delay: Duration, status: &'static str, body: Vec<u8>) {
+        self.state.borrow_mut().responses.insert(
+            path.into(),
+            FakeResponse {
+                delay,
+                status,
+                body,
+            },
+        );
+    }
+
+    /// The parameters of each request made to `path` so far.
+    pub fn requests(&self, path: &str) -> Vec<Value> {
+        let state = self.state.borrow();
+        state
+            .requests
+            .iter()
+            .filter(|(p, _)| p == path)
+            .map(|(_, params)| params.clone())
+            .collect()
+    }
+}